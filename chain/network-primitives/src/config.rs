@@ -1,5 +1,5 @@
 use crate::network_protocol::PeerInfo;
-use crate::types::{Blacklist, ROUTED_MESSAGE_TTL};
+use crate::types::{Blacklist, TrustLevel, ROUTED_MESSAGE_TTL};
 use near_crypto::{KeyType, SecretKey};
 use near_primitives::network::PeerId;
 use near_primitives::types::AccountId;
@@ -101,6 +101,9 @@ pub struct NetworkConfig {
     pub outbound_disabled: bool,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// Account announcements are accepted only from peers whose address is trusted at least
+    /// this much. `None` accepts them from any peer.
+    pub min_announcement_trust: Option<TrustLevel>,
 }
 
 impl NetworkConfig {
@@ -178,6 +181,7 @@ impl NetworkConfig {
                 .collect(),
             outbound_disabled: false,
             archive,
+            min_announcement_trust: cfg.min_announcement_trust,
         }
     }
 
@@ -226,6 +230,7 @@ impl NetworkConfig {
             blacklist: Blacklist::default(),
             outbound_disabled: false,
             archive: false,
+            min_announcement_trust: None,
         }
     }
 
@@ -1,3 +1,4 @@
+use crate::types::TrustLevel;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -104,6 +105,11 @@ pub struct Config {
     /// Period to check on peer status
     #[serde(default = "default_peer_stats_period")]
     pub peer_stats_period: Duration,
+    /// Accept account announcements only from peers whose address we trust at least this
+    /// much: "Indirect", "Direct" or "Signed". Announcements from any peer are accepted
+    /// if not set.
+    #[serde(default)]
+    pub min_announcement_trust: Option<TrustLevel>,
 
     /// List of the public addresses (IP:port) of this node. Useful only if this node is a validator.
     /// This list will be signed and broadcasted to the whole network, so that everyone
@@ -157,6 +163,7 @@ impl Default for Config {
             blacklist: vec![],
             ttl_account_id_router: default_ttl_account_id_router(),
             peer_stats_period: default_peer_stats_period(),
+            min_announcement_trust: None,
             public_addrs: vec![],
             trusted_stun_servers: vec![],
         }
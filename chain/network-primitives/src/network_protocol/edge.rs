@@ -1,5 +1,5 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-use near_crypto::{KeyType, SecretKey, Signature};
+use near_crypto::{KeyType, PublicKey, SecretKey, Signature};
 use near_primitives::borsh::maybestd::sync::Arc;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
@@ -15,12 +15,7 @@ pub struct PartialEdgeInfo {
 
 impl PartialEdgeInfo {
     pub fn new(peer0: &PeerId, peer1: &PeerId, nonce: u64, secret_key: &SecretKey) -> Self {
-        let data = if peer0 < peer1 {
-            Edge::build_hash(peer0, peer1, nonce)
-        } else {
-            Edge::build_hash(peer1, peer0, nonce)
-        };
-
+        let data = Edge::build_hash_unordered(peer0, peer1, nonce);
         let signature = secret_key.sign(data.as_ref());
         Self { nonce, signature }
     }
@@ -78,6 +73,30 @@ impl Edge {
         }))
     }
 
+    /// Builds an addition edge signed by both peers, which passes `verify`. `nonce` must be
+    /// odd. The peers are ordered as required by `verify`, regardless of the order of keys.
+    #[cfg(any(test, feature = "test_features"))]
+    pub fn new_valid_for_test(peer0_sk: &SecretKey, peer1_sk: &SecretKey, nonce: u64) -> Self {
+        assert_eq!(nonce % 2, 1, "addition edges have odd nonces");
+        let mut peers = [
+            (PeerId::new(peer0_sk.public_key()), peer0_sk),
+            (PeerId::new(peer1_sk.public_key()), peer1_sk),
+        ];
+        peers.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let [(peer0, sk0), (peer1, sk1)] = peers;
+        let hash = Self::build_hash(&peer0, &peer1, nonce);
+        let (signature0, signature1) = (sk0.sign(hash.as_ref()), sk1.sign(hash.as_ref()));
+        Self::new(peer0, peer1, nonce, signature0, signature1)
+    }
+
+    /// Same as `new_valid_for_test`, but returns the removal of that edge by the owner of
+    /// `peer0_sk`, with nonce `nonce + 1`.
+    #[cfg(any(test, feature = "test_features"))]
+    pub fn removed_valid_for_test(peer0_sk: &SecretKey, peer1_sk: &SecretKey, nonce: u64) -> Self {
+        Self::new_valid_for_test(peer0_sk, peer1_sk, nonce)
+            .remove_edge(PeerId::new(peer0_sk.public_key()), peer0_sk)
+    }
+
     /// Build a new edge with given information from the other party.
     pub fn build_with_secret_key(
         peer0: PeerId,
@@ -86,11 +105,7 @@ impl Edge {
         secret_key: &SecretKey,
         signature1: Signature,
     ) -> Self {
-        let hash = if peer0 < peer1 {
-            Self::build_hash(&peer0, &peer1, nonce)
-        } else {
-            Self::build_hash(&peer1, &peer0, nonce)
-        };
+        let hash = Self::build_hash_unordered(&peer0, &peer1, nonce);
         let signature0 = secret_key.sign(hash.as_ref());
         Self::new(peer0, peer1, nonce, signature0, signature1)
     }
@@ -102,6 +117,12 @@ impl Edge {
         CryptoHash::hash_borsh(&(peer0, peer1, nonce))
     }
 
+    /// Same as `build_hash`, but accepts the peers in any order.
+    fn build_hash_unordered(peer0: &PeerId, peer1: &PeerId, nonce: u64) -> CryptoHash {
+        let (peer0, peer1) = Self::key_order(peer0, peer1);
+        Self::build_hash(peer0, peer1, nonce)
+    }
+
     pub fn make_key(peer0: PeerId, peer1: PeerId) -> (PeerId, PeerId) {
         if peer0 < peer1 {
             (peer0, peer1)
@@ -110,15 +131,21 @@ impl Edge {
         }
     }
 
+    /// Same order as `make_key`, but without cloning the peers, for callers which only
+    /// need to compare or look up keys.
+    pub fn key_order<'a>(peer0: &'a PeerId, peer1: &'a PeerId) -> (&'a PeerId, &'a PeerId) {
+        if peer0 < peer1 {
+            (peer0, peer1)
+        } else {
+            (peer1, peer0)
+        }
+    }
+
     /// Helper function when adding a new edge and we receive information from new potential peer
     /// to verify the signature.
     pub fn partial_verify(peer0: &PeerId, peer1: &PeerId, edge_info: &PartialEdgeInfo) -> bool {
         let pk = peer1.public_key();
-        let data = if peer0 < peer1 {
-            Edge::build_hash(peer0, peer1, edge_info.nonce)
-        } else {
-            Edge::build_hash(peer1, peer0, edge_info.nonce)
-        };
+        let data = Edge::build_hash_unordered(peer0, peer1, edge_info.nonce);
         edge_info.signature.verify(data.as_ref(), pk)
     }
 
@@ -134,20 +161,150 @@ impl Edge {
         SimpleEdge::new(self.key().0.clone(), self.key().1.clone(), self.nonce())
     }
 
+    /// Compact identifier of the edge content. See [`SimpleEdge::content_id`].
+    pub fn content_id(&self) -> u64 {
+        self.to_simple_edge().content_id()
+    }
+
     /// Create the remove edge change from an added edge change.
+    /// Panics in the cases in which `try_remove_edge` fails.
     pub fn remove_edge(&self, my_peer_id: PeerId, sk: &SecretKey) -> Edge {
-        assert_eq!(self.edge_type(), EdgeState::Active);
+        self.try_remove_edge(my_peer_id, sk).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Create the remove edge change from an added edge change. Fails if this edge isn't
+    /// active, i.e. its nonce isn't odd, if the nonce can't be incremented, or if `my_peer_id`
+    /// is not an endpoint of the edge, since the result would be a malformed removal.
+    pub fn try_remove_edge(&self, my_peer_id: PeerId, sk: &SecretKey) -> anyhow::Result<Edge> {
+        if self.edge_type() != EdgeState::Active {
+            anyhow::bail!("Edge with even nonce {} is already removed", self.nonce());
+        }
+        if !self.contains_peer(&my_peer_id) {
+            anyhow::bail!("Peer {} is not an endpoint of edge {:?}", my_peer_id, self.key());
+        }
         let mut edge = self.0.as_ref().clone();
-        edge.nonce += 1;
+        edge.nonce = match edge.nonce.checked_add(1) {
+            Some(nonce) => nonce,
+            None => anyhow::bail!("Nonce of edge {:?} can't be incremented", edge.key),
+        };
         let me = edge.key.0 == my_peer_id;
-        let hash = edge.hash();
+        let hash = edge.signing_hash();
         let signature = sk.sign(hash.as_ref());
         edge.removal_info = Some((me, signature));
-        Edge(Arc::new(edge))
+        Ok(Edge(Arc::new(edge)))
     }
 
-    fn hash(&self) -> CryptoHash {
-        Edge::build_hash(&self.key().0, &self.key().1, self.nonce())
+    /// Create the remove edge change from an added edge change, using the removal
+    /// signature of the other party. `party` has the same meaning as in `removal_info`:
+    /// `true` if the signature is from `key.0`, `false` if from `key.1`.
+    /// Fails if this edge isn't active or the resulting edge doesn't verify.
+    pub fn with_remote_removal(&self, party: bool, signature: Signature) -> anyhow::Result<Edge> {
+        if self.edge_type() != EdgeState::Active {
+            anyhow::bail!("Edge with nonce {} is already removed", self.nonce());
+        }
+        let mut edge = self.0.as_ref().clone();
+        edge.nonce = match edge.nonce.checked_add(1) {
+            Some(nonce) => nonce,
+            None => anyhow::bail!("Nonce of edge {:?} can't be incremented", edge.key),
+        };
+        edge.removal_info = Some((party, signature));
+        let edge = Edge(Arc::new(edge));
+        if !edge.verify() {
+            anyhow::bail!("Invalid removal of edge {:?}", edge.key());
+        }
+        Ok(edge)
+    }
+
+    /// Compact encoding of the edge for gossip. Compared to Borsh it omits what the receiver
+    /// can derive: the key types of the signatures (same as of the signing peers), the presence
+    /// of `removal_info` (implied by the nonce parity), and encodes the nonce as LEB128.
+    /// For an ED25519 edge with a nonce below 128 that's 195 instead of 205 bytes for an
+    /// active edge, and 260 instead of 271 bytes for a removed one.
+    ///
+    /// Only valid edges round-trip, see `from_compact_bytes`.
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.key().0.public_key().serialize(&mut out).unwrap();
+        self.key().1.public_key().serialize(&mut out).unwrap();
+        let mut nonce = self.nonce();
+        while nonce >= 0x80 {
+            out.push((nonce & 0x7f) as u8 | 0x80);
+            nonce >>= 7;
+        }
+        out.push(nonce as u8);
+        // Signature without the leading key type.
+        let write_signature = |out: &mut Vec<u8>, signature: &Signature| {
+            out.extend_from_slice(&signature.try_to_vec().unwrap()[1..])
+        };
+        write_signature(&mut out, self.signature0());
+        write_signature(&mut out, self.signature1());
+        if self.edge_type() == EdgeState::Removed {
+            if let Some((party, signature)) = self.removal_info() {
+                out.push(*party as u8);
+                write_signature(&mut out, signature);
+            }
+        }
+        out
+    }
+
+    /// Decodes an edge encoded with `to_compact_bytes`. Fails if the bytes are malformed or
+    /// the decoded edge doesn't `verify`.
+    pub fn from_compact_bytes(bytes: &[u8]) -> anyhow::Result<Edge> {
+        let buf = &mut &bytes[..];
+        let peer0 = PeerId::new(PublicKey::deserialize(buf)?);
+        let peer1 = PeerId::new(PublicKey::deserialize(buf)?);
+        let mut nonce = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = u8::deserialize(buf)?;
+            // Only the minimal encoding is accepted, otherwise the same signed edge would
+            // have many encodings: no trailing zero bytes and no bits beyond the 64th.
+            if shift > 0 && byte == 0 {
+                anyhow::bail!("Nonce of the compact edge is not minimally encoded");
+            }
+            if shift == 63 && byte > 1 {
+                anyhow::bail!("Nonce of the compact edge is too long");
+            }
+            nonce |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let read_signature = |buf: &mut &[u8], peer: &PeerId| -> anyhow::Result<Signature> {
+            let key_type = peer.public_key().key_type();
+            let len = match key_type {
+                KeyType::ED25519 => 64,
+                KeyType::SECP256K1 => 65,
+            };
+            if buf.len() < len {
+                anyhow::bail!("Compact edge is truncated");
+            }
+            let slice: &[u8] = *buf;
+            let (data, rest) = slice.split_at(len);
+            *buf = rest;
+            Ok(Signature::from_parts(key_type, data)?)
+        };
+        let signature0 = read_signature(buf, &peer0)?;
+        let signature1 = read_signature(buf, &peer1)?;
+        let removal_info = if nonce % 2 == 0 {
+            let party = match u8::deserialize(buf)? {
+                0 => false,
+                1 => true,
+                party => anyhow::bail!("Invalid party {} of the compact edge removal", party),
+            };
+            let signer = if party { &peer0 } else { &peer1 };
+            Some((party, read_signature(buf, signer)?))
+        } else {
+            None
+        };
+        if !buf.is_empty() {
+            anyhow::bail!("Compact edge has {} trailing bytes", buf.len());
+        }
+        let edge =
+            Edge::new(peer0, peer1, nonce, signature0, signature1).with_removal_info(removal_info);
+        if !edge.verify() {
+            anyhow::bail!("Invalid compact edge {:?}", edge.key());
+        }
+        Ok(edge)
     }
 
     fn prev_hash(&self) -> CryptoHash {
@@ -161,7 +318,7 @@ impl Edge {
 
         match self.edge_type() {
             EdgeState::Active => {
-                let data = self.hash();
+                let data = self.0.signing_hash();
 
                 self.removal_info().is_none()
                     && self.signature0().verify(data.as_ref(), self.key().0.public_key())
@@ -181,17 +338,31 @@ impl Edge {
                     return false;
                 }
 
-                if let Some((party, signature)) = self.removal_info() {
-                    let peer = if *party { &self.key().0 } else { &self.key().1 };
-                    let del_hash = self.hash();
-                    signature.verify(del_hash.as_ref(), peer.public_key())
-                } else {
-                    false
-                }
+                self.verify_removal_only()
             }
         }
     }
 
+    /// Checks only that this is a well-formed removal edge, signed by the party
+    /// of the edge indicated in `removal_info`. Signatures of the referred added
+    /// edge are not checked, so the caller must have validated it already.
+    /// Use `verify` for the full check.
+    pub fn verify_removal_only(&self) -> bool {
+        if self.key().0 > self.key().1 || self.nonce() == 0 {
+            return false;
+        }
+        if self.edge_type() != EdgeState::Removed {
+            return false;
+        }
+        if let Some((party, signature)) = self.removal_info() {
+            let peer = if *party { &self.key().0 } else { &self.key().1 };
+            let del_hash = self.0.signing_hash();
+            signature.verify(del_hash.as_ref(), peer.public_key())
+        } else {
+            false
+        }
+    }
+
     /// It will be considered as a new edge if the nonce is odd, otherwise it is canceling the
     /// previous edge.
     pub fn edge_type(&self) -> EdgeState {
@@ -276,7 +447,9 @@ impl EdgeInner {
         Self { key: (peer0, peer1), nonce, signature0, signature1, removal_info: None }
     }
 
-    fn hash(&self) -> CryptoHash {
+    /// Hash of the edge content, which is signed by the peers. For an addition edge both
+    /// peers sign it, for a removal edge only the party removing the edge.
+    pub fn signing_hash(&self) -> CryptoHash {
         Edge::build_hash(&self.key.0, &self.key.1, self.nonce)
     }
 }
@@ -311,6 +484,16 @@ impl SimpleEdge {
             EdgeState::Removed
         }
     }
+
+    /// Compact identifier of the edge content, used as an element key in edge
+    /// set reconciliation.
+    /// It is derived from the same `(key.0, key.1, nonce)` triple which is
+    /// signed by both peers, so every node computes the same id for the same
+    /// logical edge.
+    pub fn content_id(&self) -> u64 {
+        let hash = Edge::build_hash(&self.key.0, &self.key.1, self.nonce);
+        u64::from_le_bytes(hash.0[..8].try_into().unwrap())
+    }
 }
 
 /// State of a given edge.
@@ -325,3 +508,244 @@ pub enum EdgeState {
     /// Though, it may be removed  from memory if both peers become unreachable.
     Removed,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_id_ignores_peer_order() {
+        let peer0 = PeerId::random();
+        let peer1 = PeerId::random();
+        let a = SimpleEdge::new(peer0.clone(), peer1.clone(), 3);
+        let b = SimpleEdge::new(peer1.clone(), peer0.clone(), 3);
+        assert_eq!(a.content_id(), b.content_id());
+        assert_ne!(a.content_id(), SimpleEdge::new(peer0.clone(), peer1.clone(), 4).content_id());
+
+        let edge = Edge::make_fake_edge(a.key().0.clone(), a.key().1.clone(), 3);
+        assert_eq!(edge.content_id(), a.content_id());
+    }
+
+    #[test]
+    fn key_order_matches_make_key() {
+        let peer0 = PeerId::random();
+        let peer1 = PeerId::random();
+        let (key0, key1) = Edge::make_key(peer0.clone(), peer1.clone());
+        assert_eq!(Edge::key_order(&peer0, &peer1), (&key0, &key1));
+        assert_eq!(Edge::key_order(&peer1, &peer0), (&key0, &key1));
+        assert_eq!(Edge::key_order(&peer0, &peer0), (&peer0, &peer0));
+    }
+
+    #[test]
+    fn verify_removal_only() {
+        let sk0 = SecretKey::from_random(KeyType::ED25519);
+        let sk1 = SecretKey::from_random(KeyType::ED25519);
+        let peer0 = PeerId::new(sk0.public_key());
+        let peer1 = PeerId::new(sk1.public_key());
+        let signature1 = PartialEdgeInfo::new(&peer1, &peer0, 1, &sk1).signature;
+        let edge = Edge::build_with_secret_key(peer0.clone(), peer1.clone(), 1, &sk0, signature1);
+        assert!(!edge.verify_removal_only());
+
+        let removed = edge.remove_edge(peer0.clone(), &sk0);
+        assert!(removed.verify());
+        assert!(removed.verify_removal_only());
+
+        // Removal signed by someone claiming to be peer0.
+        let impostor = SecretKey::from_random(KeyType::ED25519);
+        let impersonated = edge.remove_edge(peer0.clone(), &impostor);
+        assert!(!impersonated.verify());
+        assert!(!impersonated.verify_removal_only());
+
+        // Signatures of the added edge are not checked.
+        let (key0, key1) = Edge::make_key(peer0.clone(), peer1);
+        let removed = Edge::make_fake_edge(key0, key1, 1).remove_edge(peer0, &sk0);
+        assert!(!removed.verify());
+        assert!(removed.verify_removal_only());
+    }
+
+    #[test]
+    fn valid_for_test() {
+        let sk0 = SecretKey::from_random(KeyType::ED25519);
+        let sk1 = SecretKey::from_random(KeyType::ED25519);
+        let peer0 = PeerId::new(sk0.public_key());
+        let peer1 = PeerId::new(sk1.public_key());
+
+        let edge = Edge::new_valid_for_test(&sk0, &sk1, 3);
+        assert!(edge.verify());
+        assert_eq!(edge.key(), &Edge::make_key(peer0.clone(), peer1.clone()));
+        assert_eq!(edge.nonce(), 3);
+        assert_eq!(Edge::new_valid_for_test(&sk1, &sk0, 3), edge);
+
+        let removed = Edge::removed_valid_for_test(&sk0, &sk1, 3);
+        assert!(removed.verify());
+        assert_eq!(removed.edge_type(), EdgeState::Removed);
+        assert_eq!(removed.nonce(), 4);
+        let party = edge.key().0 == peer0;
+        assert_eq!(removed.removal_info().unwrap().0, party);
+
+        // Tampered edges don't verify.
+        let other = Edge::new_valid_for_test(&sk0, &SecretKey::from_random(KeyType::ED25519), 3);
+        let swapped = Edge::new(
+            edge.key().0.clone(),
+            edge.key().1.clone(),
+            3,
+            edge.signature0().clone(),
+            other.signature1().clone(),
+        );
+        assert!(!swapped.verify());
+        let renonced = Edge::new(
+            edge.key().0.clone(),
+            edge.key().1.clone(),
+            5,
+            edge.signature0().clone(),
+            edge.signature1().clone(),
+        );
+        assert!(!renonced.verify());
+        let (party, signature) = removed.removal_info().unwrap().clone();
+        assert!(!removed.clone().with_removal_info(Some((!party, signature))).verify());
+    }
+
+    #[test]
+    fn signing_hash() {
+        let sk0 = SecretKey::from_random(KeyType::ED25519);
+        let sk1 = SecretKey::from_random(KeyType::ED25519);
+        let peer0 = PeerId::new(sk0.public_key());
+        let peer1 = PeerId::new(sk1.public_key());
+        let (key0, key1) = Edge::make_key(peer0.clone(), peer1.clone());
+        let expected = CryptoHash::hash_borsh(&(&key0, &key1, 1u64));
+
+        let partial0 = PartialEdgeInfo::new(&peer0, &peer1, 1, &sk0);
+        let partial1 = PartialEdgeInfo::new(&peer1, &peer0, 1, &sk1);
+        assert!(partial0.signature.verify(expected.as_ref(), &sk0.public_key()));
+        assert!(partial1.signature.verify(expected.as_ref(), &sk1.public_key()));
+        assert!(Edge::partial_verify(&peer0, &peer1, &partial1));
+        assert!(Edge::partial_verify(&peer1, &peer0, &partial0));
+
+        let edge = Edge::build_with_secret_key(peer0.clone(), peer1, 1, &sk0, partial1.signature);
+        assert_eq!(edge.0.signing_hash(), expected);
+        assert!(edge.verify());
+
+        let removed = edge.remove_edge(peer0, &sk0);
+        let expected = CryptoHash::hash_borsh(&(&key0, &key1, 2u64));
+        assert_eq!(removed.0.signing_hash(), expected);
+        assert!(removed.verify());
+    }
+
+    #[test]
+    fn try_remove_edge() {
+        let sk0 = SecretKey::from_random(KeyType::ED25519);
+        let peer0 = PeerId::new(sk0.public_key());
+        let peer1 = PeerId::random();
+        let (key0, key1) = Edge::make_key(peer0.clone(), peer1.clone());
+
+        let removed = Edge::make_fake_edge(key0.clone(), key1.clone(), 3)
+            .try_remove_edge(peer0.clone(), &sk0)
+            .unwrap();
+        assert_eq!(removed.nonce(), 4);
+        assert_eq!(removed.edge_type(), EdgeState::Removed);
+
+        // Even nonce, the edge is already removed.
+        let even = Edge::make_fake_edge(key0.clone(), key1.clone(), 4);
+        assert!(even.try_remove_edge(peer0.clone(), &sk0).is_err());
+        assert!(removed.try_remove_edge(peer0.clone(), &sk0).is_err());
+        // Not our edge.
+        let odd = Edge::make_fake_edge(key0.clone(), key1.clone(), 5);
+        assert!(odd.try_remove_edge(PeerId::random(), &sk0).is_err());
+        // The removal nonce would overflow.
+        let last = Edge::make_fake_edge(key0, key1, u64::MAX);
+        assert!(last.try_remove_edge(peer0, &sk0).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "already removed")]
+    fn remove_removed_edge() {
+        let sk0 = SecretKey::from_random(KeyType::ED25519);
+        let peer0 = PeerId::new(sk0.public_key());
+        let (key0, key1) = Edge::make_key(peer0.clone(), PeerId::random());
+        Edge::make_fake_edge(key0, key1, 2).remove_edge(peer0, &sk0);
+    }
+
+    #[test]
+    fn with_remote_removal() {
+        let sk0 = SecretKey::from_random(KeyType::ED25519);
+        let sk1 = SecretKey::from_random(KeyType::ED25519);
+        let peer0 = PeerId::new(sk0.public_key());
+        let peer1 = PeerId::new(sk1.public_key());
+        let signature1 = PartialEdgeInfo::new(&peer1, &peer0, 1, &sk1).signature;
+        let edge = Edge::build_with_secret_key(peer0.clone(), peer1.clone(), 1, &sk0, signature1);
+
+        // Removal authored by the other peer, which sends us its removal info.
+        let remote = edge.remove_edge(peer1.clone(), &sk1);
+        let (party, signature) = remote.removal_info().unwrap().clone();
+        let removed = edge.with_remote_removal(party, signature.clone()).unwrap();
+        assert!(removed.verify());
+        assert_eq!(removed, remote);
+        assert_eq!(removed.edge_type(), EdgeState::Removed);
+        assert_eq!(removed.nonce(), 2);
+
+        // Signature attributed to the wrong party.
+        assert!(edge.with_remote_removal(!party, signature.clone()).is_err());
+        // Edge is already removed.
+        assert!(removed.with_remote_removal(party, signature.clone()).is_err());
+        // The removal nonce would overflow.
+        let (key0, key1) = Edge::make_key(peer0, peer1);
+        let last = Edge::make_fake_edge(key0, key1, u64::MAX);
+        assert!(last.with_remote_removal(party, signature).is_err());
+    }
+
+    #[test]
+    fn compact_bytes() {
+        let sk0 = SecretKey::from_random(KeyType::ED25519);
+        let sk1 = SecretKey::from_random(KeyType::ED25519);
+        let peer0 = PeerId::new(sk0.public_key());
+        let peer1 = PeerId::new(sk1.public_key());
+        let signature1 = PartialEdgeInfo::new(&peer1, &peer0, 1, &sk1).signature;
+        let edge = Edge::build_with_secret_key(peer0.clone(), peer1.clone(), 1, &sk0, signature1);
+        let removed = edge.remove_edge(peer1.clone(), &sk1);
+
+        for (edge, compact_len, borsh_len) in [(&edge, 195, 205), (&removed, 260, 271)] {
+            let bytes = edge.to_compact_bytes();
+            assert_eq!(bytes.len(), compact_len);
+            assert_eq!(edge.try_to_vec().unwrap().len(), borsh_len);
+            assert_eq!(&Edge::from_compact_bytes(&bytes).unwrap(), edge);
+
+            // Tampered signature.
+            let mut tampered = bytes.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(Edge::from_compact_bytes(&tampered).is_err());
+            // Tampered nonce, right after the two 33 byte keys.
+            let mut tampered = bytes.clone();
+            tampered[66] += 2;
+            assert!(Edge::from_compact_bytes(&tampered).is_err());
+            assert!(Edge::from_compact_bytes(&bytes[..bytes.len() - 1]).is_err());
+            assert!(Edge::from_compact_bytes(&[bytes.clone(), vec![0]].concat()).is_err());
+        }
+
+        // Multi-byte nonces.
+        let nonce = 1_000_001;
+        let signature1 = PartialEdgeInfo::new(&peer1, &peer0, nonce, &sk1).signature;
+        let edge =
+            Edge::build_with_secret_key(peer0.clone(), peer1.clone(), nonce, &sk0, signature1);
+        let bytes = edge.to_compact_bytes();
+        assert_eq!(bytes.len(), 195 + 2);
+        assert_eq!(Edge::from_compact_bytes(&bytes).unwrap(), edge);
+
+        // Non-minimal encoding of nonce 2 with a trailing zero byte.
+        let bytes = removed.to_compact_bytes();
+        assert_eq!(bytes[66], 2);
+        let padded = [&bytes[..66], &[0x82, 0x00][..], &bytes[67..]].concat();
+        assert!(Edge::from_compact_bytes(&padded).is_err());
+
+        // The 10th byte of the nonce carries only its highest bit.
+        let nonce = u64::MAX;
+        let signature1 = PartialEdgeInfo::new(&peer1, &peer0, nonce, &sk1).signature;
+        let edge = Edge::build_with_secret_key(peer0, peer1, nonce, &sk0, signature1);
+        let bytes = edge.to_compact_bytes();
+        assert_eq!(bytes.len(), 195 + 9);
+        assert_eq!(Edge::from_compact_bytes(&bytes).unwrap(), edge);
+        let mut overflowing = bytes.clone();
+        assert_eq!(overflowing[66 + 9], 1);
+        overflowing[66 + 9] = 3;
+        assert!(Edge::from_compact_bytes(&overflowing).is_err());
+    }
+}
@@ -52,7 +52,17 @@ pub const MAX_NUM_PEERS: usize = 128;
 
 /// Peer type.
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, strum::IntoStaticStr)]
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    Hash,
+    strum::IntoStaticStr,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 pub enum PeerType {
     /// Inbound session
     Inbound,
@@ -160,6 +170,20 @@ impl KnownPeerStatus {
     }
 }
 
+/// Level of trust we have about a new (PeerId, Addr) pair.
+/// Variants are ordered from the least to the most trusted.
+#[derive(
+    Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum TrustLevel {
+    /// We learn about it from other peers.
+    Indirect,
+    /// Responding node at addr claims to possess PeerId.
+    Direct,
+    /// Responding peer proved to have SecretKey associated with this PeerID.
+    Signed,
+}
+
 /// Information node stores about known peers.
 #[derive(Debug, Clone)]
 pub struct KnownPeerState {
@@ -167,15 +191,28 @@ pub struct KnownPeerState {
     pub status: KnownPeerStatus,
     pub first_seen: time::Utc,
     pub last_seen: time::Utc,
+    /// Direction of the last connection with this peer, None if we have never connected.
+    pub direction: Option<PeerType>,
+    /// Trust level of the (PeerId, Addr) pair, `Indirect` if the address is unknown.
+    pub trust_level: TrustLevel,
+    /// Lowered when the peer gets banned, depending on the reason, and recovered over time.
+    /// Between 0 and `MAX_REPUTATION`.
+    pub reputation: u32,
 }
 
 impl KnownPeerState {
+    /// Reputation of the peers which have never been banned.
+    pub const MAX_REPUTATION: u32 = 1000;
+
     pub fn new(peer_info: PeerInfo, now: time::Utc) -> Self {
         KnownPeerState {
             peer_info,
             status: KnownPeerStatus::Unknown,
             first_seen: now,
             last_seen: now,
+            direction: None,
+            trust_level: TrustLevel::Indirect,
+            reputation: Self::MAX_REPUTATION,
         }
     }
 }
@@ -213,7 +250,17 @@ pub struct OutboundTcpConnect {
 
 /// Ban reason.
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq, Copy)]
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Copy,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 pub enum ReasonForBan {
     None = 0,
     BadBlock = 1,
@@ -1,14 +1,14 @@
 use crate::network_protocol::Encoding;
 use crate::peer::codec::Codec;
 use crate::peer::peer_actor::PeerActor;
-use crate::peer_manager::peer_store::PeerStore;
+use crate::peer_manager::peer_store::{PeerEvent, PeerStore};
 use crate::private_actix::{
     PeerRequestResult, PeersRequest, RegisterPeer, RegisterPeerResponse, SendMessage, StopMsg,
     Unregister, ValidateEdgeList,
 };
 use crate::routing;
 use crate::routing::edge_validator_actor::EdgeValidatorHelper;
-use crate::routing::routing_table_view::RoutingTableView;
+use crate::routing::routing_table_view::{PongStatus, RoutingTableConfig, RoutingTableView};
 
 use crate::stats::metrics;
 use crate::stats::metrics::{NetworkMetrics, PARTIAL_ENCODED_CHUNK_REQUEST_DELAY};
@@ -98,6 +98,8 @@ const REPORT_BANDWIDTH_THRESHOLD_BYTES: usize = 10_000_000;
 const REPORT_BANDWIDTH_THRESHOLD_COUNT: usize = 10_000;
 /// How long a peer has to be unreachable, until we prune it from the in-memory graph.
 const PRUNE_UNREACHABLE_PEERS_AFTER: time::Duration = time::Duration::hours(1);
+/// How long a connected peer may leave a ping unanswered before we disconnect from it.
+const DISCONNECT_UNRESPONSIVE_PEER_AFTER: time::Duration = time::Duration::seconds(60);
 
 /// Contains information relevant to a connected peer.
 struct ConnectedPeer {
@@ -172,6 +174,10 @@ impl TryFrom<&PeerInfo> for WhitelistNode {
     }
 }
 
+fn log_peer_event(event: PeerEvent) {
+    debug!(target: "network", peer_id = ?event.peer_id, transition = ?event.transition, "Peer store event");
+}
+
 /// Actor that manages peers connections.
 pub struct PeerManagerActor {
     clock: time::Clock,
@@ -185,6 +191,8 @@ pub struct PeerManagerActor {
     view_client_addr: Recipient<NetworkViewClientMessages>,
     /// Peer store that provides read/write access to peers.
     peer_store: PeerStore,
+    /// Database of the node, to write the peer store replacing `peer_store` on import.
+    store: near_store::Store,
     /// Set of outbound connections that were not consolidated yet.
     outgoing_peers: HashSet<PeerId>,
     /// Connected peers (inbound and outbound) with their full peer information.
@@ -313,13 +321,14 @@ impl PeerManagerActor {
         view_client_addr: Recipient<NetworkViewClientMessages>,
     ) -> anyhow::Result<Self> {
         let clock = time::Clock::real();
-        let peer_store = PeerStore::new(
+        let mut peer_store = PeerStore::new(
             &clock,
             store::Store::new(store.clone()),
             &config.boot_nodes,
             config.blacklist.clone(),
         )
         .map_err(|e| anyhow::Error::msg(e.to_string()))?;
+        peer_store.set_event_callback(Box::new(log_peer_event));
         debug!(target: "network", len = peer_store.len(), boot_nodes = config.boot_nodes.len(), "Found known peers");
         debug!(target: "network", blacklist = ?config.blacklist, "Blacklist");
 
@@ -331,7 +340,8 @@ impl PeerManagerActor {
             network_graph.clone(),
         )
         .start();
-        let routing_table_view = RoutingTableView::new(store::Store::new(store.clone()));
+        let routing_table_view =
+            RoutingTableView::new(store::Store::new(store.clone()), RoutingTableConfig::default());
 
         let txns_since_last_block = Arc::new(AtomicUsize::new(0));
 
@@ -350,6 +360,7 @@ impl PeerManagerActor {
             client_addr,
             view_client_addr,
             peer_store,
+            store,
             connected_peers: HashMap::default(),
             outgoing_peers: HashSet::default(),
             routing_table_view,
@@ -388,7 +399,7 @@ impl PeerManagerActor {
                     peers_to_ban,
                 }) => {
                     act.routing_table_view.remove_local_edges(local_edges_to_remove.iter());
-                    act.routing_table_view.peer_forwarding = routing_table;
+                    act.routing_table_view.set_peer_forwarding(routing_table);
                     for peer in peers_to_ban {
                         act.ban_peer(&peer, ReasonForBan::InvalidEdge);
                     }
@@ -412,14 +423,27 @@ impl PeerManagerActor {
         edges: &Vec<Edge>,
         my_peer_id: &PeerId,
     ) {
-        for edge in edges.iter() {
+        let local_edges = Self::newest_local_edges(edges, my_peer_id);
+        for i in routing_table_view.filter_newer_local_edges(&local_edges) {
+            let edge = &local_edges[i];
+            let other_peer = edge.other(my_peer_id).unwrap();
+            routing_table_view.local_edges_info.insert(other_peer.clone(), edge.clone());
+        }
+    }
+
+    /// Edges from `edges` adjacent to `my_peer_id`, only the one with the highest nonce
+    /// is kept for each peer, as the older ones are already stale.
+    fn newest_local_edges(edges: &[Edge], my_peer_id: &PeerId) -> Vec<Edge> {
+        let mut newest: HashMap<&PeerId, &Edge> = HashMap::new();
+        for edge in edges {
             if let Some(other_peer) = edge.other(my_peer_id) {
-                if !routing_table_view.is_local_edge_newer(other_peer, edge.nonce()) {
-                    continue;
+                let current = newest.entry(other_peer).or_insert(edge);
+                if current.nonce() < edge.nonce() {
+                    *current = edge;
                 }
-                routing_table_view.local_edges_info.insert(other_peer.clone(), edge.clone());
             }
         }
+        newest.into_values().cloned().collect()
     }
 
     fn broadcast_accounts(&mut self, mut accounts: Vec<AnnounceAccount>) {
@@ -539,27 +563,23 @@ impl PeerManagerActor {
             //
             // Also check whenever there is an edge indicating that we should be disconnected
             // from a peer, but we are connected. And try to resolve the inconsistency.
-            for edge in new_edges.iter() {
-                if let Some(other_peer) = edge.other(&self.my_peer_id) {
-                    if !self.routing_table_view.is_local_edge_newer(other_peer, edge.nonce()) {
-                        continue;
-                    }
-                    // Check whether we belong to this edge.
-                    if self.connected_peers.contains_key(other_peer) {
-                        // This is an active connection.
-                        if edge.edge_type() == EdgeState::Removed {
-                            self.maybe_remove_connected_peer(ctx, edge, other_peer);
-                        }
-                    } else if edge.edge_type() == EdgeState::Active {
-                        // We are not connected to this peer, but routing table contains
-                        // information that we do. We should wait and remove that peer
-                        // from routing table
-                        Self::wait_peer_or_remove(ctx, edge.clone(), self.network_metrics.clone());
+            let local_edges = Self::newest_local_edges(&new_edges, &self.my_peer_id);
+            for i in self.routing_table_view.filter_newer_local_edges(&local_edges) {
+                let edge = &local_edges[i];
+                let other_peer = edge.other(&self.my_peer_id).unwrap();
+                // Check whether we belong to this edge.
+                if self.connected_peers.contains_key(other_peer) {
+                    // This is an active connection.
+                    if edge.edge_type() == EdgeState::Removed {
+                        self.maybe_remove_connected_peer(ctx, edge, other_peer);
                     }
-                    self.routing_table_view
-                        .local_edges_info
-                        .insert(other_peer.clone(), edge.clone());
+                } else if edge.edge_type() == EdgeState::Active {
+                    // We are not connected to this peer, but routing table contains
+                    // information that we do. We should wait and remove that peer
+                    // from routing table
+                    Self::wait_peer_or_remove(ctx, edge.clone(), self.network_metrics.clone());
                 }
+                self.routing_table_view.local_edges_info.insert(other_peer.clone(), edge.clone());
             }
             let network_metrics = self.network_metrics.clone();
 
@@ -625,7 +645,9 @@ impl PeerManagerActor {
         if self.outgoing_peers.contains(&full_peer_info.peer_info.id) {
             self.outgoing_peers.remove(&full_peer_info.peer_info.id);
         }
-        if let Err(err) = self.peer_store.peer_connected(&self.clock, &full_peer_info.peer_info) {
+        if let Err(err) =
+            self.peer_store.peer_connected(&self.clock, &full_peer_info.peer_info, peer_type)
+        {
             error!(target: "network", ?err, "Failed to save peer data");
             return;
         };
@@ -752,6 +774,7 @@ impl PeerManagerActor {
         // If the last edge we have with this peer represent a connection addition, create the edge
         // update that represents the connection removal.
         self.connected_peers.remove(peer_id);
+        self.routing_table_view.forget_peer_liveness(peer_id);
 
         if let Some(edge) = self.routing_table_view.get_local_edge(peer_id) {
             if edge.edge_type() == EdgeState::Active {
@@ -909,10 +932,7 @@ impl PeerManagerActor {
     ///     and the total connections is less than `max_num_peers`)
     fn is_outbound_bootstrap_needed(&self) -> bool {
         let total_connections = self.connected_peers.len() + self.outgoing_peers.len();
-        let potential_outgoing_connections = (self.connected_peers.values())
-            .filter(|connected_peer| connected_peer.peer_type == PeerType::Outbound)
-            .count()
-            + self.outgoing_peers.len();
+        let potential_outgoing_connections = self.potential_outgoing_connections();
 
         (total_connections < self.config.ideal_connections_lo as usize
             || (total_connections < self.config.max_num_peers as usize
@@ -920,6 +940,14 @@ impl PeerManagerActor {
             && !self.config.outbound_disabled
     }
 
+    /// Number of outbound connections, including the ones being established.
+    fn potential_outgoing_connections(&self) -> usize {
+        (self.connected_peers.values())
+            .filter(|connected_peer| connected_peer.peer_type == PeerType::Outbound)
+            .count()
+            + self.outgoing_peers.len()
+    }
+
     fn is_inbound_allowed(&self) -> bool {
         self.connected_peers.len() + self.outgoing_peers.len() < self.config.max_num_peers as usize
     }
@@ -1101,6 +1129,47 @@ impl PeerManagerActor {
         }
         metrics::set_peer_connections(m);
 
+        let store_size = self.peer_store.size_estimate();
+        metrics::PEER_STORE_PEERS.set(store_size.peer_count as i64);
+        metrics::PEER_STORE_SIZE_BYTES.set(store_size.approx_bytes as i64);
+        metrics::set_peer_store_addresses(self.peer_store.trust_level_counts());
+        metrics::set_peer_store_last_seen(self.peer_store.last_seen_histogram(&self.clock));
+        metrics::PEER_STORE_ADDRESSLESS_PEERS.set(self.peer_store.addressless_peers().len() as i64);
+        let route_back = self.routing_table_view.route_back_stats();
+        metrics::ROUTE_BACK_CACHE_SIZE.set(route_back.len as i64);
+        metrics::ROUTE_BACK_CACHE_HITS.set(route_back.hits as i64);
+        metrics::ROUTE_BACK_CACHE_MISSES.set(route_back.misses as i64);
+        // Peers we haven't pinged yet don't have a loss and are left out of the mean.
+        let ping_losses: Vec<f64> = (self.connected_peers.keys())
+            .filter_map(|peer_id| self.routing_table_view.ping_loss(&self.clock, peer_id))
+            .collect();
+        metrics::PEER_PING_LOSS.set(if ping_losses.is_empty() {
+            0.
+        } else {
+            ping_losses.iter().sum::<f64>() / ping_losses.len() as f64
+        });
+        let mut health_scores = vec![];
+        for peer_id in self.connected_peers.keys() {
+            if let Some(health) = self.routing_table_view.peer_health(&self.clock, peer_id) {
+                trace!(
+                    target: "network",
+                    ?peer_id,
+                    score = health.score,
+                    reachable = health.reachable,
+                    direct = health.direct,
+                    ping_loss = ?health.ping_loss,
+                    rtt = ?health.rtt.map(|rtt| rtt.to_string()),
+                    "Peer health"
+                );
+                health_scores.push(health.score as f64);
+            }
+        }
+        metrics::PEER_HEALTH_SCORE.set(if health_scores.is_empty() {
+            0.
+        } else {
+            health_scores.iter().sum::<f64>() / health_scores.len() as f64
+        });
+
         for (peer_id, connected_peer) in self.connected_peers.iter() {
             let peer_id1 = peer_id.clone();
             (connected_peer.addr.send(QueryPeerStats {context: Span::current().context()}).into_actor(self))
@@ -1229,6 +1298,7 @@ impl PeerManagerActor {
     ///  - bootstrap outbound connections from known peers,
     ///  - unban peers that have been banned for awhile,
     ///  - remove expired peers,
+    ///  - disconnect peers which don't answer pings,
     ///
     /// # Arguments:
     /// - `interval` - Time between consequent runs.
@@ -1261,13 +1331,21 @@ impl PeerManagerActor {
         }
 
         if self.is_outbound_bootstrap_needed() {
-            if let Some(peer_info) = self.peer_store.unconnected_peer(|peer_state| {
-                // Ignore connecting to ourself
-                self.my_peer_id == peer_state.peer_info.id
-                    || self.config.node_addr == peer_state.peer_info.addr
-                    // Or to peers we are currently trying to connect to
-                    || self.outgoing_peers.contains(&peer_state.peer_info.id)
-            }) {
+            // Connections above `ideal_connections_hi` get pruned anyway, so we don't dial
+            // beyond it and leave the remaining slots to inbound peers.
+            let current_outbound = self.potential_outgoing_connections();
+            if let Some(peer_info) = self.peer_store.outbound_candidate(
+                &self.clock,
+                current_outbound,
+                self.config.ideal_connections_hi as usize,
+                |peer_state| {
+                    // Ignore connecting to ourself
+                    self.my_peer_id == peer_state.peer_info.id
+                        || self.config.node_addr == peer_state.peer_info.addr
+                        // Or to peers we are currently trying to connect to
+                        || self.outgoing_peers.contains(&peer_state.peer_info.id)
+                },
+            ) {
                 // Start monitor_peers_attempts from start after we discover the first healthy peer
                 if !self.started_connect_attempts {
                     self.started_connect_attempts = true;
@@ -1283,12 +1361,29 @@ impl PeerManagerActor {
             }
         }
 
+        // Disconnect from peers which stopped answering pings. Unregistering a peer forgets its
+        // pending pings, so it won't be picked again after reconnecting.
+        for peer_id in
+            self.routing_table_view.overdue_pings(&self.clock, DISCONNECT_UNRESPONSIVE_PEER_AFTER)
+        {
+            if let Some(peer) = self.connected_peers.get(&peer_id) {
+                info!(target: "network", ?peer_id, "Disconnecting peer which doesn't answer pings");
+                peer.addr.do_send(PeerManagerRequestWithContext {
+                    msg: PeerManagerRequest::UnregisterPeer,
+                    context: Span::current().context(),
+                });
+            }
+        }
+
         // If there are too many active connections try to remove some connections
         self.maybe_stop_active_connection();
 
         if let Err(err) = self.peer_store.remove_expired(&self.clock, &self.config) {
             error!(target: "network", ?err, "Failed to remove expired peers");
         };
+        if let Err(err) = self.peer_store.recover_reputation(&self.clock) {
+            error!(target: "network", ?err, "Failed to recover reputation of peers");
+        };
 
         let new_interval = min(max_interval, interval * EXPONENTIAL_BACKOFF_RATIO);
 
@@ -1519,6 +1614,7 @@ impl PeerManagerActor {
     }
 
     fn send_ping(&mut self, nonce: u64, target: PeerId) {
+        self.routing_table_view.sending_ping(&self.clock, nonce, target.clone());
         let body = RoutedMessageBody::Ping(Ping { nonce, source: self.my_peer_id.clone() });
         let msg = RawRoutedMessage { target: AccountOrPeerIdOrHash::PeerId(target), body };
         self.send_message_to_peer(msg);
@@ -1531,7 +1627,9 @@ impl PeerManagerActor {
         self.send_message_to_peer(msg);
     }
 
-    pub(crate) fn get_network_info(&self) -> NetworkInfo {
+    pub(crate) fn get_network_info(&mut self) -> NetworkInfo {
+        let announce_accounts: Vec<AnnounceAccount> =
+            self.routing_table_view.get_announce_accounts().cloned().collect();
         NetworkInfo {
             connected_peers: (self.connected_peers.values())
                 .map(|cp| cp.full_peer_info.clone())
@@ -1543,25 +1641,24 @@ impl PeerManagerActor {
             received_bytes_per_sec: (self.connected_peers.values())
                 .map(|x| x.received_bytes_per_sec)
                 .sum(),
-            known_producers: self
-                .routing_table_view
-                .get_announce_accounts()
+            known_producers: announce_accounts
+                .into_iter()
                 .map(|announce_account| KnownProducer {
-                    account_id: announce_account.account_id.clone(),
-                    peer_id: announce_account.peer_id.clone(),
-                    // TODO: fill in the address.
-                    addr: None,
+                    addr: self.peer_store.peer_addr(&announce_account.peer_id),
                     next_hops: self
                         .routing_table_view
-                        .view_route(&announce_account.peer_id)
-                        .map(|it| it.clone()),
+                        .preview_account_route(&announce_account.account_id)
+                        .ok()
+                        .map(|(_, next_hops)| next_hops),
+                    account_id: announce_account.account_id,
+                    peer_id: announce_account.peer_id,
                 })
                 .collect(),
             peer_counter: self.peer_counter.load(Ordering::SeqCst),
         }
     }
 
-    fn push_network_info_trigger(&self, ctx: &mut Context<Self>, interval: time::Duration) {
+    fn push_network_info_trigger(&mut self, ctx: &mut Context<Self>, interval: time::Duration) {
         let network_info = self.get_network_info();
 
         let _ = self.client_addr.do_send(NetworkClientMessages::NetworkInfo(network_info));
@@ -1824,7 +1921,14 @@ impl PeerManagerActor {
             NetworkRequests::SyncRoutingTable { peer_id, routing_table_update } => {
                 // Process edges and add new edges to the routing table. Also broadcast new edges.
                 let edges = routing_table_update.edges;
-                let accounts = routing_table_update.accounts;
+                let mut accounts = routing_table_update.accounts;
+                let min_trust = self.config.min_announcement_trust.clone();
+                let trusted =
+                    min_trust.map_or(true, |min| self.peer_store.meets_trust(&peer_id, min));
+                if !trusted && !accounts.is_empty() {
+                    debug!(target: "network", ?peer_id, "Ignoring untrusted announcements");
+                    accounts.clear();
+                }
 
                 // Filter known accounts before validating them.
                 let accounts: Vec<(AnnounceAccount, Option<EpochId>)> = accounts
@@ -1908,10 +2012,23 @@ impl PeerManagerActor {
             NetworkRequests::ResponseUpdateNonce(edge) => {
                 if let Some(other_peer) = edge.other(&self.my_peer_id) {
                     if edge.verify() {
+                        if !self.routing_table_view.edge_is_current(&edge) {
+                            // A replay of an edge older than the one we already have, there is
+                            // nothing to update.
+                            debug!(
+                                target: "network",
+                                ?other_peer,
+                                nonce = edge.nonce(),
+                                "Ignoring stale edge"
+                            );
+                            return NetworkResponses::NoResponse;
+                        }
                         // This happens in case, we get an edge, in `RoutingTableActor`,
                         // which says that we shouldn't be connected to local peer, but we are.
                         // This is a part of logic used to ask peer, if he really want to be disconnected.
-                        if self.routing_table_view.is_local_edge_newer(other_peer, edge.nonce()) {
+                        let (peer0, peer1) = edge.key();
+                        let current = self.routing_table_view.try_get_local_edge(peer0, peer1);
+                        if current.map_or(true, |current| current.nonce() < edge.nonce()) {
                             if let Some(nonce) =
                                 self.local_peer_pending_update_nonce_request.get(other_peer)
                             {
@@ -2004,6 +2121,13 @@ impl PeerManagerActor {
     }
 
     #[perf]
+    /// Called when dialing `peer_id` failed. Repeated failures downgrade the trust of its
+    /// address, see `PeerStore::record_connection_attempt`.
+    fn outbound_connect_failed(&mut self, peer_id: &PeerId) {
+        self.outgoing_peers.remove(peer_id);
+        self.peer_store.record_connection_attempt(&self.clock, peer_id, false);
+    }
+
     fn handle_msg_outbound_tcp_connect(&self, msg: OutboundTcpConnect, ctx: &mut Context<Self>) {
         let _d = delay_detector::DelayDetector::new(|| "outbound tcp connect".into());
         debug!(target: "network", to = ?msg.peer_info, "Trying to connect");
@@ -2022,6 +2146,11 @@ impl PeerManagerActor {
                     Ok(res) => match res {
                         Ok(stream) => {
                             debug!(target: "network", peer_info = ?msg.peer_info, "Connecting");
+                            act.peer_store.record_connection_attempt(
+                                &act.clock,
+                                &msg.peer_info.id,
+                                true,
+                            );
                             let edge_info = act.propose_edge(&msg.peer_info.id, None);
 
                             act.try_connect_peer(
@@ -2035,13 +2164,13 @@ impl PeerManagerActor {
                         }
                         Err(err) => {
                             info!(target: "network", ?addr, ?err, "Error connecting to");
-                            act.outgoing_peers.remove(&msg.peer_info.id);
+                            act.outbound_connect_failed(&msg.peer_info.id);
                             actix::fut::ready(())
                         }
                     },
                     Err(err) => {
                         info!(target: "network", ?addr, ?err, "Error connecting to");
-                        act.outgoing_peers.remove(&msg.peer_info.id);
+                        act.outbound_connect_failed(&msg.peer_info.id);
                         actix::fut::ready(())
                     }
                 })
@@ -2156,6 +2285,69 @@ impl PeerManagerActor {
         self.ban_peer(&msg.peer_id, msg.ban_reason);
     }
 
+    fn handle_msg_forget_peer(&mut self, peer_id: PeerId) -> bool {
+        let _d = delay_detector::DelayDetector::new(|| "forget peer".into());
+        if self.connected_peers.contains_key(&peer_id) {
+            // The peer store still has to track the connection until it is closed.
+            warn!(target: "network", ?peer_id, "Refusing to forget a connected peer");
+            return false;
+        }
+        match self.peer_store.forget_peer(&peer_id) {
+            Ok(existed) => existed,
+            Err(err) => {
+                error!(target: "network", ?err, "Failed to forget peer");
+                false
+            }
+        }
+    }
+
+    fn handle_msg_add_boot_nodes(&mut self, nodes: Vec<PeerInfo>) -> bool {
+        let _d = delay_detector::DelayDetector::new(|| "add boot nodes".into());
+        match self.peer_store.add_boot_nodes(&self.clock, &nodes) {
+            Ok(()) => true,
+            Err(err) => {
+                error!(target: "network", ?err, "Failed to add boot nodes");
+                false
+            }
+        }
+    }
+
+    fn handle_msg_import_peer_store(&mut self, json: String) -> bool {
+        let _d = delay_detector::DelayDetector::new(|| "import peer store".into());
+        if !self.connected_peers.is_empty() || !self.outgoing_peers.is_empty() {
+            // Connected peers have to be tracked by the store until they disconnect.
+            warn!(target: "network", "Refusing to replace the peer store while connected to peers");
+            return false;
+        }
+        let mut peer_store =
+            match PeerStore::load_json(&self.clock, store::Store::new(self.store.clone()), &json) {
+                Ok(peer_store) => peer_store,
+                Err(err) => {
+                    error!(target: "network", ?err, "Failed to import peer store");
+                    return false;
+                }
+            };
+        // Peers missing from the imported store would be loaded again on restart.
+        let imported: HashSet<PeerId> = peer_store.iter().map(|(id, _)| id.clone()).collect();
+        let stale: Vec<PeerId> = self
+            .peer_store
+            .iter()
+            .map(|(id, _)| id.clone())
+            .filter(|id| !imported.contains(id))
+            .collect();
+        for peer_id in stale {
+            if let Err(err) = self.peer_store.forget_peer(&peer_id) {
+                error!(target: "network", ?err, "Failed to forget peer");
+            }
+        }
+        if let Err(err) = peer_store.add_boot_nodes(&self.clock, &self.config.boot_nodes) {
+            error!(target: "network", ?err, "Failed to add boot nodes");
+        }
+        peer_store.set_event_callback(Box::new(log_peer_event));
+        self.peer_store = peer_store;
+        true
+    }
+
     #[perf]
     fn handle_msg_peers_request(&self, _msg: PeersRequest) -> PeerRequestResult {
         let _d = delay_detector::DelayDetector::new(|| "peers request".into());
@@ -2232,6 +2424,18 @@ impl PeerManagerActor {
                 self.handle_msg_ban(msg);
                 PeerManagerMessageResponse::Ban(())
             }
+            PeerManagerMessageRequest::ForgetPeer(peer_id) => {
+                PeerManagerMessageResponse::ForgetPeer(self.handle_msg_forget_peer(peer_id))
+            }
+            PeerManagerMessageRequest::AddBootNodes(nodes) => {
+                PeerManagerMessageResponse::AddBootNodes(self.handle_msg_add_boot_nodes(nodes))
+            }
+            PeerManagerMessageRequest::ExportPeerStore => {
+                PeerManagerMessageResponse::ExportPeerStore(self.peer_store.to_json())
+            }
+            PeerManagerMessageRequest::ImportPeerStore(json) => {
+                PeerManagerMessageResponse::ImportPeerStore(self.handle_msg_import_peer_store(json))
+            }
             PeerManagerMessageRequest::SetAdvOptions(msg) => {
                 self.handle_msg_set_adv_options(msg);
                 PeerManagerMessageResponse::SetAdvOptions(())
@@ -2260,7 +2464,9 @@ impl PeerManagerActor {
 
         if msg.expect_response() {
             trace!(target: "network", route_back = ?PeerMessage::Routed(msg.clone()), "Received peer message that requires");
-            self.routing_table_view.add_route_back(&self.clock, msg.hash(), from.clone());
+            if !self.routing_table_view.add_route_back(&self.clock, msg.hash(), from.clone()) {
+                debug!(target: "network", ?from, "Route back rate limit exceeded");
+            }
         }
 
         if Self::message_for_me(&mut self.routing_table_view, &self.my_peer_id, &msg.msg.target) {
@@ -2274,6 +2480,9 @@ impl PeerManagerActor {
                     false
                 }
                 RoutedMessageBody::Pong(pong) => {
+                    if self.routing_table_view.add_pong(&self.clock, pong) == PongStatus::Late {
+                        debug!(target: "network", source = ?pong.source, nonce = pong.nonce, "Received late pong");
+                    }
                     self.ping_counter.add_pong(pong);
                     false
                 }
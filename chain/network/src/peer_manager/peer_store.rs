@@ -2,14 +2,17 @@ use crate::store;
 use anyhow::bail;
 use near_network_primitives::time;
 use near_network_primitives::types::{
-    Blacklist, KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, ReasonForBan,
+    Blacklist, KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, PeerType, ReasonForBan,
+    TrustLevel,
 };
+use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
-use rand::seq::IteratorRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::thread_rng;
+use std::cmp::Reverse;
 use std::collections::hash_map::{Entry, Iter};
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::ops::Not;
 use tracing::{debug, error, info};
 
@@ -17,17 +20,62 @@ use tracing::{debug, error, info};
 #[path = "peer_store_test.rs"]
 mod test;
 
-/// Level of trust we have about a new (PeerId, Addr) pair.
-#[derive(Eq, PartialEq, Debug, Clone)]
-enum TrustLevel {
-    /// We learn about it from other peers.
-    Indirect,
-    /// Responding node at addr claims to possess PeerId.
-    Direct,
-    /// Responding peer proved to have SecretKey associated with this PeerID.
-    Signed,
+/// A peer which disconnected more than `QUARANTINE_DISCONNECT_LIMIT` times within
+/// `QUARANTINE_WINDOW` is quarantined for `QUARANTINE_COOLDOWN`.
+const QUARANTINE_DISCONNECT_LIMIT: usize = 5;
+const QUARANTINE_WINDOW: time::Duration = time::Duration::minutes(1);
+const QUARANTINE_COOLDOWN: time::Duration = time::Duration::minutes(10);
+
+/// Outbound connection attempts older than this don't count towards
+/// `PeerStore::connection_reliability`.
+const CONNECTION_ATTEMPT_WINDOW: time::Duration = time::Duration::hours(1);
+/// A signed address is downgraded to direct trust once this many consecutive outbound
+/// connection attempts within `CONNECTION_ATTEMPT_WINDOW` failed, see
+/// `PeerStore::record_connection_attempt`.
+const TRUST_DOWNGRADE_FAILURES: usize = 3;
+/// Time after which a peer returned by `PeerStore::reserve_unconnected_peer` can be
+/// returned again, if no connection attempt was recorded for it in the meantime.
+const RESERVATION_TIMEOUT: time::Duration = time::Duration::seconds(30);
+
+/// Peers with a reputation below this are banned. See `KnownPeerState::reputation`.
+const REPUTATION_BAN_THRESHOLD: u32 = KnownPeerState::MAX_REPUTATION / 2;
+/// How much reputation peers recover per minute.
+const REPUTATION_RECOVERY_PER_MINUTE: u32 = 5;
+
+/// How much reputation a peer loses when banned for `reason`. Bans for malicious behavior
+/// wipe out the reputation, a single protocol violation bans a peer with a clean record,
+/// and transient failures ban only peers which keep failing.
+fn ban_penalty(reason: ReasonForBan) -> u32 {
+    match reason {
+        ReasonForBan::BadHandshake | ReasonForBan::EpochSyncNoResponse => 200,
+        ReasonForBan::BadBlockHeader
+        | ReasonForBan::BadBlockApproval
+        | ReasonForBan::InvalidPeerId
+        | ReasonForBan::InvalidHash
+        | ReasonForBan::InvalidEdge
+        | ReasonForBan::EpochSyncInvalidResponse
+        | ReasonForBan::EpochSyncInvalidFinalizationResponse => 600,
+        // `None` is used when banning manually, e.g. in tests.
+        ReasonForBan::None
+        | ReasonForBan::BadBlock
+        | ReasonForBan::HeightFraud
+        | ReasonForBan::Abusive
+        | ReasonForBan::InvalidSignature
+        | ReasonForBan::Blacklisted => KnownPeerState::MAX_REPUTATION,
+    }
 }
 
+/// `PeerStore::size_estimate` serializes at most this many peers and extrapolates.
+const SIZE_ESTIMATE_SAMPLE: usize = 1000;
+/// Buckets of `PeerStore::last_seen_histogram`: a peer falls into the first bucket whose
+/// bound is above the time since it was last seen.
+const LAST_SEEN_BUCKETS: [(&str, time::Duration); 3] = [
+    ("<1m", time::Duration::minutes(1)),
+    ("<1h", time::Duration::hours(1)),
+    ("<1d", time::Duration::days(1)),
+];
+const LAST_SEEN_OLDER: &str = "older";
+
 #[derive(Debug, Clone)]
 struct VerifiedPeer {
     peer_id: PeerId,
@@ -35,14 +83,107 @@ struct VerifiedPeer {
 }
 
 impl VerifiedPeer {
-    fn new(peer_id: PeerId) -> Self {
-        Self { peer_id, trust_level: TrustLevel::Indirect }
-    }
     fn signed(peer_id: PeerId) -> Self {
         Self { peer_id, trust_level: TrustLevel::Signed }
     }
 }
 
+/// Change of the state of a known peer, reported to the callback set with
+/// `PeerStore::set_event_callback`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PeerEvent {
+    pub peer_id: PeerId,
+    pub transition: PeerTransition,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PeerTransition {
+    Connected(PeerType),
+    Disconnected,
+    /// The reputation of the peer dropped below the ban threshold.
+    Banned(ReasonForBan),
+    /// The reputation of the peer was lowered, but it is not banned.
+    Penalized(ReasonForBan),
+    Unbanned,
+    /// The peer was removed by `PeerStore::remove_expired`.
+    Expired,
+}
+
+/// Approximate size of the peer store, see `PeerStore::size_estimate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StoreSizeEstimate {
+    pub peer_count: usize,
+    pub approx_bytes: usize,
+}
+
+/// Portable form of `KnownPeerStatus`, see `PeerStore::to_json`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+enum PeerStatusJson {
+    Unknown,
+    NotConnected,
+    Connected,
+    /// UNIX timestamp of the ban in nanos.
+    Banned(ReasonForBan, u64),
+}
+
+/// Portable form of `KnownPeerState`, see `PeerStore::to_json`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct PeerStateJson {
+    peer_id: PeerId,
+    addr: Option<SocketAddr>,
+    account_id: Option<near_primitives::types::AccountId>,
+    status: PeerStatusJson,
+    /// UNIX timestamps in nanos.
+    first_seen: u64,
+    last_seen: u64,
+    direction: Option<PeerType>,
+    trust_level: TrustLevel,
+    reputation: u32,
+}
+
+impl PeerStateJson {
+    fn new(state: &KnownPeerState) -> Self {
+        let status = match state.status {
+            KnownPeerStatus::Unknown => PeerStatusJson::Unknown,
+            KnownPeerStatus::NotConnected => PeerStatusJson::NotConnected,
+            KnownPeerStatus::Connected => PeerStatusJson::Connected,
+            KnownPeerStatus::Banned(reason, at) => {
+                PeerStatusJson::Banned(reason, at.unix_timestamp_nanos() as u64)
+            }
+        };
+        Self {
+            peer_id: state.peer_info.id.clone(),
+            addr: state.peer_info.addr,
+            account_id: state.peer_info.account_id.clone(),
+            status,
+            first_seen: state.first_seen.unix_timestamp_nanos() as u64,
+            last_seen: state.last_seen.unix_timestamp_nanos() as u64,
+            direction: state.direction,
+            trust_level: state.trust_level.clone(),
+            reputation: state.reputation,
+        }
+    }
+
+    fn into_state(self) -> anyhow::Result<KnownPeerState> {
+        let utc = |nanos: u64| time::Utc::from_unix_timestamp_nanos(nanos as i128);
+        let status = match self.status {
+            PeerStatusJson::Unknown => KnownPeerStatus::Unknown,
+            PeerStatusJson::NotConnected => KnownPeerStatus::NotConnected,
+            PeerStatusJson::Connected => KnownPeerStatus::Connected,
+            PeerStatusJson::Banned(reason, at) => KnownPeerStatus::Banned(reason, utc(at)?),
+        };
+        Ok(KnownPeerState {
+            peer_info: PeerInfo { id: self.peer_id, addr: self.addr, account_id: self.account_id },
+            status,
+            first_seen: utc(self.first_seen)?,
+            last_seen: utc(self.last_seen)?,
+            direction: self.direction,
+            trust_level: self.trust_level,
+            reputation: self.reputation,
+        })
+    }
+}
+
 /// Known peers store, maintaining cache of known peers and connection to storage to save/load them.
 pub struct PeerStore {
     store: store::Store,
@@ -52,6 +193,23 @@ pub struct PeerStore {
     // they will not be present in this list, otherwise they will be present.
     addr_peers: HashMap<SocketAddr, VerifiedPeer>,
     blacklist: Blacklist,
+    /// Ids of the boot nodes, which never expire. See `PeerStore::add_boot_nodes`.
+    boot_nodes: HashSet<PeerId>,
+    /// Times of the recent disconnections of each peer, within `QUARANTINE_WINDOW`.
+    recent_disconnects: HashMap<PeerId, VecDeque<time::Instant>>,
+    /// Peers which connect and disconnect too often, with the time their quarantine ends.
+    /// Unlike bans, quarantines are kept only in memory.
+    quarantined: HashMap<PeerId, time::Instant>,
+    /// Times and outcomes (true on success) of the outbound connection attempts to each peer,
+    /// within `CONNECTION_ATTEMPT_WINDOW` of the latest one.
+    connection_attempts: HashMap<PeerId, VecDeque<(time::Instant, bool)>>,
+    /// Peers we are about to dial, see `reserve_unconnected_peer`, with the time their
+    /// reservation expires.
+    reserved: HashMap<PeerId, time::Instant>,
+    /// Time up to which the reputation recovery has been applied.
+    reputation_recovered_at: time::Instant,
+    /// Called on every `PeerEvent`, if set.
+    event_callback: Option<Box<dyn Fn(PeerEvent) + Send>>,
 }
 
 impl PeerStore {
@@ -76,8 +234,10 @@ impl PeerStore {
             }
             let peer_addr = match peer_info.addr {
                 None => continue,
-                Some(addr) => addr,
+                Some(addr) => Self::canonical_addr(addr),
             };
+            let mut peer_info = peer_info.clone();
+            peer_info.addr = Some(peer_addr);
             let entry = match addr_2_peer.entry(peer_addr) {
                 Entry::Occupied(entry) => {
                     // There is already a different peer_id with this address.
@@ -86,8 +246,11 @@ impl PeerStore {
                 Entry::Vacant(entry) => entry,
             };
             entry.insert(VerifiedPeer::signed(peer_info.id.clone()));
-            peerid_2_state
-                .insert(peer_info.id.clone(), KnownPeerState::new(peer_info.clone(), now));
+            let peer_state = KnownPeerState {
+                trust_level: TrustLevel::Signed,
+                ..KnownPeerState::new(peer_info, now)
+            };
+            peerid_2_state.insert(peer_state.peer_info.id.clone(), peer_state);
         }
 
         let mut peers_to_keep = vec![];
@@ -100,11 +263,16 @@ impl PeerStore {
                 KnownPeerStatus::NotConnected
             };
 
+            let mut peer_info = peer_state.peer_info;
+            peer_info.addr = peer_info.addr.map(Self::canonical_addr);
             let peer_state = KnownPeerState {
-                peer_info: peer_state.peer_info,
+                peer_info,
                 first_seen: peer_state.first_seen,
                 last_seen: peer_state.last_seen,
                 status,
+                direction: peer_state.direction,
+                trust_level: peer_state.trust_level,
+                reputation: peer_state.reputation,
             };
 
             let is_blacklisted =
@@ -121,17 +289,22 @@ impl PeerStore {
             match peerid_2_state.entry(peer_id) {
                 // Peer is a boot node
                 Entry::Occupied(mut current_peer_state) => {
+                    let current_peer_state = current_peer_state.get_mut();
                     if peer_state.status.is_banned() {
                         // If it says in database, that peer should be banned, ban the peer.
-                        current_peer_state.get_mut().status = peer_state.status;
+                        current_peer_state.status = peer_state.status;
                     }
+                    current_peer_state.reputation = peer_state.reputation;
                 }
                 // Peer is not a boot node
                 Entry::Vacant(entry) => {
                     if let Some(peer_addr) = peer_state.peer_info.addr {
                         if let Entry::Vacant(entry2) = addr_2_peer.entry(peer_addr) {
                             // Default case, add new entry.
-                            entry2.insert(VerifiedPeer::new(peer_state.peer_info.id.clone()));
+                            entry2.insert(VerifiedPeer {
+                                peer_id: peer_state.peer_info.id.clone(),
+                                trust_level: peer_state.trust_level.clone(),
+                            });
                             entry.insert(peer_state);
                         }
                         // else: There already exists a peer with a same addr, that's a boot node.
@@ -141,12 +314,148 @@ impl PeerStore {
             }
         }
 
-        let mut peer_store =
-            PeerStore { store, peer_states: peerid_2_state, addr_peers: addr_2_peer, blacklist };
+        let mut peer_store = PeerStore {
+            store,
+            peer_states: peerid_2_state,
+            addr_peers: addr_2_peer,
+            blacklist,
+            boot_nodes: boot_nodes.iter().map(|peer_info| peer_info.id.clone()).collect(),
+            recent_disconnects: HashMap::new(),
+            quarantined: HashMap::new(),
+            connection_attempts: HashMap::new(),
+            reserved: HashMap::new(),
+            reputation_recovered_at: clock.now(),
+            event_callback: None,
+        };
         peer_store.delete_peers(&peers_to_delete)?;
         Ok(peer_store)
     }
 
+    /// Dumps the known peers as human readable JSON, e.g. to share them for debugging
+    /// without access to the database. Load it back with `load_json`. Peers are sorted by id.
+    pub(crate) fn to_json(&self) -> String {
+        let mut peers: Vec<_> = self.peer_states.values().map(PeerStateJson::new).collect();
+        peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        serde_json::to_string_pretty(&peers).unwrap()
+    }
+
+    /// Creates a peer store holding exactly the peers dumped with `to_json`, including their
+    /// status, and writes them to `store`. Unlike `new`, connected peers are kept as connected,
+    /// so that the store can be inspected as it was.
+    pub(crate) fn load_json(
+        clock: &time::Clock,
+        mut store: store::Store,
+        json: &str,
+    ) -> anyhow::Result<Self> {
+        let peers: Vec<PeerStateJson> = serde_json::from_str(json)?;
+        let mut peer_states = HashMap::new();
+        let mut addr_peers = HashMap::new();
+        for peer in peers {
+            let mut state = peer.into_state()?;
+            state.peer_info.addr = state.peer_info.addr.map(Self::canonical_addr);
+            let peer_id = state.peer_info.id.clone();
+            if peer_states.contains_key(&peer_id) {
+                bail!("Peer {} is listed twice", peer_id);
+            }
+            if let Some(addr) = state.peer_info.addr {
+                match addr_peers.entry(addr) {
+                    Entry::Occupied(_) => bail!("Two peers have the same address {}", addr),
+                    Entry::Vacant(entry) => {
+                        entry.insert(VerifiedPeer {
+                            peer_id: peer_id.clone(),
+                            trust_level: state.trust_level.clone(),
+                        });
+                    }
+                }
+            }
+            store.set_peer_state(&peer_id, &state)?;
+            peer_states.insert(peer_id, state);
+        }
+        Ok(PeerStore {
+            store,
+            peer_states,
+            addr_peers,
+            blacklist: Default::default(),
+            boot_nodes: HashSet::new(),
+            recent_disconnects: HashMap::new(),
+            quarantined: HashMap::new(),
+            connection_attempts: HashMap::new(),
+            reserved: HashMap::new(),
+            reputation_recovered_at: clock.now(),
+            event_callback: None,
+        })
+    }
+
+    /// Adds boot nodes to a running peer store, like the ones passed to `PeerStore::new`.
+    /// Boot nodes are trusted as signed and are never removed by `remove_expired`. A boot
+    /// node takes over the address of a known peer which isn't a boot node, while two boot
+    /// nodes with the same address are an error, in which case no node is added. Nodes
+    /// without an address are ignored.
+    pub(crate) fn add_boot_nodes(
+        &mut self,
+        clock: &time::Clock,
+        nodes: &[PeerInfo],
+    ) -> anyhow::Result<()> {
+        let mut to_add: Vec<PeerInfo> = vec![];
+        for peer_info in nodes {
+            if self.boot_nodes.contains(&peer_info.id)
+                || to_add.iter().any(|added| added.id == peer_info.id)
+            {
+                error!(id = ?peer_info.id, "There is a duplicated peer in boot_nodes");
+                continue;
+            }
+            let peer_addr = match peer_info.addr {
+                None => continue,
+                Some(addr) => Self::canonical_addr(addr),
+            };
+            let is_boot_node_addr = self
+                .addr_peers
+                .get(&peer_addr)
+                .map_or(false, |verified_peer| self.boot_nodes.contains(&verified_peer.peer_id));
+            if is_boot_node_addr || to_add.iter().any(|added| added.addr == Some(peer_addr)) {
+                bail!("Two boot nodes have the same address {:?}", peer_addr);
+            }
+            let mut peer_info = peer_info.clone();
+            peer_info.addr = Some(peer_addr);
+            to_add.push(peer_info);
+        }
+
+        for peer_info in to_add {
+            let peer_addr = peer_info.addr.unwrap();
+            self.boot_nodes.insert(peer_info.id.clone());
+            self.update_peer_info(clock, peer_info, peer_addr, TrustLevel::Signed)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the callback invoked when peers get connected, disconnected, banned, unbanned
+    /// or expired. Replaces the previous callback, if any.
+    pub(crate) fn set_event_callback(&mut self, callback: Box<dyn Fn(PeerEvent) + Send>) {
+        self.event_callback = Some(callback);
+    }
+
+    fn notify(&self, peer_id: &PeerId, transition: PeerTransition) {
+        if let Some(callback) = &self.event_callback {
+            callback(PeerEvent { peer_id: peer_id.clone(), transition });
+        }
+    }
+
+    /// Normalizes the address used as a key in the address index, so that a
+    /// dual-stack peer doesn't occupy two slots. IPv4-mapped IPv6 addresses are
+    /// converted to plain IPv4 and the IPv6 flow label is dropped.
+    pub(crate) fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+        match addr {
+            SocketAddr::V4(_) => addr,
+            SocketAddr::V6(v6) => match v6.ip().segments() {
+                [0, 0, 0, 0, 0, 0xffff, hi, lo] => {
+                    let ip = ((hi as u32) << 16 | lo as u32).into();
+                    SocketAddr::V4(SocketAddrV4::new(ip, v6.port()))
+                }
+                _ => SocketAddr::V6(SocketAddrV6::new(*v6.ip(), v6.port(), 0, v6.scope_id())),
+            },
+        }
+    }
+
     pub fn is_blacklisted(&self, addr: &SocketAddr) -> bool {
         self.blacklist.contains(*addr)
     }
@@ -155,22 +464,30 @@ impl PeerStore {
         self.peer_states.len()
     }
 
+    /// Whether the reputation of the peer is below `REPUTATION_BAN_THRESHOLD`.
     pub(crate) fn is_banned(&self, peer_id: &PeerId) -> bool {
-        self.peer_states
-            .get(peer_id)
-            .map_or(false, |known_peer_state| known_peer_state.status.is_banned())
+        self.peer_states.get(peer_id).map_or(false, Self::is_state_banned)
+    }
+
+    fn is_state_banned(peer_state: &KnownPeerState) -> bool {
+        peer_state.reputation < REPUTATION_BAN_THRESHOLD
     }
 
     pub(crate) fn peer_connected(
         &mut self,
         clock: &time::Clock,
         peer_info: &PeerInfo,
+        peer_type: PeerType,
     ) -> anyhow::Result<()> {
         self.add_signed_peer(clock, peer_info.clone())?;
         let entry = self.peer_states.get_mut(&peer_info.id).unwrap();
         entry.last_seen = clock.now_utc();
         entry.status = KnownPeerStatus::Connected;
-        Ok(self.store.set_peer_state(&peer_info.id, entry)?)
+        entry.direction = Some(peer_type);
+        self.store.set_peer_state(&peer_info.id, entry)?;
+        self.reserved.remove(&peer_info.id);
+        self.notify(&peer_info.id, PeerTransition::Connected(peer_type));
+        Ok(())
     }
 
     pub(crate) fn peer_disconnected(
@@ -185,23 +502,102 @@ impl PeerStore {
         } else {
             bail!("Peer {} is missing in the peer store", peer_id);
         }
+        self.reserved.remove(peer_id);
+        self.notify(peer_id, PeerTransition::Disconnected);
+        self.record_disconnect(clock, peer_id);
         Ok(())
     }
 
+    /// Quarantines the peer if it disconnected too many times recently.
+    fn record_disconnect(&mut self, clock: &time::Clock, peer_id: &PeerId) {
+        let now = clock.now();
+        let disconnects = self.recent_disconnects.entry(peer_id.clone()).or_default();
+        disconnects.push_back(now);
+        while disconnects.front().map_or(false, |&first| now - first > QUARANTINE_WINDOW) {
+            disconnects.pop_front();
+        }
+        if disconnects.len() > QUARANTINE_DISCONNECT_LIMIT {
+            debug!(target: "network", ?peer_id, "Quarantining peer which reconnects too often");
+            self.recent_disconnects.remove(peer_id);
+            self.quarantined.insert(peer_id.clone(), now + QUARANTINE_COOLDOWN);
+        }
+    }
+
+    /// Whether the peer connected and disconnected too often recently. We don't try to
+    /// connect to quarantined peers until the quarantine ends.
+    pub(crate) fn is_quarantined(&self, clock: &time::Clock, peer_id: &PeerId) -> bool {
+        self.quarantined.get(peer_id).map_or(false, |&until| clock.now() < until)
+    }
+
+    /// Records whether an outbound connection attempt to a known peer succeeded.
+    /// Ends the reservation of the peer, see `reserve_unconnected_peer`.
+    ///
+    /// After `TRUST_DOWNGRADE_FAILURES` consecutive failures the peer has likely changed its
+    /// address, so a signed address is downgraded to direct trust, which lets a direct claim
+    /// from a new address replace it (see `downgrade_trust`). A single failure, e.g. a
+    /// dropped SYN, isn't enough.
+    pub(crate) fn record_connection_attempt(
+        &mut self,
+        clock: &time::Clock,
+        peer_id: &PeerId,
+        success: bool,
+    ) {
+        self.reserved.remove(peer_id);
+        if !self.peer_states.contains_key(peer_id) {
+            return;
+        }
+        let now = clock.now();
+        let attempts = self.connection_attempts.entry(peer_id.clone()).or_default();
+        attempts.push_back((now, success));
+        while attempts.front().map_or(false, |&(first, _)| now - first > CONNECTION_ATTEMPT_WINDOW)
+        {
+            attempts.pop_front();
+        }
+        let failures = attempts.iter().rev().take_while(|(_, success)| !success).count();
+        if failures >= TRUST_DOWNGRADE_FAILURES {
+            if let Err(err) = self.downgrade_trust(peer_id, TrustLevel::Direct) {
+                debug!(target: "network", ?err, "Failed to downgrade trust of peer");
+            }
+        }
+    }
+
+    /// Fraction of the recent outbound connection attempts to the peer which succeeded, see
+    /// `record_connection_attempt`. Returns None if there were no such attempts.
+    pub(crate) fn connection_reliability(&self, peer_id: &PeerId) -> Option<f64> {
+        let attempts = self.connection_attempts.get(peer_id)?;
+        if attempts.is_empty() {
+            return None;
+        }
+        let successes = attempts.iter().filter(|(_, success)| *success).count();
+        Some(successes as f64 / attempts.len() as f64)
+    }
+
+    /// Lowers the reputation of the peer by the penalty for `ban_reason`. The peer is banned,
+    /// i.e. its status becomes `Banned`, only if its reputation drops below the threshold.
     pub(crate) fn peer_ban(
         &mut self,
         clock: &time::Clock,
         peer_id: &PeerId,
         ban_reason: ReasonForBan,
     ) -> anyhow::Result<()> {
-        if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
+        let transition = if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
             let now = clock.now_utc();
             peer_state.last_seen = now;
-            peer_state.status = KnownPeerStatus::Banned(ban_reason, now);
+            peer_state.reputation = peer_state.reputation.saturating_sub(ban_penalty(ban_reason));
+            let transition = if Self::is_state_banned(peer_state) {
+                peer_state.status = KnownPeerStatus::Banned(ban_reason, now);
+                PeerTransition::Banned(ban_reason)
+            } else {
+                // The caller disconnects the peer anyway.
+                peer_state.status = KnownPeerStatus::NotConnected;
+                PeerTransition::Penalized(ban_reason)
+            };
             self.store.set_peer_state(peer_id, peer_state)?;
+            transition
         } else {
             bail!("Peer {} is missing in the peer store", peer_id);
-        }
+        };
+        self.notify(peer_id, transition);
         Ok(())
     }
 
@@ -213,54 +609,276 @@ impl PeerStore {
                     self.addr_peers.remove(&addr);
                 }
             }
+            self.recent_disconnects.remove(peer_id);
+            self.quarantined.remove(peer_id);
+            self.connection_attempts.remove(peer_id);
+            self.reserved.remove(peer_id);
         }
         Ok(self.store.delete_peer_states(peer_ids)?)
     }
 
+    /// Removes the peer from memory and from disk right away, without waiting for it
+    /// to expire. Returns whether the peer was known.
+    pub(crate) fn forget_peer(&mut self, peer_id: &PeerId) -> anyhow::Result<bool> {
+        let existed = self.peer_states.contains_key(peer_id);
+        self.delete_peers(std::slice::from_ref(peer_id))?;
+        Ok(existed)
+    }
+
+    /// Lifts the ban, raising the reputation of the peer up to the ban threshold. The rest of
+    /// the reputation is recovered over time.
     pub(crate) fn peer_unban(&mut self, peer_id: &PeerId) -> anyhow::Result<()> {
         if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
             peer_state.status = KnownPeerStatus::NotConnected;
+            peer_state.reputation = peer_state.reputation.max(REPUTATION_BAN_THRESHOLD);
             self.store.set_peer_state(&peer_id, peer_state)?;
         } else {
             bail!("Peer {} is missing in the peer store", peer_id);
         }
+        self.notify(peer_id, PeerTransition::Unbanned);
+        Ok(())
+    }
+
+    /// Lowers the trust level of the address of `peer_id` to `to` and persists it. Does
+    /// nothing if the current trust level is already at or below `to`.
+    ///
+    /// `add_peer` never replaces the address of a peer with a signed connection by a direct
+    /// claim, which is a problem for nodes changing their address without changing peer id.
+    /// Downgrading the trust, e.g. after a signed connection dropped, allows a subsequent
+    /// direct claim from a new address to take over.
+    pub(crate) fn downgrade_trust(
+        &mut self,
+        peer_id: &PeerId,
+        to: TrustLevel,
+    ) -> anyhow::Result<()> {
+        let addr = match self.peer_states.get(peer_id) {
+            Some(peer_state) => peer_state.peer_info.addr,
+            None => bail!("Peer {} is missing in the peer store", peer_id),
+        };
+        let verified_peer = match addr.and_then(|addr| self.addr_peers.get_mut(&addr)) {
+            Some(verified_peer) => verified_peer,
+            None => bail!("Peer {} has no known address", peer_id),
+        };
+        if to < verified_peer.trust_level {
+            verified_peer.trust_level = to.clone();
+            self.peer_states.get_mut(peer_id).unwrap().trust_level = to;
+            self.touch(peer_id)?;
+        }
         Ok(())
     }
 
-    /// Find a random subset of peers based on filter.
+    /// Whether the trust level of the known address of `peer_id` is at least `min`.
+    /// Peers which are unknown or have no known address don't meet any trust level.
+    pub(crate) fn meets_trust(&self, peer_id: &PeerId, min: TrustLevel) -> bool {
+        let addr = match self.peer_states.get(peer_id).and_then(|state| state.peer_info.addr) {
+            Some(addr) => addr,
+            None => return false,
+        };
+        self.addr_peers.get(&addr).map_or(false, |verified_peer| {
+            &verified_peer.peer_id == peer_id && verified_peer.trust_level >= min
+        })
+    }
+
+    /// Returns the peer which currently owns `addr` in the address index, together with the
+    /// trust level of the claim. The address is canonicalized first, see `canonical_addr`.
+    pub(crate) fn peer_at_addr(&self, addr: &SocketAddr) -> Option<(PeerId, TrustLevel)> {
+        self.addr_peers
+            .get(&Self::canonical_addr(*addr))
+            .map(|verified_peer| (verified_peer.peer_id.clone(), verified_peer.trust_level.clone()))
+    }
+
+    /// Known address of `peer_id`, unless the address index attributes it to another peer.
+    pub(crate) fn peer_addr(&self, peer_id: &PeerId) -> Option<SocketAddr> {
+        let addr = self.peer_states.get(peer_id)?.peer_info.addr?;
+        match self.peer_at_addr(&addr) {
+            Some((owner, _)) if &owner == peer_id => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Number of known addresses for each trust level. Peers without a known address are not
+    /// counted. Levels without any address are not in the result.
+    pub(crate) fn trust_level_counts(&self) -> HashMap<TrustLevel, usize> {
+        let mut counts = HashMap::new();
+        for verified_peer in self.addr_peers.values() {
+            *counts.entry(verified_peer.trust_level.clone()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Number of known peers by the time since they were last seen, see `LAST_SEEN_BUCKETS`.
+    /// All buckets are present in the result, even if empty. A store where most peers fall
+    /// into the "older" bucket is stale.
+    pub(crate) fn last_seen_histogram(&self, clock: &time::Clock) -> BTreeMap<&'static str, usize> {
+        let now = clock.now_utc();
+        let mut histogram: BTreeMap<&'static str, usize> =
+            LAST_SEEN_BUCKETS.iter().map(|(bucket, _)| (*bucket, 0)).collect();
+        histogram.insert(LAST_SEEN_OLDER, 0);
+        for peer_state in self.peer_states.values() {
+            let age = now - peer_state.last_seen;
+            let bucket = LAST_SEEN_BUCKETS
+                .iter()
+                .find(|(_, bound)| age < *bound)
+                .map_or(LAST_SEEN_OLDER, |(bucket, _)| *bucket);
+            *histogram.get_mut(bucket).unwrap() += 1;
+        }
+        histogram
+    }
+
+    /// Restores `REPUTATION_RECOVERY_PER_MINUTE` of reputation to every peer for each minute
+    /// passed since the last call. Peers whose reputation gets back to the ban threshold are
+    /// unbanned.
+    pub(crate) fn recover_reputation(&mut self, clock: &time::Clock) -> anyhow::Result<()> {
+        let minutes = (clock.now() - self.reputation_recovered_at).whole_minutes();
+        if minutes <= 0 {
+            return Ok(());
+        }
+        self.reputation_recovered_at += time::Duration::minutes(minutes);
+        let recovered = u32::try_from(minutes)
+            .unwrap_or(u32::MAX)
+            .saturating_mul(REPUTATION_RECOVERY_PER_MINUTE);
+
+        let mut to_touch = vec![];
+        let mut unbanned = vec![];
+        for (peer_id, peer_state) in self.peer_states.iter_mut() {
+            if peer_state.reputation >= KnownPeerState::MAX_REPUTATION {
+                continue;
+            }
+            peer_state.reputation =
+                peer_state.reputation.saturating_add(recovered).min(KnownPeerState::MAX_REPUTATION);
+            if peer_state.status.is_banned() && !Self::is_state_banned(peer_state) {
+                debug!(target: "network", ?peer_id, "Unbanning peer which recovered its reputation");
+                peer_state.status = KnownPeerStatus::NotConnected;
+                unbanned.push(peer_id.clone());
+            }
+            to_touch.push(peer_id.clone());
+        }
+        for peer_id in to_touch {
+            self.touch(&peer_id)?;
+        }
+        for peer_id in unbanned {
+            self.notify(&peer_id, PeerTransition::Unbanned);
+        }
+        Ok(())
+    }
+
+    /// Find a subset of peers based on filter, preferring the ones with higher reputation.
+    /// Peers with the same reputation are chosen randomly.
     fn find_peers<F>(&self, filter: F, count: usize) -> Vec<PeerInfo>
     where
         F: FnMut(&&KnownPeerState) -> bool,
     {
-        (self.peer_states.values())
-            .filter(filter)
-            .choose_multiple(&mut thread_rng(), count)
-            .into_iter()
-            .map(|kps| kps.peer_info.clone())
-            .collect()
+        let mut peers: Vec<_> = self.peer_states.values().filter(filter).collect();
+        peers.shuffle(&mut thread_rng());
+        // Stable sort, so that the order of peers with the same reputation stays random.
+        peers.sort_by_key(|kps| Reverse(kps.reputation));
+        peers.into_iter().take(count).map(|kps| kps.peer_info.clone()).collect()
     }
 
     /// Return unconnected or peers with unknown status that we can try to connect to.
-    /// Peers with unknown addresses are filtered out.
+    /// Peers with unknown addresses and quarantined peers are filtered out. Peers with
+    /// higher reputation are preferred, then peers with a higher `connection_reliability`.
+    /// Peers we haven't tried to connect to recently count as reliable.
     pub(crate) fn unconnected_peer(
         &self,
+        clock: &time::Clock,
         ignore_fn: impl Fn(&KnownPeerState) -> bool,
     ) -> Option<PeerInfo> {
-        self.find_peers(
-            |p| {
+        let mut peers: Vec<_> = self
+            .peer_states
+            .values()
+            .filter(|p| {
                 (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
                     && !ignore_fn(p)
                     && p.peer_info.addr.is_some()
-            },
-            1,
-        )
-        .get(0)
-        .cloned()
+                    && !self.is_quarantined(clock, &p.peer_info.id)
+            })
+            .collect();
+        // Shuffle first, so that a random one of the best peers is returned.
+        peers.shuffle(&mut thread_rng());
+        peers
+            .into_iter()
+            .min_by_key(|p| {
+                let reliability = self.connection_reliability(&p.peer_info.id).unwrap_or(1.0);
+                (Reverse(p.reputation), Reverse((reliability * 1000.0) as u32))
+            })
+            .map(|p| p.peer_info.clone())
     }
 
-    /// Return healthy known peers up to given amount.
+    /// Like `unconnected_peer`, but also reserves the returned peer, so that it isn't
+    /// returned again until a connection attempt is recorded for it, it connects or
+    /// disconnects, or `RESERVATION_TIMEOUT` passes. Prevents dialing the same peer twice.
+    pub(crate) fn reserve_unconnected_peer(
+        &mut self,
+        clock: &time::Clock,
+        ignore_fn: impl Fn(&KnownPeerState) -> bool,
+    ) -> Option<PeerInfo> {
+        let now = clock.now();
+        self.reserved.retain(|_, until| now < *until);
+        let peer_info = self.unconnected_peer(clock, |peer_state| {
+            self.reserved.contains_key(&peer_state.peer_info.id) || ignore_fn(peer_state)
+        })?;
+        self.reserved.insert(peer_info.id.clone(), now + RESERVATION_TIMEOUT);
+        Some(peer_info)
+    }
+
+    /// Reserves a peer we can try to dial (see `reserve_unconnected_peer`), as long as the
+    /// number of outbound connections `current_outbound` is below `max_outbound`, so that the
+    /// remaining connection slots stay reserved for inbound peers. Peers which only ever
+    /// connected to us come last, since they may not accept connections (e.g. behind a NAT).
+    pub(crate) fn outbound_candidate(
+        &mut self,
+        clock: &time::Clock,
+        current_outbound: usize,
+        max_outbound: usize,
+        ignore_fn: impl Fn(&KnownPeerState) -> bool,
+    ) -> Option<PeerInfo> {
+        if current_outbound >= max_outbound {
+            return None;
+        }
+        self.reserve_unconnected_peer(clock, |peer_state| {
+            ignore_fn(peer_state) || peer_state.direction == Some(PeerType::Inbound)
+        })
+        .or_else(|| self.reserve_unconnected_peer(clock, ignore_fn))
+    }
+
+    /// Returns the known peers which aren't banned, but whose address is unknown, so that
+    /// they can't be dialed (see `unconnected_peer`) until we learn it. Sorted by id.
+    pub(crate) fn addressless_peers(&self) -> Vec<PeerId> {
+        let mut peers: Vec<_> = self
+            .peer_states
+            .values()
+            .filter(|p| p.peer_info.addr.is_none() && !Self::is_state_banned(p))
+            .map(|p| p.peer_info.id.clone())
+            .collect();
+        peers.sort();
+        peers
+    }
+
+    /// Return healthy known peers up to given amount, preferring the ones with higher reputation.
     pub(crate) fn healthy_peers(&self, max_count: usize) -> Vec<PeerInfo> {
-        self.find_peers(|p| matches!(p.status, KnownPeerStatus::Banned(_, _)).not(), max_count)
+        self.find_peers(|p| Self::is_state_banned(p).not(), max_count)
+    }
+
+    /// Estimates the size of the known peers: their serialized size in the DB, plus
+    /// the in-memory size of the address index. For large stores the serialized
+    /// size is extrapolated from a random sample of `SIZE_ESTIMATE_SAMPLE` peers.
+    pub(crate) fn size_estimate(&self) -> StoreSizeEstimate {
+        let peer_count = self.peer_states.len();
+        let state_size = |(peer_id, state): (&PeerId, &KnownPeerState)| {
+            store::Store::peer_state_size(peer_id, state)
+        };
+        let states_bytes = if peer_count <= SIZE_ESTIMATE_SAMPLE {
+            self.peer_states.iter().map(state_size).sum()
+        } else {
+            let sample =
+                self.peer_states.iter().choose_multiple(&mut thread_rng(), SIZE_ESTIMATE_SAMPLE);
+            let sample_bytes: usize = sample.into_iter().map(state_size).sum();
+            sample_bytes * peer_count / SIZE_ESTIMATE_SAMPLE
+        };
+        let addr_bytes = self.addr_peers.len()
+            * (std::mem::size_of::<SocketAddr>() + std::mem::size_of::<VerifiedPeer>());
+        StoreSizeEstimate { peer_count, approx_bytes: states_bytes + addr_bytes }
     }
 
     /// Return iterator over all known peers.
@@ -269,23 +887,62 @@ impl PeerStore {
     }
 
     /// Removes peers that are not responding for expiration period.
+    /// See `expiration_deadline`. Boot nodes never expire.
     pub(crate) fn remove_expired(
         &mut self,
         clock: &time::Clock,
         config: &NetworkConfig,
     ) -> anyhow::Result<()> {
+        let now_instant = clock.now();
+        self.quarantined.retain(|_, until| now_instant < *until);
+        self.reserved.retain(|_, until| now_instant < *until);
+        self.recent_disconnects.retain(|_, disconnects| {
+            disconnects.back().map_or(false, |&last| now_instant - last <= QUARANTINE_WINDOW)
+        });
+        self.connection_attempts.retain(|_, attempts| {
+            attempts
+                .back()
+                .map_or(false, |&(last, _)| now_instant - last <= CONNECTION_ATTEMPT_WINDOW)
+        });
+
         let now = clock.now_utc();
         let mut to_remove = vec![];
         for (peer_id, peer_status) in self.peer_states.iter() {
             let diff = now - peer_status.last_seen;
+            let deadline = Self::deadline(config, peer_id, peer_status);
             if peer_status.status != KnownPeerStatus::Connected
-                && diff > config.peer_expiration_duration
+                && now > deadline
+                && !self.boot_nodes.contains(peer_id)
             {
                 debug!(target: "network", "Removing peer: last seen {:?}", diff);
                 to_remove.push(peer_id.clone());
             }
         }
-        self.delete_peers(&to_remove)
+        self.delete_peers(&to_remove)?;
+        for peer_id in &to_remove {
+            self.notify(peer_id, PeerTransition::Expired);
+        }
+        Ok(())
+    }
+
+    /// Time after which a not connected peer is removed by `remove_expired`.
+    /// The expiration period is `peer_expiration_duration` adjusted by up to ±10%,
+    /// deterministically derived from the peer id, so that peers seen at the same
+    /// time don't all expire in the same pass.
+    pub(crate) fn expiration_deadline(
+        &self,
+        config: &NetworkConfig,
+        peer_id: &PeerId,
+    ) -> Option<time::Utc> {
+        self.peer_states.get(peer_id).map(|state| Self::deadline(config, peer_id, state))
+    }
+
+    fn deadline(config: &NetworkConfig, peer_id: &PeerId, state: &KnownPeerState) -> time::Utc {
+        let hash = CryptoHash::hash_borsh(peer_id);
+        let seed = u64::from_le_bytes(hash.0[..8].try_into().unwrap());
+        // Uniform in [-0.1, 0.1] with a step of 0.0001.
+        let jitter = (seed % 2001) as f64 / 10_000.0 - 0.1;
+        state.last_seen + config.peer_expiration_duration.mul_f64(1.0 + jitter)
     }
 
     /// Copies the in-mem state of the peer to DB.
@@ -311,6 +968,7 @@ impl PeerStore {
         if let Some(verified_peer) = self.addr_peers.remove(&peer_addr) {
             self.peer_states.entry(verified_peer.peer_id).and_modify(|peer_state| {
                 peer_state.peer_info.addr = None;
+                peer_state.trust_level = TrustLevel::Indirect;
                 touch_other = Some(peer_state.peer_info.id.clone());
             });
         }
@@ -323,16 +981,20 @@ impl PeerStore {
         }
 
         // Add new address
-        self.addr_peers
-            .insert(peer_addr, VerifiedPeer { peer_id: peer_info.id.clone(), trust_level });
+        self.addr_peers.insert(
+            peer_addr,
+            VerifiedPeer { peer_id: peer_info.id.clone(), trust_level: trust_level.clone() },
+        );
 
         let now = clock.now_utc();
 
         // Update peer_id addr
-        self.peer_states
+        let peer_state = self
+            .peer_states
             .entry(peer_info.id.clone())
-            .and_modify(|peer_state| peer_state.peer_info.addr = Some(peer_addr))
             .or_insert_with(|| KnownPeerState::new(peer_info.clone(), now));
+        peer_state.peer_info.addr = Some(peer_addr);
+        peer_state.trust_level = trust_level;
 
         self.touch(&peer_info.id)?;
         if let Some(touch_other) = touch_other {
@@ -346,9 +1008,10 @@ impl PeerStore {
     fn add_peer(
         &mut self,
         clock: &time::Clock,
-        peer_info: PeerInfo,
+        mut peer_info: PeerInfo,
         trust_level: TrustLevel,
     ) -> anyhow::Result<()> {
+        peer_info.addr = peer_info.addr.map(Self::canonical_addr);
         if let Some(peer_addr) = peer_info.addr {
             match trust_level {
                 TrustLevel::Signed => {
@@ -358,6 +1021,7 @@ impl PeerStore {
                     // If this peer already exists with a signed connection ignore this update.
                     // Warning: This is a problem for nodes that changes its address without changing peer_id.
                     //          It is recommended to change peer_id if address is changed.
+                    //          See also `downgrade_trust`.
                     let is_peer_trusted =
                         self.peer_states.get(&peer_info.id).map_or(false, |peer_state| {
                             peer_state.peer_info.addr.map_or(false, |current_addr| {
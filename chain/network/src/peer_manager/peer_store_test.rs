@@ -2,8 +2,9 @@ use near_crypto::{KeyType, SecretKey};
 use near_network_primitives::types::{Blacklist, BlacklistEntry};
 use near_store::test_utils::create_test_store;
 use near_store::{Store, StoreOpener};
-use std::collections::HashSet;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::collections::{BTreeMap, HashSet};
+use std::net::{Ipv4Addr, SocketAddrV4, SocketAddrV6};
+use std::sync::{Arc, Mutex};
 
 use super::*;
 
@@ -50,6 +51,250 @@ fn ban_store() {
     }
 }
 
+#[test]
+fn ban_reputation() {
+    let clock = time::FakeClock::default();
+    let (_tmp_dir, opener) = Store::test_opener();
+    let abusive = gen_peer_info(0);
+    let flaky = gen_peer_info(1);
+    let clean = gen_peer_info(2);
+    let boot_nodes = vec![abusive.clone(), flaky.clone(), clean.clone()];
+    let reputation =
+        |peer_store: &PeerStore, peer_id: &PeerId| peer_store.peer_states[peer_id].reputation;
+    {
+        let store = store::Store::new(opener.open());
+        let mut peer_store =
+            PeerStore::new(&clock.clock(), store, &boot_nodes, Default::default()).unwrap();
+        peer_store.peer_ban(&clock.clock(), &abusive.id, ReasonForBan::Abusive).unwrap();
+        peer_store.peer_ban(&clock.clock(), &flaky.id, ReasonForBan::BadHandshake).unwrap();
+        assert!(reputation(&peer_store, &abusive.id) < reputation(&peer_store, &flaky.id));
+        assert!(
+            reputation(&peer_store, &flaky.id) < KnownPeerState::MAX_REPUTATION,
+            "a minor ban still lowers the reputation"
+        );
+        assert!(peer_store.is_banned(&abusive.id));
+        assert!(!peer_store.is_banned(&flaky.id));
+        assert!(!peer_store.is_banned(&clean.id));
+
+        // The banned peer is never selected, the clean one is preferred over the flaky one.
+        for _ in 0..10 {
+            assert_eq!(peer_store.unconnected_peer(&clock.clock(), |_| false).unwrap(), clean);
+            assert_eq!(peer_store.healthy_peers(1), vec![clean.clone()]);
+        }
+        let ignore_clean = |state: &KnownPeerState| state.peer_info.id == clean.id;
+        assert_eq!(peer_store.unconnected_peer(&clock.clock(), ignore_clean).unwrap(), flaky);
+        assert_eq!(peer_store.healthy_peers(3).len(), 2);
+
+        // Repeated minor bans add up.
+        while !peer_store.is_banned(&flaky.id) {
+            peer_store.peer_ban(&clock.clock(), &flaky.id, ReasonForBan::BadHandshake).unwrap();
+        }
+    }
+    {
+        // The reputation is persisted.
+        let store = store::Store::new(opener.open());
+        let mut peer_store =
+            PeerStore::new(&clock.clock(), store, &boot_nodes, Default::default()).unwrap();
+        assert!(peer_store.is_banned(&abusive.id));
+        assert!(peer_store.is_banned(&flaky.id));
+        assert_eq!(reputation(&peer_store, &clean.id), KnownPeerState::MAX_REPUTATION);
+
+        // Reputation recovers over time, unbanning the peers.
+        let flaky_reputation = reputation(&peer_store, &flaky.id);
+        clock.advance(time::Duration::minutes(10));
+        peer_store.recover_reputation(&clock.clock()).unwrap();
+        assert_eq!(
+            reputation(&peer_store, &flaky.id),
+            flaky_reputation + 10 * REPUTATION_RECOVERY_PER_MINUTE
+        );
+        clock.advance(time::Duration::days(1));
+        peer_store.recover_reputation(&clock.clock()).unwrap();
+        for peer_id in [&abusive.id, &flaky.id, &clean.id] {
+            assert_eq!(reputation(&peer_store, peer_id), KnownPeerState::MAX_REPUTATION);
+            assert!(!peer_store.is_banned(peer_id));
+        }
+        assert_eq!(peer_store.healthy_peers(3).len(), 3);
+    }
+}
+
+#[test]
+fn test_outbound_candidate() {
+    let clock = time::FakeClock::default();
+    let (_tmp_dir, opener) = Store::test_opener();
+    let peer_info_a = gen_peer_info(0);
+    let peer_info_b = gen_peer_info(1);
+    let boot_nodes = vec![peer_info_a.clone(), peer_info_b.clone()];
+    {
+        let store = store::Store::new(opener.open());
+        let mut peer_store =
+            PeerStore::new(&clock.clock(), store, &boot_nodes, Default::default()).unwrap();
+        // Peer b only ever connected to us, so it comes last.
+        peer_store.peer_connected(&clock.clock(), &peer_info_b, PeerType::Inbound).unwrap();
+        peer_store.peer_disconnected(&clock.clock(), &peer_info_b.id).unwrap();
+        let candidate = peer_store.outbound_candidate(&clock.clock(), 0, 2, |_| false);
+        assert_eq!(candidate.map(|p| p.id), Some(peer_info_a.id.clone()));
+        let candidate = peer_store.outbound_candidate(&clock.clock(), 1, 2, |_| false);
+        assert_eq!(candidate.map(|p| p.id), Some(peer_info_b.id.clone()));
+
+        // Outbound budget is spent, even though there are unconnected peers.
+        clock.advance(RESERVATION_TIMEOUT);
+        assert!(peer_store.outbound_candidate(&clock.clock(), 2, 2, |_| false).is_none());
+        assert!(peer_store.outbound_candidate(&clock.clock(), 1, 2, |_| false).is_some());
+        peer_store.peer_connected(&clock.clock(), &peer_info_a, PeerType::Outbound).unwrap();
+    }
+    {
+        // Direction of the connection is persisted.
+        let store = store::Store::new(opener.open());
+        let peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+        let state = peer_store.peer_states.get(&peer_info_a.id).unwrap();
+        assert_eq!(state.direction, Some(PeerType::Outbound));
+    }
+}
+
+#[test]
+fn test_expiration_deadline() {
+    let clock = time::FakeClock::default();
+    let config = NetworkConfig::from_seed("node", 0);
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let peer_infos: Vec<_> = (0..2)
+        .map(|i| get_peer_info(get_peer_id(format!("node{}", i)), Some(get_addr(i))))
+        .collect();
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos.clone().into_iter()).unwrap();
+
+    let mut deadlines: Vec<_> = peer_infos
+        .iter()
+        .map(|info| (peer_store.expiration_deadline(&config, &info.id).unwrap(), info.id.clone()))
+        .collect();
+    deadlines.sort();
+    let (first, first_peer) = deadlines[0].clone();
+    let (second, second_peer) = deadlines[1].clone();
+    assert_ne!(first, second);
+
+    let now = clock.now_utc();
+    let expiration = config.peer_expiration_duration;
+    for deadline in [first, second] {
+        assert!(deadline >= now + expiration.mul_f64(0.9));
+        assert!(deadline <= now + expiration.mul_f64(1.1));
+    }
+
+    // Only the peer with the earlier deadline expires.
+    // Deadlines differ by at least 0.01% of the expiration period.
+    clock.advance(first - now + time::Duration::nanoseconds(1));
+    peer_store.remove_expired(&clock.clock(), &config).unwrap();
+    assert!(!peer_store.peer_states.contains_key(&first_peer));
+    assert!(peer_store.peer_states.contains_key(&second_peer));
+
+    clock.advance(second - clock.now_utc() + time::Duration::nanoseconds(1));
+    peer_store.remove_expired(&clock.clock(), &config).unwrap();
+    assert!(!peer_store.peer_states.contains_key(&second_peer));
+}
+
+#[test]
+fn event_callback() {
+    let clock = time::FakeClock::default();
+    let config = NetworkConfig::from_seed("node", 0);
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+    let events = Arc::new(Mutex::new(vec![]));
+    {
+        let events = events.clone();
+        peer_store.set_event_callback(Box::new(move |event| events.lock().unwrap().push(event)));
+    }
+
+    let peer_info = gen_peer_info(0);
+    let id = peer_info.id.clone();
+    peer_store.peer_connected(&clock.clock(), &peer_info, PeerType::Inbound).unwrap();
+    peer_store.peer_disconnected(&clock.clock(), &id).unwrap();
+    peer_store.peer_ban(&clock.clock(), &id, ReasonForBan::BadHandshake).unwrap();
+    peer_store.peer_ban(&clock.clock(), &id, ReasonForBan::Abusive).unwrap();
+    peer_store.peer_unban(&id).unwrap();
+    clock.advance(config.peer_expiration_duration.mul_f64(1.2));
+    peer_store.remove_expired(&clock.clock(), &config).unwrap();
+
+    let event = |transition| PeerEvent { peer_id: id.clone(), transition };
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            event(PeerTransition::Connected(PeerType::Inbound)),
+            event(PeerTransition::Disconnected),
+            event(PeerTransition::Penalized(ReasonForBan::BadHandshake)),
+            event(PeerTransition::Banned(ReasonForBan::Abusive)),
+            event(PeerTransition::Unbanned),
+            event(PeerTransition::Expired),
+        ]
+    );
+}
+
+#[test]
+fn remove_expired_peers() {
+    let clock = time::FakeClock::default();
+    let config = NetworkConfig::from_seed("node", 0);
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let aged = gen_peer_info(0);
+    let connected = gen_peer_info(1);
+    let fresh = gen_peer_info(2);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(aged.clone())).unwrap();
+    peer_store.peer_connected(&clock.clock(), &connected, PeerType::Inbound).unwrap();
+
+    // Past the deadline of every peer seen so far, including the jitter.
+    clock.advance(config.peer_expiration_duration.mul_f64(1.2));
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(fresh.clone())).unwrap();
+    peer_store.remove_expired(&clock.clock(), &config).unwrap();
+
+    assert!(!peer_store.peer_states.contains_key(&aged.id));
+    // Connected peers never expire.
+    assert!(peer_store.peer_states.contains_key(&connected.id));
+    assert!(peer_store.peer_states.contains_key(&fresh.id));
+
+    peer_store.peer_disconnected(&clock.clock(), &connected.id).unwrap();
+    clock.advance(config.peer_expiration_duration.mul_f64(0.5));
+    peer_store.remove_expired(&clock.clock(), &config).unwrap();
+    assert!(peer_store.peer_states.contains_key(&connected.id));
+    assert!(peer_store.peer_states.contains_key(&fresh.id));
+}
+
+#[test]
+fn add_boot_nodes() {
+    let clock = time::FakeClock::default();
+    let config = NetworkConfig::from_seed("node", 0);
+    let store = store::Store::new(create_test_store());
+    let boot_node = gen_peer_info(0);
+    let mut peer_store =
+        PeerStore::new(&clock.clock(), store, &[boot_node.clone()], Default::default()).unwrap();
+
+    let known = gen_peer_info(1);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(known.clone())).unwrap();
+    // Takes over the address of a peer which isn't a boot node.
+    let new_boot_node = get_peer_info(get_peer_id("new".to_string()), known.addr);
+    peer_store.add_boot_nodes(&clock.clock(), &[new_boot_node.clone()]).unwrap();
+    assert!(check_exist(
+        &peer_store,
+        &new_boot_node.id,
+        Some((known.addr.unwrap(), TrustLevel::Signed))
+    ));
+    assert!(check_exist(&peer_store, &known.id, None));
+    assert!(check_integrity(&peer_store));
+
+    // Collides with the address of a boot node, nothing is added.
+    let colliding = get_peer_info(get_peer_id("colliding".to_string()), boot_node.addr);
+    let other = gen_peer_info(2);
+    assert!(peer_store
+        .add_boot_nodes(&clock.clock(), &[other.clone(), colliding.clone()])
+        .is_err());
+    assert!(!peer_store.peer_states.contains_key(&other.id));
+    assert!(!peer_store.peer_states.contains_key(&colliding.id));
+
+    clock.advance(config.peer_expiration_duration.mul_f64(1.2));
+    peer_store.remove_expired(&clock.clock(), &config).unwrap();
+    assert!(peer_store.peer_states.contains_key(&boot_node.id));
+    assert!(peer_store.peer_states.contains_key(&new_boot_node.id));
+    assert!(!peer_store.peer_states.contains_key(&known.id));
+}
+
 #[test]
 fn test_unconnected_peer() {
     let clock = time::FakeClock::default();
@@ -61,11 +306,110 @@ fn test_unconnected_peer() {
         let store = store::Store::new(opener.open());
         let peer_store =
             PeerStore::new(&clock.clock(), store, &boot_nodes, Default::default()).unwrap();
-        assert!(peer_store.unconnected_peer(|_| false).is_some());
-        assert!(peer_store.unconnected_peer(|_| true).is_none());
+        assert!(peer_store.unconnected_peer(&clock.clock(), |_| false).is_some());
+        assert!(peer_store.unconnected_peer(&clock.clock(), |_| true).is_none());
     }
 }
 
+#[test]
+fn reserve_unconnected_peer() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+    let peer_infos: Vec<_> = (0..2).map(gen_peer_info).collect();
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos.iter().cloned()).unwrap();
+
+    let first = peer_store.reserve_unconnected_peer(&clock.clock(), |_| false).unwrap();
+    let second = peer_store.reserve_unconnected_peer(&clock.clock(), |_| false).unwrap();
+    assert_ne!(first.id, second.id);
+    assert!(peer_store.reserve_unconnected_peer(&clock.clock(), |_| false).is_none());
+    // Reservations don't affect `unconnected_peer`.
+    assert!(peer_store.unconnected_peer(&clock.clock(), |_| false).is_some());
+
+    // A failed attempt ends the reservation.
+    peer_store.record_connection_attempt(&clock.clock(), &first.id, false);
+    assert_eq!(peer_store.reserve_unconnected_peer(&clock.clock(), |_| false), Some(first));
+
+    // Reservations expire without an attempt.
+    clock.advance(RESERVATION_TIMEOUT);
+    assert!(peer_store.reserve_unconnected_peer(&clock.clock(), |_| false).is_some());
+    assert!(peer_store.reserve_unconnected_peer(&clock.clock(), |_| false).is_some());
+    assert!(peer_store.reserve_unconnected_peer(&clock.clock(), |_| false).is_none());
+}
+
+#[test]
+fn addressless_peers() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+    assert!(peer_store.addressless_peers().is_empty());
+
+    let no_addr = get_peer_info(get_peer_id("no_addr".to_string()), None);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(no_addr.clone())).unwrap();
+    assert_eq!(peer_store.addressless_peers(), vec![no_addr.id.clone()]);
+    assert!(peer_store.unconnected_peer(&clock.clock(), |_| false).is_none());
+
+    // Peers with an address aren't included.
+    let with_addr = gen_peer_info(0);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(with_addr.clone())).unwrap();
+    assert_eq!(peer_store.addressless_peers(), vec![no_addr.id.clone()]);
+    assert_eq!(peer_store.unconnected_peer(&clock.clock(), |_| false), Some(with_addr));
+
+    // Once we connect to it, the peer is no longer addressless.
+    let learned = get_peer_info(no_addr.id.clone(), Some(get_addr(1)));
+    peer_store.add_direct_peer(&clock.clock(), learned).unwrap();
+    assert!(peer_store.addressless_peers().is_empty());
+}
+
+#[test]
+fn quarantine_flapping_peer() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let flapping = gen_peer_info(0);
+    let stable = gen_peer_info(1);
+    for _ in 0..QUARANTINE_DISCONNECT_LIMIT {
+        peer_store.peer_connected(&clock.clock(), &flapping, PeerType::Outbound).unwrap();
+        peer_store.peer_disconnected(&clock.clock(), &flapping.id).unwrap();
+        clock.advance(time::Duration::seconds(1));
+    }
+    assert!(!peer_store.is_quarantined(&clock.clock(), &flapping.id));
+
+    peer_store.peer_connected(&clock.clock(), &stable, PeerType::Outbound).unwrap();
+    peer_store.peer_disconnected(&clock.clock(), &stable.id).unwrap();
+    peer_store.peer_connected(&clock.clock(), &flapping, PeerType::Outbound).unwrap();
+    peer_store.peer_disconnected(&clock.clock(), &flapping.id).unwrap();
+    assert!(peer_store.is_quarantined(&clock.clock(), &flapping.id));
+    assert!(!peer_store.is_quarantined(&clock.clock(), &stable.id));
+    // Quarantine is not a ban.
+    assert!(!peer_store.is_banned(&flapping.id));
+    for _ in 0..10 {
+        let candidate = peer_store.unconnected_peer(&clock.clock(), |_| false).unwrap();
+        assert_eq!(candidate.id, stable.id);
+    }
+
+    clock.advance(QUARANTINE_COOLDOWN);
+    assert!(!peer_store.is_quarantined(&clock.clock(), &flapping.id));
+    let ignore_stable = |state: &KnownPeerState| state.peer_info.id == stable.id;
+    let candidate = peer_store.unconnected_peer(&clock.clock(), ignore_stable).unwrap();
+    assert_eq!(candidate.id, flapping.id);
+
+    // Disconnects spread over more than the window don't quarantine the peer.
+    for _ in 0..2 * QUARANTINE_DISCONNECT_LIMIT {
+        peer_store.peer_connected(&clock.clock(), &stable, PeerType::Inbound).unwrap();
+        peer_store.peer_disconnected(&clock.clock(), &stable.id).unwrap();
+        clock.advance(QUARANTINE_WINDOW / 3);
+    }
+    assert!(!peer_store.is_quarantined(&clock.clock(), &stable.id));
+
+    // Disconnects older than the window are dropped.
+    assert!(peer_store.recent_disconnects.contains_key(&stable.id));
+    clock.advance(QUARANTINE_WINDOW);
+    peer_store.remove_expired(&clock.clock(), &NetworkConfig::from_seed("node", 0)).unwrap();
+    assert!(peer_store.recent_disconnects.is_empty());
+}
+
 fn check_exist(
     peer_store: &PeerStore,
     peer_id: &PeerId,
@@ -100,6 +444,7 @@ fn check_integrity(peer_store: &PeerStore) -> bool {
             .peer_states
             .get(&v.peer_id)
             .map_or(true, |value| value.peer_info.addr.map_or(true, |addr| addr != *k))
+            && peer_store.peer_states[&v.peer_id].trust_level == v.trust_level
     })
 }
 
@@ -115,7 +460,7 @@ fn handle_peer_id_change() {
     let addr = get_addr(0);
 
     let peer_aa = get_peer_info(peers_id[0].clone(), Some(addr));
-    peer_store.peer_connected(&clock.clock(), &peer_aa).unwrap();
+    peer_store.peer_connected(&clock.clock(), &peer_aa, PeerType::Outbound).unwrap();
     assert!(check_exist(&peer_store, &peers_id[0], Some((addr, TrustLevel::Signed))));
 
     let peer_ba = get_peer_info(peers_id[1].clone(), Some(addr));
@@ -139,7 +484,7 @@ fn dont_handle_address_change() {
     let addrs = (0..2).map(get_addr).collect::<Vec<_>>();
 
     let peer_aa = get_peer_info(peers_id[0].clone(), Some(addrs[0]));
-    peer_store.peer_connected(&clock.clock(), &peer_aa).unwrap();
+    peer_store.peer_connected(&clock.clock(), &peer_aa, PeerType::Outbound).unwrap();
     assert!(check_exist(&peer_store, &peers_id[0], Some((addrs[0], TrustLevel::Signed))));
 
     let peer_ba = get_peer_info(peers_id[0].clone(), Some(addrs[1]));
@@ -148,6 +493,289 @@ fn dont_handle_address_change() {
     assert!(check_integrity(&peer_store));
 }
 
+#[test]
+fn peer_at_addr() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let peers_id = (0..2).map(|ix| get_peer_id(format!("node{}", ix))).collect::<Vec<_>>();
+    let addr = get_addr(0);
+    assert_eq!(peer_store.peer_at_addr(&addr), None);
+
+    peer_store
+        .add_indirect_peers(
+            &clock.clock(),
+            std::iter::once(get_peer_info(peers_id[0].clone(), Some(addr))),
+        )
+        .unwrap();
+    assert_eq!(peer_store.peer_at_addr(&addr), Some((peers_id[0].clone(), TrustLevel::Indirect)));
+
+    // A connected peer takes over the address.
+    let peer_info = get_peer_info(peers_id[1].clone(), Some(addr));
+    peer_store.peer_connected(&clock.clock(), &peer_info, PeerType::Outbound).unwrap();
+    assert_eq!(peer_store.peer_at_addr(&addr), Some((peers_id[1].clone(), TrustLevel::Signed)));
+    assert_eq!(peer_store.peer_at_addr(&get_addr(1)), None);
+}
+
+#[test]
+fn downgrade_trust() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let peer_id = get_peer_id("node0".to_string());
+    let addrs = (0..2).map(get_addr).collect::<Vec<_>>();
+
+    let peer_aa = get_peer_info(peer_id.clone(), Some(addrs[0]));
+    peer_store.peer_connected(&clock.clock(), &peer_aa, PeerType::Outbound).unwrap();
+    peer_store.peer_disconnected(&clock.clock(), &peer_id).unwrap();
+
+    // Downgrading never raises the trust level.
+    peer_store.downgrade_trust(&peer_id, TrustLevel::Signed).unwrap();
+    assert!(check_exist(&peer_store, &peer_id, Some((addrs[0], TrustLevel::Signed))));
+
+    peer_store.downgrade_trust(&peer_id, TrustLevel::Direct).unwrap();
+    assert!(check_exist(&peer_store, &peer_id, Some((addrs[0], TrustLevel::Direct))));
+
+    // Direct claim from a new address now takes effect.
+    let peer_ab = get_peer_info(peer_id.clone(), Some(addrs[1]));
+    peer_store.add_peer(&clock.clock(), peer_ab, TrustLevel::Direct).unwrap();
+    assert!(check_exist(&peer_store, &peer_id, Some((addrs[1], TrustLevel::Direct))));
+    assert!(check_integrity(&peer_store));
+
+    let unknown = get_peer_id("node1".to_string());
+    assert!(peer_store.downgrade_trust(&unknown, TrustLevel::Indirect).is_err());
+}
+
+#[test]
+fn repeated_dial_failures_downgrade_trust() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let peer_info = gen_peer_info(0);
+    let addr = peer_info.addr;
+    peer_store.add_signed_peer(&clock.clock(), peer_info.clone()).unwrap();
+
+    // A success in between resets the count of consecutive failures.
+    for _ in 0..TRUST_DOWNGRADE_FAILURES - 1 {
+        peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, false);
+    }
+    peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, true);
+    for _ in 0..TRUST_DOWNGRADE_FAILURES - 1 {
+        peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, false);
+    }
+    assert!(check_exist(&peer_store, &peer_info.id, Some((addr.unwrap(), TrustLevel::Signed))));
+
+    // Failures older than the window don't count either.
+    clock.advance(CONNECTION_ATTEMPT_WINDOW + time::Duration::seconds(1));
+    peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, false);
+    assert!(check_exist(&peer_store, &peer_info.id, Some((addr.unwrap(), TrustLevel::Signed))));
+
+    for _ in 0..TRUST_DOWNGRADE_FAILURES - 1 {
+        peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, false);
+    }
+    assert!(check_exist(&peer_store, &peer_info.id, Some((addr.unwrap(), TrustLevel::Direct))));
+    assert!(check_integrity(&peer_store));
+}
+
+#[test]
+fn meets_trust() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let peer_infos: Vec<_> = (0..4).map(gen_peer_info).collect();
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(peer_infos[0].clone())).unwrap();
+    peer_store.add_direct_peer(&clock.clock(), peer_infos[1].clone()).unwrap();
+    peer_store.add_signed_peer(&clock.clock(), peer_infos[2].clone()).unwrap();
+    let no_addr = get_peer_info(peer_infos[3].id.clone(), None);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(no_addr)).unwrap();
+
+    let levels = [TrustLevel::Indirect, TrustLevel::Direct, TrustLevel::Signed];
+    for (i, peer_info) in peer_infos[0..3].iter().enumerate() {
+        for (j, min) in levels.iter().enumerate() {
+            assert_eq!(peer_store.meets_trust(&peer_info.id, min.clone()), i >= j);
+        }
+    }
+    for min in levels {
+        assert!(!peer_store.meets_trust(&peer_infos[3].id, min.clone()));
+        assert!(!peer_store.meets_trust(&get_peer_id("unknown".to_string()), min));
+    }
+}
+
+#[test]
+fn connection_reliability() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let peer_info = gen_peer_info(0);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(peer_info.clone())).unwrap();
+    assert_eq!(peer_store.connection_reliability(&peer_info.id), None);
+
+    peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, false);
+    clock.advance(time::Duration::minutes(30));
+    for success in [true, true, false] {
+        peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, success);
+    }
+    assert_eq!(peer_store.connection_reliability(&peer_info.id), Some(0.5));
+
+    // The first failure falls out of the window.
+    clock.advance(time::Duration::minutes(31));
+    peer_store.record_connection_attempt(&clock.clock(), &peer_info.id, true);
+    assert_eq!(peer_store.connection_reliability(&peer_info.id), Some(0.75));
+
+    // Attempts to unknown peers are not recorded.
+    let unknown = gen_peer_info(1);
+    peer_store.record_connection_attempt(&clock.clock(), &unknown.id, true);
+    assert_eq!(peer_store.connection_reliability(&unknown.id), None);
+
+    peer_store.forget_peer(&peer_info.id).unwrap();
+    assert_eq!(peer_store.connection_reliability(&peer_info.id), None);
+}
+
+#[test]
+fn prefer_reliable_peers() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let reliable = gen_peer_info(0);
+    let unreliable = gen_peer_info(1);
+    peer_store
+        .add_indirect_peers(&clock.clock(), [reliable.clone(), unreliable.clone()].into_iter())
+        .unwrap();
+    for success in [true, false] {
+        peer_store.record_connection_attempt(&clock.clock(), &reliable.id, true);
+        peer_store.record_connection_attempt(&clock.clock(), &unreliable.id, success);
+    }
+    for _ in 0..10 {
+        assert_eq!(peer_store.unconnected_peer(&clock.clock(), |_| false), Some(reliable.clone()));
+    }
+
+    // Reputation goes first.
+    peer_store.peer_ban(&clock.clock(), &reliable.id, ReasonForBan::BadHandshake).unwrap();
+    assert!(!peer_store.is_banned(&reliable.id));
+    assert_eq!(peer_store.unconnected_peer(&clock.clock(), |_| false), Some(unreliable));
+}
+
+#[test]
+fn json_round_trip() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let connected = gen_peer_info(0);
+    let banned = gen_peer_info(1);
+    let unknown = get_peer_info(gen_peer_info(2).id, None);
+    peer_store.peer_connected(&clock.clock(), &connected, PeerType::Inbound).unwrap();
+    peer_store
+        .add_indirect_peers(&clock.clock(), [banned.clone(), unknown.clone()].into_iter())
+        .unwrap();
+    clock.advance(time::Duration::seconds(5));
+    peer_store.peer_ban(&clock.clock(), &banned.id, ReasonForBan::Abusive).unwrap();
+    assert!(peer_store.is_banned(&banned.id));
+
+    let json = peer_store.to_json();
+    assert!(json.contains("Abusive"));
+    let db = create_test_store();
+    let loaded =
+        PeerStore::load_json(&clock.clock(), store::Store::new(db.clone()), &json).unwrap();
+    assert_eq!(loaded.to_json(), json);
+    assert_eq!(loaded.len(), 3);
+    for peer_id in [&connected.id, &banned.id, &unknown.id] {
+        let expected = peer_store.peer_states.get(peer_id).unwrap();
+        let actual = loaded.peer_states.get(peer_id).unwrap();
+        assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+    }
+    assert_eq!(loaded.peer_states[&connected.id].status, KnownPeerStatus::Connected);
+    assert_eq!(loaded.peer_states[&connected.id].direction, Some(PeerType::Inbound));
+    assert!(matches!(
+        loaded.peer_states[&banned.id].status,
+        KnownPeerStatus::Banned(ReasonForBan::Abusive, _)
+    ));
+    assert!(loaded.is_banned(&banned.id));
+    assert_eq!(loaded.peer_at_addr(&connected.addr.unwrap()).unwrap().0, connected.id);
+    assert!(check_integrity(&loaded));
+    // The peers were written to the store as well.
+    assert_eq!(store::Store::new(db).list_peer_states().unwrap().len(), 3);
+
+    let store = store::Store::new(create_test_store());
+    assert!(PeerStore::load_json(&clock.clock(), store, "not json").is_err());
+}
+
+#[test]
+fn last_seen_histogram() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+    let empty = BTreeMap::from([("<1m", 0), ("<1h", 0), ("<1d", 0), ("older", 0)]);
+    assert_eq!(peer_store.last_seen_histogram(&clock.clock()), empty);
+
+    let peer_infos: Vec<_> = (0..5).map(gen_peer_info).collect();
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos[0..1].iter().cloned()).unwrap();
+    clock.advance(time::Duration::days(2));
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos[1..2].iter().cloned()).unwrap();
+    clock.advance(time::Duration::hours(2));
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos[2..4].iter().cloned()).unwrap();
+    clock.advance(time::Duration::minutes(10));
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos[4..5].iter().cloned()).unwrap();
+    clock.advance(time::Duration::seconds(30));
+
+    let expected = BTreeMap::from([("<1m", 1), ("<1h", 2), ("<1d", 1), ("older", 1)]);
+    assert_eq!(peer_store.last_seen_histogram(&clock.clock()), expected);
+}
+
+/// A dual-stack peer learned via both its IPv4 address and the IPv4-mapped
+/// IPv6 form of it should occupy a single slot in the address index.
+#[test]
+fn handle_ipv4_mapped_address() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+
+    let peer_id = get_peer_id("node0".to_string());
+    let addr_v4 = get_addr(24567);
+    let addr_v6: SocketAddr =
+        SocketAddrV6::new(Ipv4Addr::new(127, 0, 0, 1).to_ipv6_mapped(), 24567, 0, 0).into();
+    assert_eq!(PeerStore::canonical_addr(addr_v6), addr_v4);
+
+    let peer_info = get_peer_info(peer_id.clone(), Some(addr_v4));
+    peer_store.peer_connected(&clock.clock(), &peer_info, PeerType::Outbound).unwrap();
+    let peer_info = get_peer_info(peer_id.clone(), Some(addr_v6));
+    peer_store.add_peer(&clock.clock(), peer_info, TrustLevel::Signed).unwrap();
+
+    assert_eq!(peer_store.addr_peers.len(), 1);
+    assert!(check_exist(&peer_store, &peer_id, Some((addr_v4, TrustLevel::Signed))));
+    assert!(check_integrity(&peer_store));
+}
+
+#[test]
+fn trust_level_counts() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+    assert!(peer_store.trust_level_counts().is_empty());
+
+    let peer_infos: Vec<_> = (0..7).map(gen_peer_info).collect();
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos[0..3].iter().cloned()).unwrap();
+    peer_store.add_direct_peer(&clock.clock(), peer_infos[3].clone()).unwrap();
+    peer_store.add_direct_peer(&clock.clock(), peer_infos[4].clone()).unwrap();
+    peer_store.add_signed_peer(&clock.clock(), peer_infos[5].clone()).unwrap();
+    // Peers without an address are not counted.
+    let no_addr = get_peer_info(peer_infos[6].id.clone(), None);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(no_addr)).unwrap();
+
+    let expected = HashMap::from([
+        (TrustLevel::Indirect, 3),
+        (TrustLevel::Direct, 2),
+        (TrustLevel::Signed, 1),
+    ]);
+    assert_eq!(peer_store.trust_level_counts(), expected);
+}
+
 #[test]
 fn check_add_peers_overriding() {
     let clock = time::FakeClock::default();
@@ -163,7 +791,7 @@ fn check_add_peers_overriding() {
 
     // Create signed connection A - #A
     let peer_00 = get_peer_info(peers_id[0].clone(), Some(addrs[0]));
-    peer_store.peer_connected(&clock.clock(), &peer_00).unwrap();
+    peer_store.peer_connected(&clock.clock(), &peer_00, PeerType::Outbound).unwrap();
     assert!(check_exist(&peer_store, &peers_id[0], Some((addrs[0], TrustLevel::Signed))));
     assert!(check_integrity(&peer_store));
 
@@ -174,7 +802,7 @@ fn check_add_peers_overriding() {
     assert!(check_integrity(&peer_store));
 
     // Create signed connection B - #B
-    peer_store.peer_connected(&clock.clock(), &peer_11).unwrap();
+    peer_store.peer_connected(&clock.clock(), &peer_11, PeerType::Outbound).unwrap();
     assert!(check_exist(&peer_store, &peers_id[1], Some((addrs[1], TrustLevel::Signed))));
     assert!(check_integrity(&peer_store));
 
@@ -185,14 +813,14 @@ fn check_add_peers_overriding() {
     assert!(check_integrity(&peer_store));
 
     // Create signed connection C - #C
-    peer_store.peer_connected(&clock.clock(), &peer_22).unwrap();
+    peer_store.peer_connected(&clock.clock(), &peer_22, PeerType::Outbound).unwrap();
     assert!(check_exist(&peer_store, &peers_id[2], Some((addrs[2], TrustLevel::Signed))));
     assert!(check_integrity(&peer_store));
 
     // Create signed connection C - #B
     // This overrides C - #C and B - #B
     let peer_21 = get_peer_info(peers_id[2].clone(), Some(addrs[1]));
-    peer_store.peer_connected(&clock.clock(), &peer_21).unwrap();
+    peer_store.peer_connected(&clock.clock(), &peer_21, PeerType::Outbound).unwrap();
     assert!(check_exist(&peer_store, &peers_id[1], None));
     assert!(check_exist(&peer_store, &peers_id[2], Some((addrs[1], TrustLevel::Signed))));
     assert!(check_integrity(&peer_store));
@@ -232,7 +860,9 @@ fn check_add_peers_overriding() {
     let peer_store_2 =
         PeerStore::new(&clock.clock(), store::Store::new(store.clone()), &[], Default::default())
             .unwrap();
-    assert!(check_exist(&peer_store_2, &peers_id[0], Some((addrs[0], TrustLevel::Indirect))));
+    assert!(check_exist(&peer_store_2, &peers_id[0], Some((addrs[0], TrustLevel::Signed))));
+    assert!(check_exist(&peer_store_2, &peers_id[2], Some((addrs[1], TrustLevel::Signed))));
+    assert!(check_exist(&peer_store_2, &peers_id[4], Some((addrs[3], TrustLevel::Direct))));
     assert!(check_integrity(&peer_store_2));
 }
 
@@ -394,3 +1024,58 @@ fn test_delete_peers() {
     }
     assert_peers_in_store(&opener, &[]);
 }
+
+#[test]
+fn test_forget_peer() {
+    let clock = time::FakeClock::default();
+    let (_tmp_dir, opener) = Store::test_opener();
+    let (peer_ids, peer_infos): (Vec<_>, Vec<_>) = (0..3)
+        .map(|i| {
+            let id = get_peer_id(format!("node{}", i));
+            let info = get_peer_info(id.clone(), Some(get_addr(i)));
+            (id, info)
+        })
+        .unzip();
+    let peer_addresses = peer_infos.iter().map(|info| info.addr.unwrap()).collect::<Vec<_>>();
+
+    let store = store::Store::new(opener.open());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos.into_iter()).unwrap();
+
+    assert!(peer_store.forget_peer(&peer_ids[1]).unwrap());
+    assert_peers_in_cache(
+        &peer_store,
+        &[peer_ids[0].clone(), peer_ids[2].clone()],
+        &[peer_addresses[0], peer_addresses[2]],
+    );
+    assert!(check_integrity(&peer_store));
+
+    // Forgetting an unknown peer is a no-op.
+    assert!(!peer_store.forget_peer(&peer_ids[1]).unwrap());
+    drop(peer_store);
+    assert_peers_in_store(&opener, &[peer_ids[0].clone(), peer_ids[2].clone()]);
+}
+
+#[test]
+fn size_estimate() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut peer_store = PeerStore::new(&clock.clock(), store, &[], Default::default()).unwrap();
+    assert_eq!(peer_store.size_estimate(), StoreSizeEstimate { peer_count: 0, approx_bytes: 0 });
+
+    let peer_infos: Vec<_> = (0..3).map(gen_peer_info).collect();
+    peer_store.add_indirect_peers(&clock.clock(), peer_infos.into_iter()).unwrap();
+    // A peer without an address isn't in the address index.
+    let no_addr = get_peer_info(get_peer_id("no_addr".to_string()), None);
+    peer_store.add_indirect_peers(&clock.clock(), std::iter::once(no_addr)).unwrap();
+
+    let estimate = peer_store.size_estimate();
+    assert_eq!(estimate.peer_count, 4);
+    let states_bytes: usize =
+        peer_store.iter().map(|(id, state)| store::Store::peer_state_size(id, state)).sum();
+    let addr_bytes = 3 * (std::mem::size_of::<SocketAddr>() + std::mem::size_of::<VerifiedPeer>());
+    assert_eq!(estimate.approx_bytes, states_bytes + addr_bytes);
+    // Each row holds at least the ed25519 key of the peer in both the key and the value.
+    assert!(estimate.approx_bytes > 4 * 2 * 32);
+    assert!(estimate.approx_bytes < 4 * 1024);
+}
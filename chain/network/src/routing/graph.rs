@@ -1,9 +1,43 @@
-use near_network_primitives::types::MAX_NUM_PEERS;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_network_primitives::time;
+use near_network_primitives::types::{Edge, MAX_NUM_PEERS};
 use near_primitives::network::PeerId;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cmp::Reverse;
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
 use tracing::warn;
 
+/// Peers with more neighbors than this get an index of their neighbors in addition to the
+/// adjacency list, so that checking whether an edge exists and removing it don't scan the list.
+const ADJACENCY_SET_THRESHOLD: usize = 64;
+
+/// Serializable copy of a `Graph`, used to replay the exact state of a graph in tests, e.g. to
+/// reproduce routing issues or to seed fuzzing. A `Graph` restored with `Graph::from_snapshot`
+/// computes the same routes as the original one.
+#[derive(BorshSerialize, Clone, Debug, PartialEq, Eq)]
+pub struct GraphSnapshot {
+    pub source: PeerId,
+    /// All edges, each once with endpoints ordered by `Edge::make_key`. Edges of `source` come
+    /// first, in the order of its adjacency list, which determines the order of next hops
+    /// (and which neighbors are used at all). The remaining edges are sorted.
+    pub edges: Vec<(PeerId, PeerId)>,
+    /// Ids of the peers other than `source`, sorted by id, if captured with
+    /// `Graph::to_snapshot_with_ids`. Otherwise ids are assigned from scratch on restore.
+    /// Added later, snapshots written by older versions are read as `None`.
+    pub ids: Option<Vec<(PeerId, u32)>>,
+}
+
+impl BorshDeserialize for GraphSnapshot {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let source = BorshDeserialize::deserialize(buf)?;
+        let edges = BorshDeserialize::deserialize(buf)?;
+        let ids = if buf.is_empty() { None } else { BorshDeserialize::deserialize(buf)? };
+        Ok(Self { source, edges, ids })
+    }
+}
+
 /// `Graph` is used to compute `peer_routing`, which contains information how to route messages to
 /// all known peers. That is, for each `peer`, we get a sub-set of peers to which we are connected
 /// to that are on the shortest path between us as destination `peer`.
@@ -25,6 +59,23 @@ pub struct Graph {
     /// Compressed adjacency table, we use 32 bit integer as ids instead of using full `PeerId`.
     /// This is undirected graph, we store edges in both directions.
     adjacency: Vec<Vec<u32>>,
+    /// Positions of the neighbors in `adjacency` of the ids with more than
+    /// `ADJACENCY_SET_THRESHOLD` neighbors. Indexes are dropped once the number of neighbors
+    /// falls to half the threshold. The lists are still kept, since the order of neighbors of
+    /// `source` determines routes.
+    adjacency_index: HashMap<u32, HashMap<u32, usize>>,
+    /// First peer which advertised each edge added with `add_edges_from`, keyed by
+    /// `Edge::make_key`. Entries are dropped together with their edges.
+    edge_sources: HashMap<(PeerId, PeerId), PeerId>,
+    /// Ids which are never released, even once the peer has no edges, so that critical
+    /// peers (e.g. our sentry nodes) keep a stable id. See `pin_peer`.
+    pinned: HashSet<u32>,
+    /// Edges which are kept, but not routed through, see `suppress_edge`. Ids are ordered.
+    suppressed: HashSet<(u32, u32)>,
+    /// When each edge added with `add_edge_at` was last added or refreshed, see
+    /// `calculate_distance_fresh`. Ids are ordered. Edges without a timestamp never go stale.
+    /// Only `calculate_distance_fresh` looks at the timestamps.
+    edge_timestamps: HashMap<(u32, u32), time::Instant>,
 
     /// Total number of edges used for stats.
     total_active_edges: u64,
@@ -40,6 +91,11 @@ impl Graph {
             used: Vec::default(),
             unused: Vec::default(),
             adjacency: Vec::default(),
+            adjacency_index: HashMap::default(),
+            edge_sources: HashMap::default(),
+            pinned: HashSet::default(),
+            suppressed: HashSet::default(),
+            edge_timestamps: HashMap::default(),
             total_active_edges: 0,
         };
         res.id2p.push(source.clone());
@@ -66,19 +122,295 @@ impl Graph {
         result / 2
     }
 
+    /// Estimates the betweenness centrality of every peer, i.e. the number of shortest paths
+    /// between other pairs of peers passing through it, where a pair with several shortest
+    /// paths contributes the fraction of them. Only the paths from `samples` randomly chosen
+    /// sources are counted, and the result is scaled up to the number of peers. With `samples`
+    /// at least the number of peers, the result is exact.
+    pub fn approx_betweenness(&self, samples: usize, rng: &mut impl Rng) -> HashMap<PeerId, f64> {
+        let nodes = self.id2p.len();
+        let used_ids: Vec<u32> = (0..nodes as u32).filter(|&id| self.used[id as usize]).collect();
+        let sources: Vec<u32> = used_ids.choose_multiple(rng, samples).copied().collect();
+
+        let mut centrality = vec![0f64; nodes];
+        let mut distance = vec![-1i32; nodes];
+        // Number of shortest paths from the source.
+        let mut paths = vec![0f64; nodes];
+        let mut predecessors: Vec<Vec<u32>> = vec![vec![]; nodes];
+        let mut dependency = vec![0f64; nodes];
+        let mut order = Vec::with_capacity(nodes);
+        let mut queue = VecDeque::new();
+        // Brandes' algorithm, restricted to the sampled sources.
+        for &source in &sources {
+            for &id in &order {
+                distance[id as usize] = -1;
+                paths[id as usize] = 0.;
+                predecessors[id as usize].clear();
+                dependency[id as usize] = 0.;
+            }
+            order.clear();
+
+            distance[source as usize] = 0;
+            paths[source as usize] = 1.;
+            queue.push_back(source);
+            while let Some(cur_peer) = queue.pop_front() {
+                order.push(cur_peer);
+                let cur_distance = distance[cur_peer as usize];
+                for &neighbor in &self.adjacency[cur_peer as usize] {
+                    if !self.is_usable(cur_peer, neighbor, None) {
+                        continue;
+                    }
+                    if distance[neighbor as usize] == -1 {
+                        distance[neighbor as usize] = cur_distance + 1;
+                        queue.push_back(neighbor);
+                    }
+                    if distance[neighbor as usize] == cur_distance + 1 {
+                        paths[neighbor as usize] += paths[cur_peer as usize];
+                        predecessors[neighbor as usize].push(cur_peer);
+                    }
+                }
+            }
+
+            for &id in order.iter().rev() {
+                for &predecessor in &predecessors[id as usize] {
+                    dependency[predecessor as usize] += paths[predecessor as usize]
+                        / paths[id as usize]
+                        * (1. + dependency[id as usize]);
+                }
+                if id != source {
+                    centrality[id as usize] += dependency[id as usize];
+                }
+            }
+        }
+
+        // Every pair is counted from both ends when all peers are sources.
+        let scale = used_ids.len() as f64 / sources.len().max(1) as f64 / 2.;
+        used_ids
+            .into_iter()
+            .map(|id| (self.id2p[id as usize].clone(), centrality[id as usize] * scale))
+            .collect()
+    }
+
+    /// Checks all the internal invariants of the graph and returns the first one which is
+    /// violated. Meant as an oracle for tests and fuzzing of sequences of graph mutations.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let nodes = self.id2p.len();
+        if self.used.len() != nodes || self.adjacency.len() != nodes {
+            return Err(format!(
+                "id2p, used and adjacency differ in length: {}, {}, {}",
+                nodes,
+                self.used.len(),
+                self.adjacency.len()
+            ));
+        }
+        if self.p2id.get(&self.my_peer_id) != Some(&self.source_id) {
+            return Err(format!("{} is not mapped to the source id", self.my_peer_id));
+        }
+
+        // `p2id` and `id2p` are inverse of each other over the used ids.
+        for (peer, &id) in &self.p2id {
+            if !self.used.get(id as usize).copied().unwrap_or(false) {
+                return Err(format!("{} is mapped to id {} which is not used", peer, id));
+            }
+            if &self.id2p[id as usize] != peer {
+                return Err(format!(
+                    "{} is mapped to id {} of {}",
+                    peer, id, self.id2p[id as usize]
+                ));
+            }
+        }
+        let used_count = self.used.iter().filter(|&&used| used).count();
+        if self.p2id.len() != used_count {
+            return Err(format!("{} peers are mapped to {} used ids", self.p2id.len(), used_count));
+        }
+
+        // `unused` holds exactly the ids which are not used, each once.
+        let mut unused = vec![false; nodes];
+        for &id in &self.unused {
+            match unused.get_mut(id as usize) {
+                None => return Err(format!("unused id {} is out of range", id)),
+                Some(true) => return Err(format!("id {} is unused twice", id)),
+                Some(seen) => *seen = true,
+            }
+            if self.used[id as usize] {
+                return Err(format!("id {} is both used and unused", id));
+            }
+        }
+        if self.unused.len() + used_count != nodes {
+            return Err(format!(
+                "{} ids are neither used nor unused",
+                nodes - self.unused.len() - used_count
+            ));
+        }
+
+        // The adjacency is symmetric, without loops or duplicates, and only between used ids.
+        let mut total_neighbors = 0;
+        for (id, neighbors) in self.adjacency.iter().enumerate() {
+            if !self.used[id] {
+                if !neighbors.is_empty() {
+                    return Err(format!("unused id {} has neighbors", id));
+                }
+                continue;
+            }
+            if neighbors.is_empty()
+                && id as u32 != self.source_id
+                && !self.pinned.contains(&(id as u32))
+            {
+                return Err(format!("{} has no neighbors, but is still used", self.id2p[id]));
+            }
+            let set: HashSet<u32> = neighbors.iter().copied().collect();
+            if set.len() != neighbors.len() {
+                return Err(format!("{} has duplicated neighbors", self.id2p[id]));
+            }
+            for &neighbor in neighbors {
+                if neighbor as usize == id {
+                    return Err(format!("{} is its own neighbor", self.id2p[id]));
+                }
+                if !self.used.get(neighbor as usize).copied().unwrap_or(false) {
+                    return Err(format!("{} has unused neighbor id {}", self.id2p[id], neighbor));
+                }
+                if !self.adjacency[neighbor as usize].contains(&(id as u32)) {
+                    return Err(format!(
+                        "edge {} - {} is only stored in one direction",
+                        self.id2p[id], self.id2p[neighbor as usize]
+                    ));
+                }
+            }
+            match self.adjacency_index.get(&(id as u32)) {
+                Some(index)
+                    if index.len() != neighbors.len()
+                        || neighbors
+                            .iter()
+                            .enumerate()
+                            .any(|(pos, neighbor)| index.get(neighbor) != Some(&pos)) =>
+                {
+                    return Err(format!(
+                        "neighbor index of {} differs from its list",
+                        self.id2p[id]
+                    ));
+                }
+                None if neighbors.len() > ADJACENCY_SET_THRESHOLD => {
+                    return Err(format!("{} has no neighbor index", self.id2p[id]));
+                }
+                _ => {}
+            }
+            total_neighbors += neighbors.len() as u64;
+        }
+        if let Some(id) = self.pinned.iter().find(|&&id| !self.used[id as usize]) {
+            return Err(format!("unused id {} is pinned", id));
+        }
+        if let Some(id) = self.adjacency_index.keys().find(|&&id| !self.used[id as usize]) {
+            return Err(format!("unused id {} has a neighbor index", id));
+        }
+        if total_neighbors != 2 * self.total_active_edges {
+            return Err(format!(
+                "total_active_edges is {}, but there are {} edges",
+                self.total_active_edges,
+                total_neighbors / 2
+            ));
+        }
+        if let Some((peer0, peer1)) =
+            self.edge_sources.keys().find(|(peer0, peer1)| !self.contains_edge(peer0, peer1))
+        {
+            return Err(format!("edge {} - {} has a source but doesn't exist", peer0, peer1));
+        }
+        for &(id0, id1) in &self.suppressed {
+            if id0 >= id1
+                || !self.used.get(id1 as usize).copied().unwrap_or(false)
+                || !self.used[id0 as usize]
+                || !self.has_neighbor(id0, id1)
+            {
+                return Err(format!("suppressed edge {} - {} doesn't exist", id0, id1));
+            }
+        }
+        for &(id0, id1) in self.edge_timestamps.keys() {
+            if id0 >= id1
+                || !self.used.get(id1 as usize).copied().unwrap_or(false)
+                || !self.used[id0 as usize]
+                || !self.has_neighbor(id0, id1)
+            {
+                return Err(format!("timestamped edge {} - {} doesn't exist", id0, id1));
+            }
+        }
+        Ok(())
+    }
+
     fn contains_edge(&self, peer0: &PeerId, peer1: &PeerId) -> bool {
         if let Some(&id0) = self.p2id.get(peer0) {
             if let Some(&id1) = self.p2id.get(peer1) {
-                return self.adjacency[id0 as usize].contains(&id1);
+                return self.has_neighbor(id0, id1);
             }
         }
         false
     }
 
+    /// Checks whether there is an edge between `id0` and `id1`. Scans the shorter adjacency
+    /// list, unless it's long enough to have an index.
+    fn has_neighbor(&self, id0: u32, id1: u32) -> bool {
+        let (id0, id1) = if self.adjacency[id0 as usize].len() <= self.adjacency[id1 as usize].len()
+        {
+            (id0, id1)
+        } else {
+            (id1, id0)
+        };
+        match self.adjacency_index.get(&id0) {
+            Some(index) => index.contains_key(&id1),
+            None => self.adjacency[id0 as usize].contains(&id1),
+        }
+    }
+
+    fn push_neighbor(&mut self, id: u32, neighbor: u32) {
+        let neighbors = &mut self.adjacency[id as usize];
+        neighbors.push(neighbor);
+        match self.adjacency_index.entry(id) {
+            Entry::Occupied(mut index) => {
+                index.get_mut().insert(neighbor, neighbors.len() - 1);
+            }
+            Entry::Vacant(index) => {
+                if neighbors.len() > ADJACENCY_SET_THRESHOLD {
+                    index.insert(neighbors.iter().enumerate().map(|(pos, &n)| (n, pos)).collect());
+                }
+            }
+        }
+    }
+
+    /// Removes `neighbor` from the list of `id`. With an index, the last neighbor takes the
+    /// place of the removed one, which is O(1). The exception is `source`, whose neighbors
+    /// keep their order, since it determines the route bits (see `compute_routes`). Short
+    /// lists without an index keep their order too.
+    fn remove_neighbor(&mut self, id: u32, neighbor: u32) {
+        let neighbors = &mut self.adjacency[id as usize];
+        let index = match self.adjacency_index.get_mut(&id) {
+            Some(index) => index,
+            None => {
+                neighbors.retain(|&x| x != neighbor);
+                return;
+            }
+        };
+        let pos = match index.remove(&neighbor) {
+            Some(pos) => pos,
+            None => return,
+        };
+        if id == self.source_id {
+            neighbors.remove(pos);
+            for moved in &neighbors[pos..] {
+                *index.get_mut(moved).unwrap() -= 1;
+            }
+        } else {
+            neighbors.swap_remove(pos);
+            if let Some(&moved) = neighbors.get(pos) {
+                index.insert(moved, pos);
+            }
+        }
+        if neighbors.len() <= ADJACENCY_SET_THRESHOLD / 2 {
+            self.adjacency_index.remove(&id);
+        }
+    }
+
     fn remove_if_unused(&mut self, id: u32) {
         let entry = &self.adjacency[id as usize];
 
-        if entry.is_empty() && id != self.source_id {
+        if entry.is_empty() && id != self.source_id && !self.pinned.contains(&id) {
             self.used[id as usize] = false;
             self.unused.push(id);
             self.p2id.remove(&self.id2p[id as usize]);
@@ -115,33 +447,476 @@ impl Graph {
             let id0 = self.get_id(peer0);
             let id1 = self.get_id(peer1);
 
-            self.adjacency[id0 as usize].push(id1);
-            self.adjacency[id1 as usize].push(id0);
+            self.push_neighbor(id0, id1);
+            self.push_neighbor(id1, id0);
 
             self.total_active_edges += 1;
         }
     }
 
+    /// Same as `add_edge`, but also records `now` as the time the edge was last seen, for
+    /// `calculate_distance_fresh`. Adding an existing edge again refreshes its timestamp.
+    pub fn add_edge_at(&mut self, peer0: &PeerId, peer1: &PeerId, now: time::Instant) {
+        self.add_edge(peer0, peer1);
+        let id0 = self.p2id[peer0];
+        let id1 = self.p2id[peer1];
+        self.edge_timestamps.insert((id0.min(id1), id0.max(id1)), now);
+    }
+
     pub fn remove_edge(&mut self, peer0: &PeerId, peer1: &PeerId) {
         assert_ne!(peer0, peer1);
         if self.contains_edge(peer0, peer1) {
             let id0 = self.get_id(peer0);
             let id1 = self.get_id(peer1);
 
-            self.adjacency[id0 as usize].retain(|&x| x != id1);
-            self.adjacency[id1 as usize].retain(|&x| x != id0);
+            self.remove_neighbor(id0, id1);
+            self.remove_neighbor(id1, id0);
 
             self.remove_if_unused(id0);
             self.remove_if_unused(id1);
 
             self.total_active_edges -= 1;
+            self.edge_sources.remove(&Edge::make_key(peer0.clone(), peer1.clone()));
+            self.suppressed.remove(&(id0.min(id1), id0.max(id1)));
+            self.edge_timestamps.remove(&(id0.min(id1), id0.max(id1)));
+        }
+    }
+
+    /// Stops routing through the edge between `peer0` and `peer1` in `calculate_distance`, its
+    /// variants and the other traversals of the graph, without removing the edge, e.g. to stop using a suspicious neighbor
+    /// without advertising a removal. Does nothing if there is no such edge. The suppression
+    /// ends with `unsuppress_edge` or once the edge is removed.
+    pub fn suppress_edge(&mut self, peer0: &PeerId, peer1: &PeerId) {
+        if let (Some(&id0), Some(&id1)) = (self.p2id.get(peer0), self.p2id.get(peer1)) {
+            if id0 != id1 && self.has_neighbor(id0, id1) {
+                self.suppressed.insert((id0.min(id1), id0.max(id1)));
+            }
+        }
+    }
+
+    /// Reverts `suppress_edge`.
+    pub fn unsuppress_edge(&mut self, peer0: &PeerId, peer1: &PeerId) {
+        if let (Some(&id0), Some(&id1)) = (self.p2id.get(peer0), self.p2id.get(peer1)) {
+            self.suppressed.remove(&(id0.min(id1), id0.max(id1)));
+        }
+    }
+
+    fn is_suppressed(&self, id0: u32, id1: u32) -> bool {
+        !self.suppressed.is_empty() && self.suppressed.contains(&(id0.min(id1), id0.max(id1)))
+    }
+
+    /// Keeps the id of `peer` allocated, even while it has no edges, until `unpin_peer` is
+    /// called. Allocates an id if the peer isn't in the graph yet.
+    pub fn pin_peer(&mut self, peer: &PeerId) {
+        let id = self.get_id(peer);
+        self.pinned.insert(id);
+    }
+
+    /// Reverts `pin_peer`. The id of `peer` is released right away if it has no edges.
+    pub fn unpin_peer(&mut self, peer: &PeerId) {
+        if let Some(&id) = self.p2id.get(peer) {
+            if self.pinned.remove(&id) {
+                self.remove_if_unused(id);
+            }
+        }
+    }
+
+    /// Adds edges advertised by `source_peer`, remembering it as the source of every edge
+    /// which doesn't have one yet, so that the peer can be held accountable if the edge
+    /// turns out to be bogus. See `edge_source`.
+    pub fn add_edges_from(&mut self, source_peer: &PeerId, edges: &[(PeerId, PeerId)]) {
+        for (peer0, peer1) in edges {
+            self.add_edge(peer0, peer1);
+            self.edge_sources
+                .entry(Edge::make_key(peer0.clone(), peer1.clone()))
+                .or_insert_with(|| source_peer.clone());
+        }
+    }
+
+    /// First peer which advertised `edge` through `add_edges_from`. `None` if the edge
+    /// doesn't exist or was only added with `add_edge`.
+    pub fn edge_source(&self, edge: &(PeerId, PeerId)) -> Option<&PeerId> {
+        self.edge_sources.get(&Edge::make_key(edge.0.clone(), edge.1.clone()))
+    }
+
+    /// Peers adjacent to both `peer0` and `peer1`, sorted. Empty if either peer is unknown.
+    pub fn common_neighbors(&self, peer0: &PeerId, peer1: &PeerId) -> Vec<PeerId> {
+        let (id0, id1) = match (self.p2id.get(peer0), self.p2id.get(peer1)) {
+            (Some(&id0), Some(&id1)) => (id0, id1),
+            _ => return vec![],
+        };
+        let mut result: Vec<PeerId> =
+            self.common_neighbor_ids(id0, id1).map(|id| self.id2p[id as usize].clone()).collect();
+        result.sort();
+        result
+    }
+
+    /// Intersection of the adjacency lists of `id0` and `id1`, in the order of the longer one.
+    fn common_neighbor_ids(&self, id0: u32, id1: u32) -> impl Iterator<Item = u32> + '_ {
+        let (shorter, longer) =
+            if self.adjacency[id0 as usize].len() <= self.adjacency[id1 as usize].len() {
+                (&self.adjacency[id0 as usize], &self.adjacency[id1 as usize])
+            } else {
+                (&self.adjacency[id1 as usize], &self.adjacency[id0 as usize])
+            };
+        let mut shorter = shorter.clone();
+        shorter.sort_unstable();
+        longer.iter().copied().filter(move |id| shorter.binary_search(id).is_ok())
+    }
+
+    /// Average local clustering coefficient: for each peer with at least 2 neighbors, the
+    /// number of edges among its neighbors divided by the number of possible such edges.
+    /// 1.0 for a fully meshed graph and 0.0 for a tree. Returns 0.0 if no peer has 2 neighbors.
+    pub fn clustering_coefficient(&self) -> f64 {
+        let mut total = 0.;
+        let mut nodes = 0;
+        for (id, neighbors) in self.adjacency.iter().enumerate() {
+            let degree = neighbors.len();
+            if !self.used[id] || degree < 2 {
+                continue;
+            }
+            // Every edge among the neighbors is counted from both of its ends.
+            let links: usize = neighbors
+                .iter()
+                .map(|&neighbor| self.common_neighbor_ids(id as u32, neighbor).count())
+                .sum();
+            total += (links / 2) as f64 / (degree * (degree - 1) / 2) as f64;
+            nodes += 1;
+        }
+        if nodes == 0 {
+            0.
+        } else {
+            total / nodes as f64
+        }
+    }
+
+    /// List of all edges in the graph, each reported once with its endpoints ordered by
+    /// `Edge::make_key`. The result is sorted.
+    pub fn edges(&self) -> Vec<(PeerId, PeerId)> {
+        let mut result = Vec::with_capacity(self.total_active_edges as usize);
+        for (id0, neighbors) in self.adjacency.iter().enumerate() {
+            if !self.used[id0] {
+                continue;
+            }
+            for &id1 in neighbors {
+                if (id0 as u32) < id1 {
+                    result.push(Edge::make_key(
+                        self.id2p[id0].clone(),
+                        self.id2p[id1 as usize].clone(),
+                    ));
+                }
+            }
+        }
+        result.sort();
+        result
+    }
+
+    /// Captures the graph, so that it can be restored with `from_snapshot`.
+    pub fn to_snapshot(&self) -> GraphSnapshot {
+        let mut edges: Vec<_> = self.adjacency[self.source_id as usize]
+            .iter()
+            .map(|&id| Edge::make_key(self.my_peer_id.clone(), self.id2p[id as usize].clone()))
+            .collect();
+        edges.extend(
+            self.edges()
+                .into_iter()
+                .filter(|(peer0, peer1)| *peer0 != self.my_peer_id && *peer1 != self.my_peer_id),
+        );
+        GraphSnapshot { source: self.my_peer_id.clone(), edges, ids: None }
+    }
+
+    /// Same as `to_snapshot`, but also captures the id of every peer, so that peers keep their
+    /// ids once restored, e.g. to keep caches or metrics keyed by id valid across restarts.
+    pub fn to_snapshot_with_ids(&self) -> GraphSnapshot {
+        let mut ids: Vec<_> = (self.p2id.iter())
+            .filter(|(_, &id)| id != self.source_id)
+            .map(|(peer, &id)| (peer.clone(), id))
+            .collect();
+        ids.sort_by_key(|(_, id)| *id);
+        GraphSnapshot { ids: Some(ids), ..self.to_snapshot() }
+    }
+
+    /// Restores a graph captured with `to_snapshot` or `to_snapshot_with_ids`. Peers without
+    /// edges don't keep their ids, and peers added later get ids the usual way.
+    pub fn from_snapshot(snapshot: GraphSnapshot) -> Graph {
+        let mut graph = Graph::new(snapshot.source);
+        if let Some(ids) = &snapshot.ids {
+            // Every peer other than `source` is listed in `ids` or is an endpoint of an edge,
+            // so larger ids can only come from a corrupted snapshot.
+            graph.assign_ids(ids, (ids.len() + snapshot.edges.len()) as u32);
+        }
+        for (peer0, peer1) in &snapshot.edges {
+            graph.add_edge(peer0, peer1);
         }
+        for id in 0..graph.used.len() as u32 {
+            if graph.used[id as usize] {
+                graph.remove_if_unused(id);
+            }
+        }
+        graph
+    }
+
+    /// Allocates the given ids to the peers in a new graph, before any edges are added. Ids
+    /// which are out of order with the graph, i.e. of the source or already allocated, are
+    /// skipped, as well as ids above `max_id`, so that a corrupted snapshot can't make us
+    /// allocate huge tables. The ids in between are marked as unused, lowest to be reused first.
+    fn assign_ids(&mut self, ids: &[(PeerId, u32)], max_id: u32) {
+        for (peer, id) in ids {
+            let index = *id as usize;
+            if *id > max_id {
+                warn!(target: "network", ?peer, id = *id, max_id, "Too large id in snapshot");
+                continue;
+            }
+            if *id == self.source_id || self.p2id.contains_key(peer) {
+                continue;
+            }
+            if index >= self.id2p.len() {
+                self.id2p.resize(index + 1, self.my_peer_id.clone());
+                self.used.resize(index + 1, false);
+                self.adjacency.resize(index + 1, Vec::default());
+            } else if self.used[index] {
+                continue;
+            }
+            self.id2p[index] = peer.clone();
+            self.used[index] = true;
+            self.p2id.insert(peer.clone(), *id);
+        }
+        self.unused =
+            (0..self.used.len() as u32).rev().filter(|&id| !self.used[id as usize]).collect();
+    }
+
+    /// Copy of the graph restricted to `source` and the peers reachable from it (see
+    /// `calculate_distance`), with ids assigned compactly. The edges of `source` are added in
+    /// the order of its adjacency list, so the copy computes the same routes as the original.
+    /// Edge sources, pinned peers and suppressed edges are not copied.
+    pub fn reachable_subgraph(&self) -> Graph {
+        let distance = self.distances();
+        let reachable = |peer: &PeerId| distance[self.p2id[peer] as usize] >= 0;
+        let mut snapshot = self.to_snapshot();
+        snapshot.edges.retain(|(peer0, peer1)| reachable(peer0) && reachable(peer1));
+        Graph::from_snapshot(snapshot)
     }
 
     /// Compute for every node `u` on the graph (other than `source`) which are the neighbors of
     /// `sources` which belong to the shortest path from `source` to `u`. Nodes that are
     /// not connected to `source` will not appear in the result.
     pub fn calculate_distance(&self) -> HashMap<PeerId, Vec<PeerId>> {
+        let (routes, distance) = self.compute_routes(self.source_id);
+        // This takes 75% of the total time computation time of this function.
+        self.compute_result(self.source_id, &routes, &distance)
+    }
+
+    /// Same as `calculate_distance`, but the BFS stops after visiting `max_nodes` peers, to
+    /// bound the time spent on huge graphs. Since peers are visited in the order of their
+    /// distance, the result contains the nearest peers, each with all its next hops. Also
+    /// returns whether the result was truncated.
+    pub fn calculate_distance_bounded(
+        &self,
+        max_nodes: usize,
+    ) -> (HashMap<PeerId, Vec<PeerId>>, bool) {
+        let (routes, distance, truncated) = self.compute_routes_bounded(self.source_id, max_nodes);
+        (self.compute_result(self.source_id, &routes, &distance), truncated)
+    }
+
+    /// Same as `calculate_distance`, but ignores edges added with `add_edge_at` which weren't
+    /// refreshed within `max_age` before `now`, so that reachability reflects the recent
+    /// topology. Edges added with `add_edge` are always considered fresh.
+    ///
+    /// This is the only freshness aware query: all other traversals, e.g. `reachable_count`,
+    /// `is_reachable` or `spanning_tree`, treat stale edges as usable, so their results match
+    /// `calculate_distance` rather than this function.
+    pub fn calculate_distance_fresh(
+        &self,
+        max_age: time::Duration,
+        now: time::Instant,
+    ) -> HashMap<PeerId, Vec<PeerId>> {
+        let (routes, distance, _) =
+            self.compute_routes_filtered(self.source_id, usize::MAX, Some((max_age, now)));
+        self.compute_result(self.source_id, &routes, &distance)
+    }
+
+    /// Same as `calculate_distance`, but also returns how long the computation took according
+    /// to `clock`, so that callers can recompute less often when the graph gets expensive.
+    pub fn calculate_distance_timed(
+        &self,
+        clock: &time::Clock,
+    ) -> (HashMap<PeerId, Vec<PeerId>>, time::Duration) {
+        let start = clock.now();
+        let result = self.calculate_distance();
+        (result, clock.now() - start)
+    }
+
+    /// Same as `calculate_distance`, but computed as if `source` was the current peer, e.g. to
+    /// simulate the routing table of another peer. Returns an empty result if `source` is not
+    /// known.
+    pub fn calculate_distance_from(&self, source: &PeerId) -> HashMap<PeerId, Vec<PeerId>> {
+        let source_id = match self.p2id.get(source) {
+            Some(&id) => id,
+            None => return HashMap::new(),
+        };
+        let (routes, distance) = self.compute_routes(source_id);
+        self.compute_result(source_id, &routes, &distance)
+    }
+
+    /// Same as `calculate_distance`, but only the entries for `targets` are returned. The BFS
+    /// still covers the whole graph, but `PeerId`s are cloned only for the requested targets.
+    /// Targets which are unknown, unreachable or `source` itself are not in the result.
+    pub fn forwarding_for(&self, targets: &[PeerId]) -> HashMap<PeerId, Vec<PeerId>> {
+        let (routes, distance) = self.compute_routes(self.source_id);
+        debug_assert_eq!(Ok(()), self.validate_routes(self.source_id, &routes, &distance));
+        targets
+            .iter()
+            .filter_map(|target| {
+                let id = *self.p2id.get(target)? as usize;
+                if id as u32 == self.source_id || distance[id] == -1 || routes[id] == 0 {
+                    return None;
+                }
+                let next_hops = self.route_to_next_hops(self.source_id, routes[id]);
+                Some((target.clone(), next_hops.map(|id| self.id2p[id as usize].clone()).collect()))
+            })
+            .collect()
+    }
+
+    /// Same as `calculate_distance`, but peers are identified by their internal ids, which can
+    /// be resolved with `id_to_peer`. Avoids cloning a `PeerId` for every reachable peer, when
+    /// the caller needs only some of them. Ids stay valid as long as no edges are added or
+    /// removed.
+    pub fn calculate_distance_ids(&self) -> HashMap<u32, Vec<u32>> {
+        let (routes, distance) = self.compute_routes(self.source_id);
+        self.compute_result_ids(self.source_id, &routes, &distance)
+    }
+
+    /// Resolves an id returned by `calculate_distance_ids`.
+    pub fn id_to_peer(&self, id: u32) -> Option<&PeerId> {
+        match self.used.get(id as usize) {
+            Some(true) => Some(&self.id2p[id as usize]),
+            _ => None,
+        }
+    }
+
+    /// Like `calculate_distance`, but paths are chosen by their total cost rather than the
+    /// number of hops. `weights[(a, b)]` is the cost of the hop from `a` to `b`, so the cost of
+    /// a path is taken in the direction from `source` towards the destination, and asymmetric
+    /// links can be described by giving different costs to `(a, b)` and `(b, a)`. For symmetric
+    /// costs both directions have to be present. The topology is still the undirected adjacency,
+    /// weights of pairs which are not connected are ignored.
+    ///
+    /// Hops missing from `weights` cost 1, and so do hops with weight 0. With an empty `weights`
+    /// the result is the same as of `calculate_distance`, which is unaffected by this mode.
+    pub fn calculate_distance_weighted(
+        &self,
+        weights: &HashMap<(PeerId, PeerId), u32>,
+    ) -> HashMap<PeerId, Vec<PeerId>> {
+        let weights: HashMap<(u32, u32), u64> = weights
+            .iter()
+            .filter_map(|((from, to), &weight)| {
+                Some(((*self.p2id.get(from)?, *self.p2id.get(to)?), weight.max(1) as u64))
+            })
+            .collect();
+        let weight = |from: u32, to: u32| weights.get(&(from, to)).copied().unwrap_or(1);
+
+        let nodes = self.id2p.len();
+        let mut cost: Vec<Option<u64>> = vec![None; nodes];
+        let mut routes: Vec<u128> = vec![0; nodes];
+        let mut done = vec![false; nodes];
+        let mut queue = BinaryHeap::new();
+
+        cost[self.source_id as usize] = Some(0);
+        done[self.source_id as usize] = true;
+        // Mirror `calculate_distance`, which only routes through the first `MAX_NUM_PEERS`
+        // neighbors of `source`.
+        let neighbors = &self.adjacency[self.source_id as usize];
+        for (id, &neighbor) in neighbors.iter().enumerate().take(MAX_NUM_PEERS) {
+            if !self.is_usable(self.source_id, neighbor, None) {
+                continue;
+            }
+            let neighbor_cost = weight(self.source_id, neighbor);
+            cost[neighbor as usize] = Some(neighbor_cost);
+            routes[neighbor as usize] = 1u128 << id;
+            queue.push(Reverse((neighbor_cost, neighbor)));
+        }
+
+        // Dijkstra. All weights are positive, so once a peer is popped, the routes of all its
+        // predecessors on the cheapest paths are already final.
+        while let Some(Reverse((cur_cost, cur_peer))) = queue.pop() {
+            if done[cur_peer as usize] {
+                continue;
+            }
+            done[cur_peer as usize] = true;
+            for &neighbor in &self.adjacency[cur_peer as usize] {
+                if done[neighbor as usize] || !self.is_usable(cur_peer, neighbor, None) {
+                    continue;
+                }
+                let new_cost = cur_cost + weight(cur_peer, neighbor);
+                match cost[neighbor as usize] {
+                    Some(old_cost) if old_cost < new_cost => {}
+                    Some(old_cost) if old_cost == new_cost => {
+                        routes[neighbor as usize] |= routes[cur_peer as usize];
+                    }
+                    _ => {
+                        cost[neighbor as usize] = Some(new_cost);
+                        routes[neighbor as usize] = routes[cur_peer as usize];
+                        queue.push(Reverse((new_cost, neighbor)));
+                    }
+                }
+            }
+        }
+
+        let distance: Vec<i32> = cost
+            .iter()
+            .map(|cost| match cost {
+                Some(cost) => i32::try_from(*cost).unwrap_or(i32::MAX),
+                None => -1,
+            })
+            .collect();
+        self.compute_result(self.source_id, &routes, &distance)
+    }
+
+    /// Runs the BFS from `source`. For each id returns the bitmask of neighbors of `source`
+    /// which are on the shortest paths to it and its distance from `source` (-1 if the id
+    /// is unreachable).
+    fn compute_routes(&self, source: u32) -> (Vec<u128>, Vec<i32>) {
+        let (routes, distance, _) = self.compute_routes_bounded(source, usize::MAX);
+        (routes, distance)
+    }
+
+    /// Same as `compute_routes`, but the BFS stops after visiting `max_nodes` nodes, not
+    /// counting `source`. Nodes which were discovered but not visited are reported as
+    /// unreachable, since not all their routes are known yet. Also returns whether the BFS was
+    /// stopped before visiting all reachable nodes.
+    fn compute_routes_bounded(&self, source: u32, max_nodes: usize) -> (Vec<u128>, Vec<i32>, bool) {
+        self.compute_routes_filtered(source, max_nodes, None)
+    }
+
+    /// Whether traversals may use the edge between `id0` and `id1`. If `freshness` is set to
+    /// `(max_age, now)`, timestamped edges older than `max_age` are skipped. Only
+    /// `calculate_distance_fresh` passes a `freshness`, see there.
+    fn is_usable(
+        &self,
+        id0: u32,
+        id1: u32,
+        freshness: Option<(time::Duration, time::Instant)>,
+    ) -> bool {
+        if self.is_suppressed(id0, id1) {
+            return false;
+        }
+        match freshness {
+            Some((max_age, now)) => self
+                .edge_timestamps
+                .get(&(id0.min(id1), id0.max(id1)))
+                .map_or(true, |&added| now - added <= max_age),
+            None => true,
+        }
+    }
+
+    /// Same as `compute_routes_bounded`, but only edges accepted by `is_usable` with the given
+    /// `freshness` are traversed.
+    fn compute_routes_filtered(
+        &self,
+        source: u32,
+        max_nodes: usize,
+        freshness: Option<(time::Duration, time::Instant)>,
+    ) -> (Vec<u128>, Vec<i32>, bool) {
         // TODO add removal of unreachable nodes
 
         let mut queue = VecDeque::new();
@@ -150,21 +925,33 @@ impl Graph {
         let mut distance: Vec<i32> = vec![-1; nodes];
         let mut routes: Vec<u128> = vec![0; nodes];
 
-        distance[self.source_id as usize] = 0;
+        distance[source as usize] = 0;
 
         {
-            let neighbors = &self.adjacency[self.source_id as usize];
+            let neighbors = &self.adjacency[source as usize];
             for (id, &neighbor) in neighbors.iter().enumerate().take(MAX_NUM_PEERS) {
+                if !self.is_usable(source, neighbor, freshness) {
+                    continue;
+                }
                 queue.push_back(neighbor);
                 distance[neighbor as usize] = 1;
                 routes[neighbor as usize] = 1u128 << id;
             }
         }
 
+        let mut visited = 0;
         while let Some(cur_peer) = queue.pop_front() {
+            if visited == max_nodes {
+                queue.push_front(cur_peer);
+                break;
+            }
+            visited += 1;
             let cur_distance = distance[cur_peer as usize];
 
             for &neighbor in &self.adjacency[cur_peer as usize] {
+                if !self.is_usable(cur_peer, neighbor, freshness) {
+                    continue;
+                }
                 if distance[neighbor as usize] == -1 {
                     distance[neighbor as usize] = cur_distance + 1;
                     queue.push_back(neighbor);
@@ -177,78 +964,507 @@ impl Graph {
             }
         }
 
-        // This takes 75% of the total time computation time of this function.
-        self.compute_result(&routes, &distance)
+        let truncated = !queue.is_empty();
+        for id in queue {
+            distance[id as usize] = -1;
+            routes[id as usize] = 0;
+        }
+        (routes, distance, truncated)
     }
 
-    /// Converts representation of the result, from an array representation, to
-    /// a hashmap of PeerId -> Vec<PeerIds>
-    /// Arguments:
-    ///   - routes - for node given node at index `i`, give list of connected peers, which
-    ///     are on the optimal path
-    ///   - distances - not really needed: TODO remove this argument
-    fn compute_result(&self, routes: &[u128], distance: &[i32]) -> HashMap<PeerId, Vec<PeerId>> {
-        let mut res = HashMap::with_capacity(routes.len());
-
-        let neighbors = &self.adjacency[self.source_id as usize];
-        let mut unreachable_nodes = 0;
-
-        for (key, &cur_route) in routes.iter().enumerate() {
-            if distance[key] == -1 && self.used[key] {
-                unreachable_nodes += 1;
-            }
-            if key as u32 == self.source_id
-                || distance[key] == -1
-                || cur_route == 0u128
-                || !self.used[key]
-            {
+    /// Checks that `routes` and `distance`, as computed by the BFS, agree with each other:
+    /// every reachable id other than `source` must have a non-zero route mask, and every
+    /// id with a non-zero route mask must be reachable.
+    pub(crate) fn validate_routes(
+        &self,
+        source: u32,
+        routes: &[u128],
+        distance: &[i32],
+    ) -> Result<(), String> {
+        if routes.len() != distance.len() {
+            return Err(format!(
+                "routes and distance differ in length: {} != {}",
+                routes.len(),
+                distance.len()
+            ));
+        }
+        for (id, (&route, &dist)) in routes.iter().zip(distance).enumerate() {
+            if id as u32 == source {
                 continue;
             }
-            // We convert list of peers, which are represented as bits
-            // to a list of Vec<PeerId>
-            // This is a bit wasteful representation, but that's ok.
-            let peer_set = neighbors
-                .iter()
-                .enumerate()
-                .take(MAX_NUM_PEERS)
-                .filter(|(id, _)| (cur_route & (1u128 << id)) != 0)
-                .map(|(_, &neighbor)| self.id2p[neighbor as usize].clone())
-                .collect();
-            res.insert(self.id2p[key].clone(), peer_set);
-        }
-        if unreachable_nodes > 1000 {
-            warn!("We store more than 1000 unreachable nodes: {}", unreachable_nodes);
+            if dist >= 0 && route == 0 {
+                return Err(format!("{} at distance {} has no route", self.id2p[id], dist));
+            }
+            if dist < 0 && route != 0 {
+                return Err(format!("{} is unreachable, but has a route", self.id2p[id]));
+            }
         }
-        res
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod test {
-    use crate::routing::graph::Graph;
-    use crate::test_utils::{expected_routing_tables, random_peer_id};
-    use std::ops::Not;
 
-    #[test]
-    fn graph_contains_edge() {
-        let source = random_peer_id();
+    /// Returns the peers which are only routed through `neighbor`, i.e. all their shortest
+    /// paths start with `neighbor`. These are the peers we'd lose routes to if the connection
+    /// to `neighbor` went down, until a longer path is found. `neighbor` itself is not
+    /// included. Returns an empty list if `neighbor` is not a routable neighbor of `source`.
+    /// The result is sorted.
+    pub fn destinations_dependent_on(&self, neighbor: &PeerId) -> Vec<PeerId> {
+        let neighbor_id = match self.p2id.get(neighbor) {
+            Some(&id) => id,
+            None => return vec![],
+        };
+        let bit = match self.adjacency[self.source_id as usize]
+            .iter()
+            .take(MAX_NUM_PEERS)
+            .position(|&id| id == neighbor_id)
+        {
+            Some(position) => 1u128 << position,
+            None => return vec![],
+        };
+        let (routes, _) = self.compute_routes(self.source_id);
+        let mut result: Vec<_> = routes
+            .iter()
+            .enumerate()
+            .filter(|&(id, &route)| route == bit && self.used[id] && id as u32 != neighbor_id)
+            .map(|(id, _)| self.id2p[id].clone())
+            .collect();
+        result.sort();
+        result
+    }
 
-        let node0 = random_peer_id();
-        let node1 = random_peer_id();
+    /// Returns the neighbors of `source` which aren't the first hop of any shortest path to
+    /// another peer, i.e. whose route bit is set for no destination but themselves. Dropping
+    /// the connection to one of them only changes the route to that neighbor. Neighbors past
+    /// the first `MAX_NUM_PEERS`, which are never used for routing, are included as well.
+    /// The result is sorted.
+    pub fn non_essential_source_edges(&self) -> Vec<PeerId> {
+        let (routes, distance) = self.compute_routes(self.source_id);
+        // Neighbors are at distance 1 and routed only through themselves, so the bits used
+        // for other peers are the ones set for peers further away.
+        let used_bits = routes
+            .iter()
+            .zip(&distance)
+            .filter(|&(_, &dist)| dist > 1)
+            .fold(0u128, |acc, (&route, _)| acc | route);
+        let mut result: Vec<_> = self.adjacency[self.source_id as usize]
+            .iter()
+            .enumerate()
+            .filter(|&(position, _)| {
+                position >= MAX_NUM_PEERS || used_bits & (1u128 << position) == 0
+            })
+            .map(|(_, &id)| self.id2p[id as usize].clone())
+            .collect();
+        result.sort();
+        result
+    }
 
-        let mut graph = Graph::new(source.clone());
+    /// Whether there are two node-disjoint paths from `source` to `target`, i.e. whether
+    /// `target` stays reachable if any single other peer goes down. This is the case iff both
+    /// are in the same biconnected component, and that component isn't just a bridge between
+    /// them. Returns false for `source` itself and for unknown peers.
+    pub fn is_two_connected_to(&self, target: &PeerId) -> bool {
+        let target = match self.p2id.get(target) {
+            Some(&id) if id != self.source_id => id,
+            _ => return false,
+        };
+        self.biconnected_components().iter().any(|component| {
+            component.len() > 2
+                && component.contains(&self.source_id)
+                && component.contains(&target)
+        })
+    }
 
-        assert!(graph.contains_edge(&source, &node0).not());
-        assert!(graph.contains_edge(&source, &node1).not());
-        assert!(graph.contains_edge(&node0, &node1).not());
-        assert!(graph.contains_edge(&node1, &node0).not());
+    /// Splits the connected component of `source` into biconnected components, using
+    /// Tarjan's algorithm. Each component is the set of ids it spans; cut vertices belong to
+    /// more than one of them. The DFS is iterative, since the graph may be deep.
+    fn biconnected_components(&self) -> Vec<HashSet<u32>> {
+        let mut discovered = vec![0u32; self.id2p.len()];
+        let mut low = vec![0u32; self.id2p.len()];
+        let mut time = 1;
+        let mut edge_stack: Vec<(u32, u32)> = vec![];
+        let mut components = vec![];
+
+        // (id, parent, index of the next neighbor to visit)
+        let mut stack = vec![(self.source_id, self.source_id, 0usize)];
+        discovered[self.source_id as usize] = time;
+        low[self.source_id as usize] = time;
+        while let Some(top) = stack.last_mut() {
+            let (id, parent) = (top.0, top.1);
+            let next = self.adjacency[id as usize].get(top.2).copied();
+            top.2 += 1;
+            match next {
+                Some(neighbor) if !self.is_usable(id, neighbor, None) => {}
+                Some(neighbor) if discovered[neighbor as usize] == 0 => {
+                    time += 1;
+                    discovered[neighbor as usize] = time;
+                    low[neighbor as usize] = time;
+                    edge_stack.push((id, neighbor));
+                    stack.push((neighbor, id, 0));
+                }
+                Some(neighbor) => {
+                    // Back edge, each one is seen from both endpoints, keep only one of them.
+                    if neighbor != parent && discovered[neighbor as usize] < discovered[id as usize]
+                    {
+                        edge_stack.push((id, neighbor));
+                        low[id as usize] = low[id as usize].min(discovered[neighbor as usize]);
+                    }
+                }
+                None => {
+                    stack.pop();
+                    if stack.is_empty() {
+                        break;
+                    }
+                    low[parent as usize] = low[parent as usize].min(low[id as usize]);
+                    if low[id as usize] >= discovered[parent as usize] {
+                        // `parent` separates the subtree of `id` from the rest of the graph.
+                        let mut component = HashSet::new();
+                        while let Some(edge) = edge_stack.pop() {
+                            component.insert(edge.0);
+                            component.insert(edge.1);
+                            if edge == (parent, id) {
+                                break;
+                            }
+                        }
+                        components.push(component);
+                    }
+                }
+            }
+        }
+        components
+    }
 
-        graph.add_edge(&node0, &node1);
+    /// Whether `source` has no edges, in which case no peer is reachable. Unlike
+    /// `reachable_count`, this doesn't run a BFS.
+    pub fn is_source_isolated(&self) -> bool {
+        self.adjacency[self.source_id as usize].is_empty()
+    }
 
-        assert!(graph.contains_edge(&source, &node0).not());
-        assert!(graph.contains_edge(&source, &node1).not());
-        assert!(graph.contains_edge(&node0, &node1));
-        assert!(graph.contains_edge(&node1, &node0));
+    /// Number of peers reachable from `source`, not counting `source` itself.
+    /// Equivalent to `calculate_distance().len()`, but only runs the BFS distance pass,
+    /// without computing routes or allocating the result map.
+    pub fn reachable_count(&self) -> usize {
+        self.distances().iter().filter(|&&distance| distance >= 0).count() - 1
+    }
+
+    /// Whether `peer` is reachable from `source`, i.e. whether it is a key of the result of
+    /// `calculate_distance`. `source` itself is not considered reachable. Runs a BFS which
+    /// stops as soon as `peer` is found.
+    pub fn is_reachable(&self, peer: &PeerId) -> bool {
+        let target = match self.p2id.get(peer) {
+            Some(&id) if id != self.source_id => id,
+            _ => return false,
+        };
+        let mut visited = vec![false; self.id2p.len()];
+        visited[self.source_id as usize] = true;
+        let mut queue = VecDeque::new();
+
+        // Mirror `calculate_distance`, which only routes through the first `MAX_NUM_PEERS`
+        // neighbors of `source`.
+        for &neighbor in self.adjacency[self.source_id as usize].iter().take(MAX_NUM_PEERS) {
+            if !self.is_usable(self.source_id, neighbor, None) {
+                continue;
+            }
+            if neighbor == target {
+                return true;
+            }
+            visited[neighbor as usize] = true;
+            queue.push_back(neighbor);
+        }
+
+        while let Some(cur_peer) = queue.pop_front() {
+            for &neighbor in &self.adjacency[cur_peer as usize] {
+                if !self.is_usable(cur_peer, neighbor, None) {
+                    continue;
+                }
+                if neighbor == target {
+                    return true;
+                }
+                if !visited[neighbor as usize] {
+                    visited[neighbor as usize] = true;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        false
+    }
+
+    /// Number of reachable peers at each hop distance from `source`. `source` itself is not
+    /// included, so the counts sum up to `reachable_count`.
+    pub fn distance_distribution(&self) -> BTreeMap<u32, usize> {
+        let mut distance = vec![u32::MAX; self.id2p.len()];
+        distance[self.source_id as usize] = 0;
+        let mut queue = VecDeque::new();
+        let mut result = BTreeMap::new();
+
+        for &neighbor in self.adjacency[self.source_id as usize].iter().take(MAX_NUM_PEERS) {
+            if !self.is_usable(self.source_id, neighbor, None) {
+                continue;
+            }
+            distance[neighbor as usize] = 1;
+            queue.push_back(neighbor);
+        }
+
+        while let Some(cur_peer) = queue.pop_front() {
+            let cur_distance = distance[cur_peer as usize];
+            *result.entry(cur_distance).or_insert(0) += 1;
+            for &neighbor in &self.adjacency[cur_peer as usize] {
+                if !self.is_usable(cur_peer, neighbor, None) {
+                    continue;
+                }
+                if distance[neighbor as usize] == u32::MAX {
+                    distance[neighbor as usize] = cur_distance + 1;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        result
+    }
+
+    /// Edges of a BFS tree rooted at `source`, as `(parent, child)` pairs. Spans exactly the
+    /// peers reachable in the sense of `calculate_distance`, so it has `reachable_count` edges.
+    pub fn spanning_tree(&self) -> Vec<(PeerId, PeerId)> {
+        let mut visited = vec![false; self.id2p.len()];
+        visited[self.source_id as usize] = true;
+        let mut queue = VecDeque::new();
+        let mut tree = vec![];
+
+        for &neighbor in self.adjacency[self.source_id as usize].iter().take(MAX_NUM_PEERS) {
+            if !self.is_usable(self.source_id, neighbor, None) {
+                continue;
+            }
+            visited[neighbor as usize] = true;
+            queue.push_back(neighbor);
+            tree.push((self.source_id, neighbor));
+        }
+
+        while let Some(cur_peer) = queue.pop_front() {
+            for &neighbor in &self.adjacency[cur_peer as usize] {
+                if !visited[neighbor as usize] && self.is_usable(cur_peer, neighbor, None) {
+                    visited[neighbor as usize] = true;
+                    queue.push_back(neighbor);
+                    tree.push((cur_peer, neighbor));
+                }
+            }
+        }
+        tree.into_iter()
+            .map(|(parent, child)| {
+                (self.id2p[parent as usize].clone(), self.id2p[child as usize].clone())
+            })
+            .collect()
+    }
+
+    /// Whether connecting to `candidate` would let us reach peers we can't reach now.
+    /// Returns true iff `candidate` is present in the graph (we know some of its edges),
+    /// but it is not reachable from `source`. In that case a direct connection makes the
+    /// whole component of `candidate` reachable, while connecting to an already reachable
+    /// peer doesn't change the reachable set. Returns false for peers not in the graph,
+    /// since we know nothing about their adjacency.
+    pub fn would_connect(&self, candidate: &PeerId) -> bool {
+        match self.p2id.get(candidate) {
+            Some(&id) => self.distances()[id as usize] < 0,
+            None => false,
+        }
+    }
+
+    /// Distance from `source` for each id, or -1 if the id is unreachable, computed with a
+    /// plain BFS. Ids are internal to the graph, but stay valid as long as no edges are added,
+    /// so the result can be passed to `newly_unreachable_after_remove`.
+    pub fn distances(&self) -> Vec<i32> {
+        let mut queue = VecDeque::new();
+        let mut distance = vec![-1; self.id2p.len()];
+        distance[self.source_id as usize] = 0;
+
+        // Mirror `calculate_distance`, which only routes through the first `MAX_NUM_PEERS`
+        // neighbors of `source`.
+        for &neighbor in self.adjacency[self.source_id as usize].iter().take(MAX_NUM_PEERS) {
+            if distance[neighbor as usize] == -1 && self.is_usable(self.source_id, neighbor, None) {
+                distance[neighbor as usize] = 1;
+                queue.push_back(neighbor);
+            }
+        }
+
+        while let Some(cur_peer) = queue.pop_front() {
+            let cur_distance = distance[cur_peer as usize];
+            for &neighbor in &self.adjacency[cur_peer as usize] {
+                if distance[neighbor as usize] == -1 && self.is_usable(cur_peer, neighbor, None) {
+                    distance[neighbor as usize] = cur_distance + 1;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        distance
+    }
+
+    /// Returns the peers which became unreachable after `removed_edge` was removed, given
+    /// `prev_distance` computed by `distances` right before the removal. The result is sorted.
+    ///
+    /// Only the endpoint farther from `source` can lose its shortest paths. We search its
+    /// component in the current graph and stop as soon as we find a peer which was closer to
+    /// `source` than that endpoint. Such a peer is still reachable, because its shortest path
+    /// can't have used the removed edge, and so is the whole component.
+    pub fn newly_unreachable_after_remove(
+        &self,
+        removed_edge: &(PeerId, PeerId),
+        prev_distance: &[i32],
+    ) -> Vec<PeerId> {
+        let prev = |id: u32| prev_distance.get(id as usize).copied().unwrap_or(-1);
+        let (id0, id1) = match (self.find_id(&removed_edge.0), self.find_id(&removed_edge.1)) {
+            (Some(id0), Some(id1)) => (id0, id1),
+            _ => return vec![],
+        };
+        if prev(id0) < 0 || prev(id1) < 0 || prev(id0) == prev(id1) {
+            return vec![];
+        }
+        let far = if prev(id0) > prev(id1) { id0 } else { id1 };
+        let far_distance = prev(far);
+
+        let mut visited = HashSet::from([far]);
+        let mut queue = VecDeque::from([far]);
+        while let Some(cur_peer) = queue.pop_front() {
+            for &neighbor in &self.adjacency[cur_peer as usize] {
+                if !self.is_usable(cur_peer, neighbor, None) {
+                    continue;
+                }
+                if (0..far_distance).contains(&prev(neighbor)) {
+                    return vec![];
+                }
+                if visited.insert(neighbor) {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        let mut result: Vec<_> = visited
+            .into_iter()
+            .filter(|&id| prev(id) >= 0)
+            .map(|id| self.id2p[id as usize].clone())
+            .collect();
+        result.sort();
+        result
+    }
+
+    /// Like `p2id`, but also finds ids which were released after the peer lost all its edges,
+    /// and haven't been reused yet.
+    fn find_id(&self, peer: &PeerId) -> Option<u32> {
+        match self.p2id.get(peer) {
+            Some(&id) => Some(id),
+            None => (0..self.id2p.len())
+                .find(|&id| !self.used[id] && self.id2p[id] == *peer)
+                .map(|id| id as u32),
+        }
+    }
+
+    /// Converts representation of the result, from an array representation, to
+    /// a hashmap of PeerId -> Vec<PeerIds>
+    /// Arguments:
+    ///   - routes - for node given node at index `i`, give list of connected peers, which
+    ///     are on the optimal path
+    ///   - distances - not really needed: TODO remove this argument
+    fn compute_result(
+        &self,
+        source: u32,
+        routes: &[u128],
+        distance: &[i32],
+    ) -> HashMap<PeerId, Vec<PeerId>> {
+        self.compute_result_ids(source, routes, distance)
+            .into_iter()
+            .map(|(key, peer_set)| {
+                let peer_set = peer_set.into_iter().map(|id| self.id2p[id as usize].clone());
+                (self.id2p[key as usize].clone(), peer_set.collect())
+            })
+            .collect()
+    }
+
+    /// Same as `compute_result`, but keeps peers as ids.
+    fn compute_result_ids(
+        &self,
+        source: u32,
+        routes: &[u128],
+        distance: &[i32],
+    ) -> HashMap<u32, Vec<u32>> {
+        debug_assert_eq!(Ok(()), self.validate_routes(source, routes, distance));
+        let mut res = HashMap::with_capacity(routes.len());
+
+        let mut unreachable_nodes = 0;
+
+        for (key, &cur_route) in routes.iter().enumerate() {
+            if distance[key] == -1 && self.used[key] {
+                unreachable_nodes += 1;
+            }
+            if key as u32 == source || distance[key] == -1 || cur_route == 0u128 || !self.used[key]
+            {
+                continue;
+            }
+            res.insert(key as u32, self.route_to_next_hops(source, cur_route).collect());
+        }
+        if unreachable_nodes > 1000 {
+            warn!("We store more than 1000 unreachable nodes: {}", unreachable_nodes);
+        }
+        res
+    }
+
+    /// Converts `route`, a set of neighbors of `source` represented as bits, to a list of ids.
+    fn route_to_next_hops(&self, source: u32, route: u128) -> impl Iterator<Item = u32> + '_ {
+        self.adjacency[source as usize]
+            .iter()
+            .enumerate()
+            .take(MAX_NUM_PEERS)
+            .filter(move |(id, _)| (route & (1u128 << id)) != 0)
+            .map(|(_, &neighbor)| neighbor)
+    }
+}
+
+/// Computes which edges were added and which were removed when going from `old` to `new`.
+/// Endpoints are canonicalized with `Edge::make_key`, so `(a, b)` and `(b, a)` are the same edge.
+/// Both returned lists are sorted.
+pub fn topology_diff(
+    old: &[(PeerId, PeerId)],
+    new: &[(PeerId, PeerId)],
+) -> (Vec<(PeerId, PeerId)>, Vec<(PeerId, PeerId)>) {
+    let canonical = |edges: &[(PeerId, PeerId)]| -> HashSet<(PeerId, PeerId)> {
+        edges.iter().map(|(peer0, peer1)| Edge::make_key(peer0.clone(), peer1.clone())).collect()
+    };
+    let old = canonical(old);
+    let new = canonical(new);
+
+    let mut added: Vec<_> = new.difference(&old).cloned().collect();
+    let mut removed: Vec<_> = old.difference(&new).cloned().collect();
+    added.sort();
+    removed.sort();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::routing::graph::{topology_diff, Graph, GraphSnapshot};
+    use crate::test_utils::{expected_routing_tables, random_peer_id};
+    use crate::tests::util::make_rng;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use near_network_primitives::time;
+    use near_network_primitives::types::Edge;
+    use near_primitives::network::PeerId;
+    use rand::Rng;
+    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::ops::Not;
+
+    #[test]
+    fn graph_contains_edge() {
+        let source = random_peer_id();
+
+        let node0 = random_peer_id();
+        let node1 = random_peer_id();
+
+        let mut graph = Graph::new(source.clone());
+
+        assert!(graph.contains_edge(&source, &node0).not());
+        assert!(graph.contains_edge(&source, &node1).not());
+        assert!(graph.contains_edge(&node0, &node1).not());
+        assert!(graph.contains_edge(&node1, &node0).not());
+
+        graph.add_edge(&node0, &node1);
+
+        assert!(graph.contains_edge(&source, &node0).not());
+        assert!(graph.contains_edge(&source, &node1).not());
+        assert!(graph.contains_edge(&node0, &node1));
+        assert!(graph.contains_edge(&node1, &node0));
 
         graph.remove_edge(&node1, &node0);
 
@@ -386,8 +1602,1020 @@ mod test {
         }
 
         assert!(expected_routing_tables(&graph.calculate_distance(), &next_hops));
+        assert_eq!(9, graph.reachable_count());
+        assert_eq!(graph.calculate_distance().len(), graph.reachable_count());
+
+        for node in &nodes[0..9] {
+            assert!(graph.is_reachable(node));
+        }
+        // Dummy nodes.
+        assert!(graph.is_reachable(&nodes[9]).not());
+        assert!(graph.is_reachable(&nodes[10]).not());
+        assert!(graph.is_reachable(&source).not());
+        assert!(graph.is_reachable(&random_peer_id()).not());
+
+        let distribution = graph.distance_distribution();
+        assert_eq!(distribution, BTreeMap::from([(1, 3), (2, 3), (3, 3)]));
+        assert_eq!(distribution.values().sum::<usize>(), graph.reachable_count());
+
+        let tree = graph.spanning_tree();
+        assert_eq!(graph.reachable_count(), tree.len());
+        let parents: HashMap<_, _> =
+            tree.iter().map(|(parent, child)| (child.clone(), parent.clone())).collect();
+        assert_eq!(tree.len(), parents.len());
+        for (parent, child) in &tree {
+            assert!(graph.contains_edge(parent, child));
+            assert!(graph.is_reachable(child));
+        }
+        // Following parents leads to the source without cycles.
+        for node in parents.keys() {
+            let mut cur = node;
+            for _ in 0..=tree.len() {
+                if *cur == source {
+                    break;
+                }
+                cur = &parents[cur];
+            }
+            assert_eq!(&source, cur);
+        }
 
         assert_eq!(22, graph.total_active_edges() as usize);
         assert_eq!(22, graph.compute_total_active_edges() as usize);
+
+        let snapshot = graph.to_snapshot();
+        let bytes = snapshot.try_to_vec().unwrap();
+        let restored = Graph::from_snapshot(GraphSnapshot::try_from_slice(&bytes).unwrap());
+        assert_eq!(restored.calculate_distance(), graph.calculate_distance());
+        assert_eq!(restored.edges(), graph.edges());
+        assert_eq!(restored.to_snapshot(), snapshot);
+    }
+
+    #[test]
+    fn graph_calculate_distance_from() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..5).map(|_| random_peer_id()).collect();
+
+        // s - 0 - 1 - 2    3 - 4
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        graph.add_edge(&nodes[3], &nodes[4]);
+
+        assert_eq!(graph.calculate_distance_from(&source), graph.calculate_distance());
+
+        let from1 = graph.calculate_distance_from(&nodes[1]);
+        let expected = [
+            (source.clone(), vec![nodes[0].clone()]),
+            (nodes[0].clone(), vec![nodes[0].clone()]),
+            (nodes[2].clone(), vec![nodes[2].clone()]),
+        ];
+        assert!(expected_routing_tables(&from1, &expected));
+
+        let from3 = graph.calculate_distance_from(&nodes[3]);
+        assert!(expected_routing_tables(&from3, &[(nodes[4].clone(), vec![nodes[4].clone()])]));
+
+        assert!(graph.calculate_distance_from(&random_peer_id()).is_empty());
+    }
+
+    #[test]
+    fn graph_forwarding_for() {
+        let mut rng = make_rng(1436);
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..20).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        for node in &nodes[0..5] {
+            graph.add_edge(&source, node);
+        }
+        for _ in 0..40 {
+            let peer0 = &nodes[rng.gen_range(0, nodes.len())];
+            let peer1 = &nodes[rng.gen_range(0, nodes.len())];
+            if peer0 != peer1 {
+                graph.add_edge(peer0, peer1);
+            }
+        }
+        let full = graph.calculate_distance();
+
+        let unknown = random_peer_id();
+        let targets = [nodes[3].clone(), nodes[17].clone(), source.clone(), unknown];
+        let subset = graph.forwarding_for(&targets);
+        let expected: HashMap<_, _> = full
+            .iter()
+            .filter(|(peer, _)| targets.contains(peer))
+            .map(|(peer, next_hops)| (peer.clone(), next_hops.clone()))
+            .collect();
+        assert_eq!(expected, subset);
+        assert!(subset.contains_key(&nodes[3]));
+
+        let all = graph.forwarding_for(&nodes);
+        assert_eq!(full, all);
+        assert!(graph.forwarding_for(&[]).is_empty());
+    }
+
+    #[test]
+    fn graph_calculate_distance_timed() {
+        let mut rng = make_rng(1439);
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..1000).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        for node in &nodes[0..20] {
+            graph.add_edge(&source, node);
+        }
+        for _ in 0..5000 {
+            let peer0 = &nodes[rng.gen_range(0, nodes.len())];
+            let peer1 = &nodes[rng.gen_range(0, nodes.len())];
+            if peer0 != peer1 {
+                graph.add_edge(peer0, peer1);
+            }
+        }
+
+        let (result, elapsed) = graph.calculate_distance_timed(&time::Clock::real());
+        assert_eq!(result, graph.calculate_distance());
+        assert!(elapsed > time::Duration::ZERO);
+
+        let clock = time::FakeClock::default();
+        let (_, elapsed) = graph.calculate_distance_timed(&clock.clock());
+        assert_eq!(elapsed, time::Duration::ZERO);
+    }
+
+    #[test]
+    fn graph_calculate_distance_bounded() {
+        let mut rng = make_rng(1443);
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..1000).map(|_| random_peer_id()).collect();
+
+        // Random tree with a few extra edges, so that some peers have several next hops.
+        let mut graph = Graph::new(source.clone());
+        for node in &nodes[0..5] {
+            graph.add_edge(&source, node);
+        }
+        for i in 5..nodes.len() {
+            graph.add_edge(&nodes[rng.gen_range(0, i)], &nodes[i]);
+        }
+        for _ in 0..100 {
+            let peer0 = &nodes[rng.gen_range(0, nodes.len())];
+            let peer1 = &nodes[rng.gen_range(0, nodes.len())];
+            if peer0 != peer1 {
+                graph.add_edge(peer0, peer1);
+            }
+        }
+        let full = graph.calculate_distance();
+        let distances = graph.distances();
+        let distance = |peer: &PeerId| distances[graph.p2id[peer] as usize];
+
+        let (bounded, truncated) = graph.calculate_distance_bounded(100);
+        assert!(truncated);
+        assert_eq!(bounded.len(), 100);
+        let farthest_included = bounded.keys().map(distance).max().unwrap();
+        for (peer, next_hops) in &full {
+            match bounded.get(peer) {
+                Some(bounded_next_hops) => assert_eq!(bounded_next_hops, next_hops),
+                None => assert!(distance(peer) >= farthest_included),
+            }
+        }
+
+        assert_eq!(graph.calculate_distance_bounded(nodes.len()), (full.clone(), false));
+        assert_eq!(graph.calculate_distance_bounded(usize::MAX), (full, false));
+        assert_eq!(graph.calculate_distance_bounded(0), (HashMap::new(), true));
+    }
+
+    #[test]
+    fn graph_check_invariants() {
+        let mut rng = make_rng(921853233);
+        let peers: Vec<_> = (0..100).map(|_| random_peer_id()).collect();
+        let source = peers[0].clone();
+
+        let mut graph = Graph::new(source);
+        assert_eq!(Ok(()), graph.check_invariants());
+        for step in 0..5000 {
+            let peer0 = &peers[rng.gen_range(0, peers.len())];
+            let peer1 = &peers[rng.gen_range(0, peers.len())];
+            if peer0 == peer1 {
+                continue;
+            }
+            // Mostly add edges at first, so that high degree peers show up, then mostly remove.
+            if rng.gen_bool(if step < 2500 { 0.8 } else { 0.2 }) {
+                graph.add_edge(peer0, peer1);
+            } else {
+                graph.remove_edge(peer0, peer1);
+            }
+            assert_eq!(Ok(()), graph.check_invariants(), "at step {}", step);
+        }
+
+        graph.add_edge(&peers[1], &peers[2]);
+        graph.total_active_edges += 1;
+        assert!(graph.check_invariants().is_err());
+        graph.total_active_edges -= 1;
+        let id1 = graph.p2id[&peers[1]];
+        let id2 = graph.p2id[&peers[2]];
+        graph.adjacency[id1 as usize].retain(|&id| id != id2);
+        assert!(graph.check_invariants().is_err());
+    }
+
+    #[test]
+    fn graph_approx_betweenness() {
+        let source = random_peer_id();
+        let hub = random_peer_id();
+        let leaves: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        // source - hub - leaves, with an extra edge between two leaves.
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &hub);
+        for leaf in &leaves {
+            graph.add_edge(&hub, leaf);
+        }
+        graph.add_edge(&leaves[0], &leaves[1]);
+
+        let mut rng = make_rng(1237812);
+        // Exact with every peer as a source: the hub is on the shortest paths of all pairs of
+        // the other 5 peers, except for the pair of connected leaves.
+        let exact = graph.approx_betweenness(6, &mut rng);
+        assert_eq!(exact.len(), 6);
+        assert_eq!(exact[&hub], 9.);
+        for peer in leaves.iter().chain([&source]) {
+            assert_eq!(exact[peer], 0.);
+        }
+
+        for _ in 0..10 {
+            let approx = graph.approx_betweenness(2, &mut rng);
+            for peer in leaves.iter().chain([&source]) {
+                assert!(approx[&hub] > approx[peer]);
+            }
+        }
+    }
+
+    #[test]
+    fn graph_snapshot_keeps_next_hop_order() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        // Neighbors of source are added in reverse order, so that their order differs from the
+        // sorted edge list.
+        for node in nodes[0..3].iter().rev() {
+            graph.add_edge(&source, node);
+            graph.add_edge(node, &nodes[3]);
+        }
+
+        let restored = Graph::from_snapshot(graph.to_snapshot());
+        assert_eq!(restored.calculate_distance(), graph.calculate_distance());
+    }
+
+    #[test]
+    fn graph_topology_diff() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        let old = graph.edges();
+        assert_eq!(3, old.len());
+
+        graph.remove_edge(&nodes[1], &nodes[2]);
+        graph.add_edge(&nodes[2], &nodes[3]);
+        let new = graph.edges();
+
+        let (added, removed) = topology_diff(&old, &new);
+        assert_eq!(added, vec![Edge::make_key(nodes[2].clone(), nodes[3].clone())]);
+        assert_eq!(removed, vec![Edge::make_key(nodes[1].clone(), nodes[2].clone())]);
+
+        // Endpoint order doesn't matter.
+        let swapped: Vec<_> = new.iter().map(|(a, b)| (b.clone(), a.clone())).collect();
+        assert_eq!(topology_diff(&new, &swapped), (vec![], vec![]));
+    }
+
+    #[test]
+    fn graph_is_source_isolated() {
+        let source = random_peer_id();
+        let node0 = random_peer_id();
+        let node1 = random_peer_id();
+
+        let mut graph = Graph::new(source.clone());
+        assert!(graph.is_source_isolated());
+        // Edges between other peers don't connect the source.
+        graph.add_edge(&node0, &node1);
+        assert!(graph.is_source_isolated());
+        assert_eq!(graph.reachable_count(), 0);
+
+        graph.add_edge(&source, &node0);
+        assert!(!graph.is_source_isolated());
+        assert_eq!(graph.reachable_count(), 2);
+
+        graph.remove_edge(&source, &node0);
+        assert!(graph.is_source_isolated());
+    }
+
+    #[test]
+    fn graph_would_connect() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..5).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        // Isolated cluster.
+        graph.add_edge(&nodes[2], &nodes[3]);
+
+        assert!(graph.would_connect(&nodes[1]).not());
+        assert!(graph.would_connect(&nodes[2]));
+        assert!(graph.would_connect(&nodes[3]));
+        // Unknown peer.
+        assert!(graph.would_connect(&nodes[4]).not());
+        assert!(graph.would_connect(&source).not());
+
+        graph.add_edge(&nodes[1], &nodes[2]);
+        assert!(graph.would_connect(&nodes[3]).not());
+    }
+
+    #[test]
+    fn graph_newly_unreachable_after_remove() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..6).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        graph.add_edge(&nodes[0], &nodes[2]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        graph.add_edge(&nodes[2], &nodes[3]);
+        graph.add_edge(&nodes[3], &nodes[4]);
+        graph.add_edge(&nodes[4], &nodes[5]);
+
+        let edges = [
+            // Redundant edge, nodes[2] is still reachable through nodes[1].
+            (nodes[0].clone(), nodes[2].clone()),
+            // Disconnects the chain nodes[3] - nodes[4] - nodes[5].
+            (nodes[2].clone(), nodes[3].clone()),
+            // Disconnects a peer which has no other edges.
+            (source.clone(), nodes[0].clone()),
+            // Both endpoints are already unreachable.
+            (nodes[4].clone(), nodes[5].clone()),
+        ];
+        for (peer0, peer1) in edges {
+            let before: HashSet<_> = graph.calculate_distance().into_keys().collect();
+            let prev_distance = graph.distances();
+            graph.remove_edge(&peer0, &peer1);
+            let after: HashSet<_> = graph.calculate_distance().into_keys().collect();
+
+            let mut expected: Vec<_> = before.difference(&after).cloned().collect();
+            expected.sort();
+            assert_eq!(
+                expected,
+                graph.newly_unreachable_after_remove(&(peer0, peer1), &prev_distance)
+            );
+        }
+    }
+
+    #[test]
+    fn graph_pin_peer() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        graph.pin_peer(&nodes[1]);
+        let id = graph.p2id[&nodes[1]];
+
+        // nodes[1] becomes unreachable and loses all its edges, but keeps its id.
+        graph.remove_edge(&nodes[0], &nodes[1]);
+        assert!(!graph.calculate_distance().contains_key(&nodes[1]));
+        assert_eq!(graph.p2id.get(&nodes[1]), Some(&id));
+        graph.check_invariants().unwrap();
+
+        // The id is not handed out to other peers, and is used again once nodes[1] is back.
+        graph.add_edge(&nodes[0], &nodes[2]);
+        assert_ne!(graph.p2id[&nodes[2]], id);
+        graph.add_edge(&nodes[2], &nodes[1]);
+        assert_eq!(graph.p2id[&nodes[1]], id);
+        assert!(graph.calculate_distance().contains_key(&nodes[1]));
+
+        // Once unpinned, the id is released with the last edge.
+        graph.unpin_peer(&nodes[1]);
+        assert_eq!(graph.p2id.get(&nodes[1]), Some(&id));
+        graph.remove_edge(&nodes[2], &nodes[1]);
+        assert_eq!(graph.p2id.get(&nodes[1]), None);
+        graph.check_invariants().unwrap();
+
+        // A peer can be pinned before it has any edges, unpinning it releases the id.
+        graph.pin_peer(&nodes[1]);
+        assert!(graph.p2id.contains_key(&nodes[1]));
+        graph.check_invariants().unwrap();
+        graph.unpin_peer(&nodes[1]);
+        assert!(!graph.p2id.contains_key(&nodes[1]));
+        graph.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn graph_reachable_subgraph() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..11).map(|_| random_peer_id()).collect();
+
+        // Same graph as in `graph_distance4`.
+        let mut graph = Graph::new(source.clone());
+        for node in &nodes[0..3] {
+            graph.add_edge(&source, node);
+        }
+        for level in 0..2 {
+            for i in 0..3 {
+                for j in 0..3 {
+                    graph.add_edge(&nodes[level * 3 + i], &nodes[level * 3 + 3 + j]);
+                }
+            }
+        }
+        // Dummy edge.
+        graph.add_edge(&nodes[9], &nodes[10]);
+        // Leave an unused id behind.
+        let removed = random_peer_id();
+        graph.add_edge(&nodes[0], &removed);
+        graph.remove_edge(&nodes[0], &removed);
+
+        let subgraph = graph.reachable_subgraph();
+        assert_eq!(subgraph.check_invariants(), Ok(()));
+        assert_eq!(subgraph.my_peer_id(), &source);
+        assert_eq!(subgraph.calculate_distance(), graph.calculate_distance());
+        assert_eq!(subgraph.total_active_edges(), graph.total_active_edges() - 1);
+        // Only the source and the 9 reachable peers get ids.
+        assert_eq!(subgraph.id2p.len(), 10);
+        assert!(subgraph.unused.is_empty());
+        // Dummy nodes.
+        assert!(!subgraph.p2id.contains_key(&nodes[9]));
+        assert!(!subgraph.p2id.contains_key(&nodes[10]));
+
+        // An isolated source yields an empty graph.
+        let subgraph = Graph::new(source.clone()).reachable_subgraph();
+        assert_eq!(subgraph.check_invariants(), Ok(()));
+        assert_eq!(subgraph.id2p, vec![source]);
+    }
+
+    #[test]
+    fn graph_suppress_edge() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+
+        // source - 0 - 2 and source - 1 - 2, plus a direct source - 2 edge.
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        graph.add_edge(&source, &nodes[2]);
+        graph.add_edge(&nodes[0], &nodes[2]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        let original = graph.calculate_distance();
+        assert_eq!(original[&nodes[2]], vec![nodes[2].clone()]);
+
+        graph.suppress_edge(&nodes[2], &source);
+        assert_eq!(graph.check_invariants(), Ok(()));
+        assert_eq!(graph.total_active_edges(), 5);
+        let rerouted = graph.calculate_distance();
+        let mut next_hops = rerouted[&nodes[2]].clone();
+        next_hops.sort();
+        let mut expected = vec![nodes[0].clone(), nodes[1].clone()];
+        expected.sort();
+        assert_eq!(next_hops, expected);
+        assert_eq!(rerouted[&nodes[0]], vec![nodes[0].clone()]);
+
+        // Suppressing the only remaining paths makes the peer unreachable.
+        graph.suppress_edge(&nodes[0], &nodes[2]);
+        graph.suppress_edge(&nodes[1], &nodes[2]);
+        assert!(!graph.calculate_distance().contains_key(&nodes[2]));
+        graph.unsuppress_edge(&nodes[0], &nodes[2]);
+        graph.unsuppress_edge(&nodes[1], &nodes[2]);
+
+        graph.unsuppress_edge(&source, &nodes[2]);
+        assert_eq!(graph.calculate_distance(), original);
+
+        // Removing the edge drops its suppression, so it isn't suppressed once re-added.
+        graph.suppress_edge(&source, &nodes[2]);
+        graph.remove_edge(&source, &nodes[2]);
+        assert_eq!(graph.check_invariants(), Ok(()));
+        graph.add_edge(&source, &nodes[2]);
+        assert_eq!(graph.calculate_distance()[&nodes[2]], vec![nodes[2].clone()]);
+        // Unknown edges are ignored.
+        graph.suppress_edge(&nodes[0], &nodes[1]);
+        assert_eq!(graph.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn graph_suppress_edge_traversals() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        // source - 0 - 1 - 2 and source - 3, plus a cycle 0 - 3 - 2.
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[3]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        graph.add_edge(&nodes[0], &nodes[3]);
+        graph.add_edge(&nodes[3], &nodes[2]);
+
+        let check = |graph: &Graph| {
+            let distance = graph.calculate_distance();
+            assert_eq!(graph.reachable_count(), distance.len());
+            assert_eq!(graph.spanning_tree().len(), distance.len());
+            assert_eq!(graph.distance_distribution().values().sum::<usize>(), distance.len());
+            for node in &nodes {
+                assert_eq!(graph.is_reachable(node), distance.contains_key(node));
+                assert_eq!(graph.would_connect(node), !distance.contains_key(node));
+            }
+        };
+        check(&graph);
+        assert!(graph.is_two_connected_to(&nodes[2]));
+
+        // Suppressing 1 - 2 leaves 2 reachable through 3 only.
+        graph.suppress_edge(&nodes[1], &nodes[2]);
+        check(&graph);
+        assert!(!graph.is_two_connected_to(&nodes[2]));
+        assert_eq!(graph.distance_distribution(), BTreeMap::from([(1, 2), (2, 2)]));
+
+        // Suppressing 3 - 2 as well cuts 2 off.
+        graph.suppress_edge(&nodes[3], &nodes[2]);
+        check(&graph);
+        assert!(!graph.is_reachable(&nodes[2]));
+        assert_eq!(graph.reachable_count(), 3);
+
+        // Suppressing a source edge reroutes through the other neighbor.
+        graph.suppress_edge(&source, &nodes[0]);
+        check(&graph);
+        assert_eq!(graph.calculate_distance()[&nodes[0]], vec![nodes[3].clone()]);
+        assert!(graph.spanning_tree().contains(&(source.clone(), nodes[3].clone())));
+        assert!(!graph.spanning_tree().contains(&(source.clone(), nodes[0].clone())));
+
+        graph.unsuppress_edge(&source, &nodes[0]);
+        graph.unsuppress_edge(&nodes[1], &nodes[2]);
+        graph.unsuppress_edge(&nodes[3], &nodes[2]);
+        check(&graph);
+        assert_eq!(graph.reachable_count(), 4);
+    }
+
+    #[test]
+    fn graph_calculate_distance_fresh() {
+        let clock = time::FakeClock::default();
+        let max_age = time::Duration::minutes(5);
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+
+        // source - 0 - 1 with timestamps, plus a legacy edge source - 2.
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge_at(&source, &nodes[0], clock.now());
+        clock.advance(time::Duration::minutes(3));
+        graph.add_edge_at(&nodes[0], &nodes[1], clock.now());
+        graph.add_edge(&source, &nodes[2]);
+        assert_eq!(graph.check_invariants(), Ok(()));
+        assert_eq!(
+            graph.calculate_distance_fresh(max_age, clock.now()),
+            graph.calculate_distance()
+        );
+
+        // The first edge gets stale, cutting off both peers behind it.
+        clock.advance(time::Duration::minutes(3));
+        let fresh = graph.calculate_distance_fresh(max_age, clock.now());
+        assert_eq!(fresh.keys().collect::<Vec<_>>(), vec![&nodes[2]]);
+        assert_eq!(graph.calculate_distance().len(), 3);
+        // Other traversals ignore the timestamps.
+        assert_eq!(graph.reachable_count(), 3);
+        assert!(graph.is_reachable(&nodes[1]));
+
+        // Refreshing the edge makes the peers reachable again.
+        graph.add_edge_at(&nodes[0], &source, clock.now());
+        assert_eq!(
+            graph.calculate_distance_fresh(max_age, clock.now()),
+            graph.calculate_distance()
+        );
+
+        graph.remove_edge(&source, &nodes[0]);
+        graph.remove_edge(&nodes[0], &nodes[1]);
+        assert_eq!(graph.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn graph_snapshot_with_ids() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..6).map(|_| random_peer_id()).collect();
+        let mut graph = Graph::new(source.clone());
+        for node in &nodes {
+            graph.add_edge(&source, node);
+        }
+        graph.add_edge(&nodes[4], &nodes[5]);
+        // Release the ids of two peers, so that the ids are no longer contiguous.
+        graph.remove_edge(&source, &nodes[1]);
+        graph.remove_edge(&source, &nodes[3]);
+        let ids = |graph: &Graph| -> HashMap<PeerId, u32> {
+            graph.p2id.iter().map(|(peer, &id)| (peer.clone(), id)).collect()
+        };
+
+        let snapshot = graph.to_snapshot_with_ids();
+        let bytes = snapshot.try_to_vec().unwrap();
+        let mut restored = Graph::from_snapshot(GraphSnapshot::try_from_slice(&bytes).unwrap());
+        assert_eq!(restored.check_invariants(), Ok(()));
+        assert_eq!(ids(&restored), ids(&graph));
+        assert_eq!(restored.calculate_distance(), graph.calculate_distance());
+        assert_eq!(restored.to_snapshot_with_ids(), snapshot);
+
+        // New peers reuse one of the released ids.
+        let new_peer = random_peer_id();
+        restored.add_edge(&source, &new_peer);
+        assert_eq!(restored.check_invariants(), Ok(()));
+        let new_id = restored.p2id[&new_peer];
+        assert!(!ids(&graph).values().any(|&id| id == new_id));
+        assert!(new_id < graph.id2p.len() as u32, "{} is not a released id", new_id);
+        graph.add_edge(&source, &new_peer);
+
+        // Without ids, they are assigned from scratch.
+        let compact = Graph::from_snapshot(graph.to_snapshot());
+        assert_eq!(compact.check_invariants(), Ok(()));
+        assert_eq!(compact.calculate_distance(), graph.calculate_distance());
+
+        // Snapshots written before ids were added load without them.
+        let snapshot = graph.to_snapshot();
+        let legacy = (snapshot.source.clone(), snapshot.edges.clone()).try_to_vec().unwrap();
+        assert_eq!(GraphSnapshot::try_from_slice(&legacy).unwrap(), snapshot);
+        assert_eq!(
+            GraphSnapshot::try_from_slice(&snapshot.try_to_vec().unwrap()).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn graph_snapshot_with_corrupted_ids() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..2).map(|_| random_peer_id()).collect();
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+
+        let mut snapshot = graph.to_snapshot_with_ids();
+        snapshot.ids.as_mut().unwrap()[1].1 = u32::MAX;
+        let restored = Graph::from_snapshot(snapshot);
+        assert_eq!(restored.check_invariants(), Ok(()));
+        assert!(restored.id2p.len() <= 5);
+        assert_eq!(restored.calculate_distance(), graph.calculate_distance());
+    }
+
+    #[test]
+    fn graph_common_neighbors() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        // Triangle source - 0 - 1, with 2 attached to 0 and 3 attached to 1.
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        graph.add_edge(&nodes[0], &nodes[2]);
+        graph.add_edge(&nodes[1], &nodes[3]);
+
+        assert_eq!(graph.common_neighbors(&nodes[0], &nodes[1]), vec![source.clone()]);
+        assert_eq!(graph.common_neighbors(&source, &nodes[0]), vec![nodes[1].clone()]);
+        assert_eq!(graph.common_neighbors(&nodes[1], &source), vec![nodes[0].clone()]);
+        assert!(graph.common_neighbors(&nodes[2], &nodes[3]).is_empty());
+        assert!(graph.common_neighbors(&nodes[0], &random_peer_id()).is_empty());
+
+        graph.add_edge(&nodes[2], &nodes[1]);
+        let mut expected = vec![source.clone(), nodes[2].clone()];
+        expected.sort();
+        assert_eq!(graph.common_neighbors(&nodes[0], &nodes[1]), expected);
+    }
+
+    #[test]
+    fn graph_clustering_coefficient() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        assert_eq!(graph.clustering_coefficient(), 0.);
+
+        // Star around the source.
+        for node in &nodes {
+            graph.add_edge(&source, node);
+        }
+        assert_eq!(graph.clustering_coefficient(), 0.);
+
+        // Triad.
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        assert_eq!(graph.clustering_coefficient(), 1.);
+
+        // A pendant peer attached to 1: the source and 0 keep 1.0, 1 has 1 of 3 possible
+        // edges among its neighbors and 2 is skipped.
+        graph.add_edge(&nodes[1], &nodes[2]);
+        assert!((graph.clustering_coefficient() - (1. + 1. + 1. / 3.) / 3.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn graph_edge_source() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edges_from(
+            &nodes[0],
+            &[(nodes[0].clone(), nodes[1].clone()), (nodes[2].clone(), nodes[1].clone())],
+        );
+        assert_eq!(graph.edge_source(&(source.clone(), nodes[0].clone())), None);
+        assert_eq!(graph.edge_source(&(nodes[1].clone(), nodes[0].clone())), Some(&nodes[0]));
+        assert_eq!(graph.edge_source(&(nodes[1].clone(), nodes[2].clone())), Some(&nodes[0]));
+
+        // The first advertiser is kept when an edge is advertised again.
+        graph.add_edges_from(
+            &nodes[1],
+            &[(nodes[1].clone(), nodes[2].clone()), (nodes[2].clone(), nodes[3].clone())],
+        );
+        graph.add_edge(&nodes[0], &nodes[1]);
+        assert_eq!(graph.edge_source(&(nodes[2].clone(), nodes[1].clone())), Some(&nodes[0]));
+        assert_eq!(graph.edge_source(&(nodes[0].clone(), nodes[1].clone())), Some(&nodes[0]));
+        assert_eq!(graph.edge_source(&(nodes[2].clone(), nodes[3].clone())), Some(&nodes[1]));
+        // An edge added with `add_edge` gets a source once it is advertised.
+        graph.add_edges_from(&nodes[3], &[(source.clone(), nodes[0].clone())]);
+        assert_eq!(graph.edge_source(&(source.clone(), nodes[0].clone())), Some(&nodes[3]));
+        graph.check_invariants().unwrap();
+
+        // The source is forgotten together with the edge.
+        graph.remove_edge(&nodes[2], &nodes[3]);
+        assert_eq!(graph.edge_source(&(nodes[2].clone(), nodes[3].clone())), None);
+        graph.add_edge(&nodes[2], &nodes[3]);
+        assert_eq!(graph.edge_source(&(nodes[2].clone(), nodes[3].clone())), None);
+        graph.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn graph_non_essential_source_edges() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        assert!(graph.non_essential_source_edges().is_empty());
+
+        // nodes[0] and nodes[1] are both on shortest paths to nodes[2], nodes[3] only
+        // leads to itself.
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        graph.add_edge(&source, &nodes[3]);
+        graph.add_edge(&nodes[0], &nodes[2]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        graph.add_edge(&nodes[3], &nodes[0]);
+        assert_eq!(graph.non_essential_source_edges(), vec![nodes[3].clone()]);
+
+        // Once nodes[3] is the only way to reach `extra`, it becomes essential.
+        let extra = random_peer_id();
+        graph.add_edge(&nodes[3], &extra);
+        assert!(graph.non_essential_source_edges().is_empty());
+
+        // Dropping a non-essential edge doesn't change the routes to other peers.
+        graph.remove_edge(&nodes[3], &extra);
+        let before = graph.calculate_distance();
+        graph.remove_edge(&source, &nodes[3]);
+        let after = graph.calculate_distance();
+        for (peer, hops) in &after {
+            if *peer != nodes[3] {
+                assert_eq!(Some(hops), before.get(peer));
+            }
+        }
+        assert_eq!(after.len(), before.len());
+    }
+
+    #[test]
+    fn graph_is_two_connected_to() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..8).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        // Cycle source - nodes[0] - nodes[1] - nodes[2] - source.
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        graph.add_edge(&nodes[2], &source);
+        // nodes[3] and nodes[4] form a cycle with nodes[1], which is a cut vertex.
+        graph.add_edge(&nodes[1], &nodes[3]);
+        graph.add_edge(&nodes[3], &nodes[4]);
+        graph.add_edge(&nodes[4], &nodes[1]);
+        // nodes[5] is behind a bridge, nodes[6] is a direct neighbor only through a bridge.
+        graph.add_edge(&nodes[2], &nodes[5]);
+        graph.add_edge(&source, &nodes[6]);
+
+        for node in &nodes[0..3] {
+            assert!(graph.is_two_connected_to(node));
+        }
+        for node in &nodes[3..7] {
+            assert!(graph.is_two_connected_to(node).not());
+        }
+        // Unknown peer and source itself.
+        assert!(graph.is_two_connected_to(&nodes[7]).not());
+        assert!(graph.is_two_connected_to(&source).not());
+
+        // Closing a cycle through the bridge makes nodes[5] and nodes[6] 2-connected.
+        graph.add_edge(&nodes[5], &nodes[6]);
+        assert!(graph.is_two_connected_to(&nodes[5]));
+        assert!(graph.is_two_connected_to(&nodes[6]));
+        assert!(graph.is_two_connected_to(&nodes[3]).not());
+    }
+
+    #[test]
+    fn graph_validate_routes() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&nodes[0], &nodes[1]);
+        // Unreachable.
+        graph.add_edge(&nodes[2], &random_peer_id());
+
+        let (mut routes, distance) = graph.compute_routes(graph.source_id);
+        assert_eq!(Ok(()), graph.validate_routes(graph.source_id, &routes, &distance));
+
+        let id1 = graph.p2id[&nodes[1]] as usize;
+        routes[id1] = 0;
+        assert!(graph.validate_routes(graph.source_id, &routes, &distance).is_err());
+
+        let id2 = graph.p2id[&nodes[2]] as usize;
+        let (mut routes, distance) = graph.compute_routes(graph.source_id);
+        routes[id2] = 1;
+        assert!(graph.validate_routes(graph.source_id, &routes, &distance).is_err());
+    }
+
+    #[test]
+    fn graph_destinations_dependent_on() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..6).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        // Only routed through nodes[0].
+        graph.add_edge(&nodes[0], &nodes[2]);
+        graph.add_edge(&nodes[2], &nodes[3]);
+        // Routed through both nodes[0] and nodes[1].
+        graph.add_edge(&nodes[0], &nodes[4]);
+        graph.add_edge(&nodes[1], &nodes[4]);
+        // Unreachable.
+        graph.add_edge(&nodes[5], &random_peer_id());
+
+        let mut expected = vec![nodes[2].clone(), nodes[3].clone()];
+        expected.sort();
+        assert_eq!(expected, graph.destinations_dependent_on(&nodes[0]));
+        assert!(graph.destinations_dependent_on(&nodes[1]).is_empty());
+        // Not a neighbor of `source`.
+        assert!(graph.destinations_dependent_on(&nodes[2]).is_empty());
+        assert!(graph.destinations_dependent_on(&nodes[5]).is_empty());
+    }
+
+    #[test]
+    fn graph_calculate_distance_weighted() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+        let target = &nodes[2];
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        graph.add_edge(&nodes[0], target);
+        graph.add_edge(&nodes[1], target);
+        // Unreachable.
+        graph.add_edge(&nodes[3], &random_peer_id());
+
+        assert_eq!(graph.calculate_distance(), graph.calculate_distance_weighted(&HashMap::new()));
+
+        // Symmetric weights: both paths to `target` cost the same.
+        let mut weights = HashMap::new();
+        for (peer0, peer1, weight) in [
+            (&source, &nodes[0], 1),
+            (&source, &nodes[1], 2),
+            (&nodes[0], target, 2),
+            (&nodes[1], target, 1),
+        ] {
+            weights.insert((peer0.clone(), peer1.clone()), weight);
+            weights.insert((peer1.clone(), peer0.clone()), weight);
+        }
+        let routes = graph.calculate_distance_weighted(&weights);
+        let next_hops: HashSet<_> = routes[target].iter().cloned().collect();
+        assert_eq!(HashSet::from([nodes[0].clone(), nodes[1].clone()]), next_hops);
+        assert!(!routes.contains_key(&nodes[3]));
+
+        // The hop from nodes[0] to `target` is slow, only the opposite direction is fast.
+        weights.insert((nodes[0].clone(), target.clone()), 5);
+        let routes = graph.calculate_distance_weighted(&weights);
+        assert_eq!(vec![nodes[1].clone()], routes[target]);
+        assert_eq!(vec![nodes[0].clone()], routes[&nodes[0]]);
+
+        // Weights in the direction towards `source` don't matter.
+        weights.insert((nodes[0].clone(), target.clone()), 2);
+        weights.insert((target.clone(), nodes[0].clone()), 5);
+        let routes = graph.calculate_distance_weighted(&weights);
+        assert_eq!(2, routes[target].len());
+    }
+
+    #[test]
+    fn graph_calculate_distance_ids() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..5).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[1]);
+        graph.add_edge(&nodes[0], &nodes[2]);
+        graph.add_edge(&nodes[1], &nodes[2]);
+        graph.add_edge(&nodes[2], &nodes[3]);
+        // Unreachable.
+        graph.add_edge(&nodes[4], &random_peer_id());
+
+        let resolve = |id| graph.id_to_peer(id).unwrap().clone();
+        let resolved: HashMap<_, Vec<_>> = graph
+            .calculate_distance_ids()
+            .into_iter()
+            .map(|(key, peer_set)| (resolve(key), peer_set.into_iter().map(resolve).collect()))
+            .collect();
+        assert_eq!(graph.calculate_distance(), resolved);
+        assert_eq!(4, resolved.len());
+
+        let id = graph.p2id[&nodes[3]];
+        assert_eq!(Some(&nodes[3]), graph.id_to_peer(id));
+        graph.remove_edge(&nodes[2], &nodes[3]);
+        assert_eq!(None, graph.id_to_peer(id));
+        assert_eq!(None, graph.id_to_peer(u32::MAX));
+    }
+
+    #[test]
+    fn graph_high_degree_peer() {
+        let source = random_peer_id();
+        let hub = random_peer_id();
+        let leaves: Vec<_> = (0..1000).map(|_| random_peer_id()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &hub);
+        // Gossip delivers the same edges many times.
+        for _ in 0..3 {
+            for leaf in &leaves {
+                graph.add_edge(&hub, leaf);
+            }
+        }
+        assert_eq!(1001, graph.total_active_edges());
+        assert_eq!(1001, graph.compute_total_active_edges());
+        let hub_id = graph.p2id[&hub];
+        assert_eq!(1001, graph.adjacency_index[&hub_id].len());
+
+        // Remove every other leaf.
+        for leaf in leaves.iter().step_by(2) {
+            graph.remove_edge(leaf, &hub);
+        }
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert_eq!(i % 2 == 1, graph.contains_edge(&hub, leaf));
+            assert_eq!(i % 2 == 1, graph.contains_edge(leaf, &hub));
+        }
+        assert_eq!(graph.check_invariants(), Ok(()));
+        let mut remaining: Vec<_> = std::iter::once(&source)
+            .chain(leaves.iter().skip(1).step_by(2))
+            .map(|peer| graph.p2id[peer])
+            .collect();
+        remaining.sort();
+        let mut hub_neighbors = graph.adjacency[hub_id as usize].clone();
+        hub_neighbors.sort();
+        assert_eq!(remaining, hub_neighbors);
+
+        let next_hops: Vec<_> = std::iter::once((hub.clone(), vec![hub.clone()]))
+            .chain(leaves.iter().skip(1).step_by(2).map(|leaf| (leaf.clone(), vec![hub.clone()])))
+            .collect();
+        assert!(expected_routing_tables(&graph.calculate_distance(), &next_hops));
+
+        // Index is dropped once the hub has few neighbors left.
+        for leaf in &leaves {
+            graph.remove_edge(&hub, leaf);
+        }
+        assert!(graph.adjacency_index.is_empty());
+        assert_eq!(1, graph.total_active_edges());
+        assert!(graph.contains_edge(&source, &hub));
+    }
+
+    #[test]
+    fn graph_high_degree_source_keeps_order() {
+        let source = random_peer_id();
+        let neighbors: Vec<_> = (0..200).map(|_| random_peer_id()).collect();
+        let mut graph = Graph::new(source.clone());
+        for neighbor in &neighbors {
+            graph.add_edge(&source, neighbor);
+        }
+        assert!(graph.adjacency_index.contains_key(&graph.source_id));
+
+        for neighbor in neighbors.iter().step_by(3) {
+            graph.remove_edge(neighbor, &source);
+        }
+        assert_eq!(graph.check_invariants(), Ok(()));
+        // The order of the neighbors of source determines which of them are used as next hops.
+        let expected_order: Vec<_> = (neighbors.iter().enumerate())
+            .filter(|(i, _)| i % 3 != 0)
+            .map(|(_, peer)| graph.p2id[peer])
+            .collect();
+        assert_eq!(expected_order, graph.adjacency[graph.source_id as usize]);
+        assert_eq!(
+            graph.calculate_distance(),
+            Graph::from_snapshot(graph.to_snapshot()).calculate_distance()
+        );
     }
 }
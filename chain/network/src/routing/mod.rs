@@ -10,5 +10,5 @@ mod graph_with_cache;
 pub(crate) use actor::Actor;
 pub(crate) use graph_with_cache::RoutingTable;
 // for benchmark only
-pub use graph::Graph;
+pub use graph::{Graph, GraphSnapshot};
 pub use graph_with_cache::GraphWithCache;
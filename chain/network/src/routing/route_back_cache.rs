@@ -175,6 +175,18 @@ impl RouteBackCache {
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.main.len()
+    }
+
+    /// All records, from the oldest to the newest.
+    #[cfg(test)]
+    pub fn entries(&self) -> Vec<(CryptoHash, PeerId)> {
+        let mut entries: Vec<_> = self.main.iter().collect();
+        entries.sort_by_key(|(hash, (time, _))| (*time, **hash));
+        entries.into_iter().map(|(hash, (_, target))| (*hash, target.clone())).collect()
+    }
+
     pub fn get(&self, hash: &CryptoHash) -> Option<&PeerId> {
         self.main.get(hash).map(|(_, target)| target)
     }
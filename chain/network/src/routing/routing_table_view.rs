@@ -3,7 +3,7 @@ use crate::store;
 use itertools::Itertools;
 use lru::LruCache;
 use near_network_primitives::time;
-use near_network_primitives::types::{Edge, PeerIdOrHash};
+use near_network_primitives::types::{Edge, PeerIdOrHash, Pong};
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::types::AccountId;
@@ -14,6 +14,54 @@ use tracing::warn;
 const ANNOUNCE_ACCOUNT_CACHE_SIZE: usize = 10_000;
 const ROUND_ROBIN_MAX_NONCE_DIFFERENCE_ALLOWED: usize = 10;
 const ROUND_ROBIN_NONCE_CACHE_SIZE: usize = 10_000;
+const PING_PONG_CACHE_SIZE: usize = 1_000;
+/// Maximal number of pings awaiting a pong tracked for a single peer.
+const WAITING_PONG_PER_PEER: usize = 10;
+/// Default time after which a ping without a pong is considered lost.
+const DEFAULT_PING_TIMEOUT: time::Duration = time::Duration::seconds(10);
+/// Default maximal number of route back entries a single peer can insert within
+/// `DEFAULT_ROUTE_BACK_RATE_WINDOW`.
+const DEFAULT_ROUTE_BACK_RATE_LIMIT: usize = 10_000;
+const DEFAULT_ROUTE_BACK_RATE_WINDOW: time::Duration = time::Duration::seconds(60);
+
+/// Sizes of the caches and limits used by `RoutingTableView`. Relay nodes tracking many
+/// peers may want larger caches, while light clients smaller ones.
+#[derive(Clone, Debug)]
+pub(crate) struct RoutingTableConfig {
+    /// Number of announced accounts kept in memory, others are loaded from the store.
+    pub announce_account_cache_size: usize,
+    /// Number of peers for which we track how many times they were used to route a message.
+    pub round_robin_nonce_cache_size: usize,
+    /// Number of peers for which we track pings awaiting a pong and pong statistics.
+    pub ping_pong_cache_size: usize,
+    /// Maximal number of route back entries a single peer can insert within
+    /// `route_back_window`.
+    pub route_back_limit: usize,
+    pub route_back_window: time::Duration,
+    /// Time after which a ping without a pong is considered lost.
+    pub ping_timeout: time::Duration,
+    /// Maximal number of next hops kept for each destination, see `set_peer_forwarding`.
+    /// `None` keeps all of them.
+    pub max_next_hops: Option<usize>,
+    /// Always route through the lowest `PeerId` among the next hops, instead of round robin,
+    /// so that routing decisions are reproducible in tests.
+    pub deterministic_routing: bool,
+}
+
+impl Default for RoutingTableConfig {
+    fn default() -> Self {
+        Self {
+            announce_account_cache_size: ANNOUNCE_ACCOUNT_CACHE_SIZE,
+            round_robin_nonce_cache_size: ROUND_ROBIN_NONCE_CACHE_SIZE,
+            ping_pong_cache_size: PING_PONG_CACHE_SIZE,
+            route_back_limit: DEFAULT_ROUTE_BACK_RATE_LIMIT,
+            route_back_window: DEFAULT_ROUTE_BACK_RATE_WINDOW,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
+            max_next_hops: None,
+            deterministic_routing: false,
+        }
+    }
+}
 
 pub(crate) struct RoutingTableView {
     /// PeerId associated for every known account id.
@@ -30,6 +78,108 @@ pub(crate) struct RoutingTableView {
     /// If there are several options use route with minimum nonce.
     /// New routes are added with minimum nonce.
     route_nonce: LruCache<PeerId, usize>,
+    /// Maximal number of route back entries a single peer can insert within `route_back_window`.
+    route_back_limit: usize,
+    route_back_window: time::Duration,
+    /// Start of the current window and number of route back entries inserted
+    /// within it, for each peer we received such messages from.
+    route_back_rate: HashMap<PeerId, (time::Instant, usize)>,
+    /// Number of route back lookups which found, respectively didn't find, an entry.
+    route_back_hits: usize,
+    route_back_misses: usize,
+    /// Time at which we sent each ping, for which we haven't received a pong yet.
+    waiting_pong: LruCache<PeerId, LruCache<u64, time::Instant>>,
+    /// Number of pongs received from each peer within and after `ping_timeout` respectively.
+    pong_counts: LruCache<PeerId, (usize, usize)>,
+    /// Round trip times of the pongs received from each peer within `ping_timeout`.
+    rtt_stats: LruCache<PeerId, RttStats>,
+    /// Time after which a ping without a pong is considered lost.
+    ping_timeout: time::Duration,
+    /// Maximal number of next hops kept for each destination in `peer_forwarding`.
+    max_next_hops: Option<usize>,
+    /// See `RoutingTableConfig::deterministic_routing`.
+    deterministic_routing: bool,
+}
+
+/// Result of matching a received pong against the pings we sent.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PongStatus {
+    /// Pong arrived within `ping_timeout`, contains the round trip time.
+    Matched(time::Duration),
+    /// Pong arrived after `ping_timeout` had already passed.
+    Late,
+    /// We have no record of sending a ping with this nonce to the peer.
+    Unknown,
+}
+
+/// Round trip times of the pongs matched from a peer, see `RoutingTableView::rtt_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RttStats {
+    pub count: usize,
+    pub min: time::Duration,
+    pub max: time::Duration,
+    pub total: time::Duration,
+}
+
+impl RttStats {
+    fn new(rtt: time::Duration) -> Self {
+        Self { count: 1, min: rtt, max: rtt, total: rtt }
+    }
+
+    fn add(&mut self, rtt: time::Duration) {
+        self.count += 1;
+        self.min = self.min.min(rtt);
+        self.max = self.max.max(rtt);
+        self.total += rtt;
+    }
+
+    pub fn mean(&self) -> time::Duration {
+        self.total / self.count as u32
+    }
+}
+
+impl std::fmt::Display for RttStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} pongs, min {}, mean {}, max {}", self.count, self.min, self.mean(), self.max)
+    }
+}
+
+/// Summary of how good a peer is to talk to, see `RoutingTableView::peer_health`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PeerHealth {
+    /// `None` if no pong was received within `ping_timeout`.
+    pub rtt: Option<RttStats>,
+    /// See `RoutingTableView::ping_loss`.
+    pub ping_loss: Option<f64>,
+    /// Whether there is a route to the peer.
+    pub reachable: bool,
+    /// Whether the peer is one of its own next hops, i.e. we are connected to it.
+    pub direct: bool,
+    /// Between 0 (unreachable) and 100. Reachability is worth 30 points, being direct 10,
+    /// no ping loss 40 and a zero mean round trip time 20, decreasing linearly until
+    /// `ping_timeout`. Unknown loss or round trip time get half their points.
+    pub score: u8,
+}
+
+/// Occupancy of the route back cache and the number of lookups in it since the
+/// `RoutingTableView` was created, see `RoutingTableView::route_back_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RouteBackStats {
+    pub len: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// Non-derived state of a `RoutingTableView`, see `RoutingTableView::export_state`.
+#[cfg(test)]
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RoutingStateSnapshot {
+    /// Local edges, sorted by the other peer.
+    pub local_edges: Vec<(PeerId, Edge)>,
+    /// Announcements in the cache, from the most to the least recently used.
+    pub accounts: Vec<AnnounceAccount>,
+    /// Route back entries, from the oldest to the newest.
+    pub route_back: Vec<(CryptoHash, PeerId)>,
 }
 
 #[derive(Debug)]
@@ -41,16 +191,27 @@ pub(crate) enum FindRouteError {
 }
 
 impl RoutingTableView {
-    pub fn new(store: store::Store) -> Self {
+    pub fn new(store: store::Store, config: RoutingTableConfig) -> Self {
         // Find greater nonce on disk and set `component_nonce` to this value.
 
         Self {
-            account_peers: LruCache::new(ANNOUNCE_ACCOUNT_CACHE_SIZE),
+            account_peers: LruCache::new(config.announce_account_cache_size),
             peer_forwarding: Default::default(),
             local_edges_info: Default::default(),
             route_back: RouteBackCache::default(),
             store,
-            route_nonce: LruCache::new(ROUND_ROBIN_NONCE_CACHE_SIZE),
+            route_nonce: LruCache::new(config.round_robin_nonce_cache_size),
+            route_back_limit: config.route_back_limit,
+            route_back_window: config.route_back_window,
+            route_back_rate: Default::default(),
+            route_back_hits: 0,
+            route_back_misses: 0,
+            waiting_pong: LruCache::new(config.ping_pong_cache_size),
+            pong_counts: LruCache::new(config.ping_pong_cache_size),
+            rtt_stats: LruCache::new(config.ping_pong_cache_size),
+            ping_timeout: config.ping_timeout,
+            max_next_hops: config.max_next_hops,
+            deterministic_routing: config.deterministic_routing,
         }
     }
 
@@ -60,10 +221,59 @@ impl RoutingTableView {
         self.local_edges_info.get(other_peer).map_or(0, |x| x.nonce()) < nonce
     }
 
+    /// Batched version of `is_local_edge_newer`. Returns indices of `candidates` which are
+    /// newer than the local edges we store. Candidates are expected to be local edges, a
+    /// candidate we don't have an edge for is considered newer.
+    pub(crate) fn filter_newer_local_edges(&self, candidates: &[Edge]) -> Vec<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, edge)| {
+                let (peer0, peer1) = edge.key();
+                let current = (self.local_edges_info.get(peer0))
+                    .or_else(|| self.local_edges_info.get(peer1))
+                    .filter(|current| current.key() == edge.key());
+                current.map_or(0, |current| current.nonce()) < edge.nonce()
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Sets the next hops for each destination, as computed by `Graph::calculate_distance`.
+    /// If `max_next_hops` is set, only that many next hops with the lowest round robin nonce,
+    /// i.e. the least used ones at this moment, are kept for each destination.
+    pub(crate) fn set_peer_forwarding(
+        &mut self,
+        peer_forwarding: Arc<HashMap<PeerId, Vec<PeerId>>>,
+    ) {
+        self.peer_forwarding = match self.max_next_hops {
+            Some(max) if peer_forwarding.values().any(|next_hops| next_hops.len() > max) => {
+                let capped = peer_forwarding
+                    .iter()
+                    .map(|(target, next_hops)| {
+                        let mut next_hops = next_hops.clone();
+                        if next_hops.len() > max {
+                            next_hops.sort_by_key(|peer_id| {
+                                self.route_nonce.peek(peer_id).cloned().unwrap_or_default()
+                            });
+                            next_hops.truncate(max);
+                        }
+                        (target.clone(), next_hops)
+                    })
+                    .collect();
+                Arc::new(capped)
+            }
+            _ => peer_forwarding,
+        };
+    }
+
     /// Find peer that is connected to `source` and belong to the shortest path
     /// from `source` to `peer_id`.
     fn find_route_from_peer_id(&mut self, peer_id: &PeerId) -> Result<PeerId, FindRouteError> {
         if let Some(routes) = self.peer_forwarding.get(peer_id) {
+            if self.deterministic_routing {
+                return routes.iter().min().cloned().ok_or(FindRouteError::Disconnected);
+            }
             match (routes.iter())
                 .map(|peer_id| {
                     (self.route_nonce.get(peer_id).cloned().unwrap_or_default(), peer_id)
@@ -107,10 +317,47 @@ impl RoutingTableView {
         }
     }
 
+    /// Snapshot of the round robin counters of the next hops, which approximate the number of
+    /// messages routed through each of them. Peers evicted from the cache are not included.
+    pub(crate) fn route_usage(&self) -> HashMap<PeerId, usize> {
+        self.route_nonce.iter().map(|(peer_id, &nonce)| (peer_id.clone(), nonce)).collect()
+    }
+
     pub(crate) fn view_route(&self, peer_id: &PeerId) -> Option<&Vec<PeerId>> {
         self.peer_forwarding.get(peer_id)
     }
 
+    /// Captures the local edges, the cached account announcements and the route back entries,
+    /// e.g. to check in tests that routing survives a restart. `peer_forwarding` is derived
+    /// from the graph and is not included.
+    #[cfg(test)]
+    pub(crate) fn export_state(&self) -> RoutingStateSnapshot {
+        let mut local_edges: Vec<_> =
+            self.local_edges_info.iter().map(|(peer, edge)| (peer.clone(), edge.clone())).collect();
+        local_edges.sort_by(|(peer0, _), (peer1, _)| peer0.cmp(peer1));
+        RoutingStateSnapshot {
+            local_edges,
+            accounts: self.get_announce_accounts().cloned().collect(),
+            route_back: self.route_back.entries(),
+        }
+    }
+
+    /// Replaces the state captured by `export_state` with `snapshot`. Announcements are also
+    /// written to the store. Route back entries are inserted as if they were received now.
+    #[cfg(test)]
+    pub(crate) fn import_state(&mut self, clock: &time::Clock, snapshot: RoutingStateSnapshot) {
+        self.local_edges_info = snapshot.local_edges.into_iter().collect();
+        self.account_peers.clear();
+        // Insert the least recently used first, to keep the order of the cache.
+        for announce_account in snapshot.accounts.into_iter().rev() {
+            self.add_account(announce_account);
+        }
+        self.route_back = RouteBackCache::default();
+        for (hash, peer_id) in snapshot.route_back {
+            self.route_back.insert(clock, hash, peer_id);
+        }
+    }
+
     /// Find peer that owns this AccountId.
     pub(crate) fn account_owner(
         &mut self,
@@ -121,6 +368,23 @@ impl RoutingTableView {
             .ok_or(FindRouteError::AccountNotFound)
     }
 
+    /// Previews how a message to `account_id` would be routed: returns the peer owning the
+    /// account together with the next hops it could be forwarded to. Unlike `find_route`,
+    /// the round robin state is left untouched.
+    pub(crate) fn preview_account_route(
+        &mut self,
+        account_id: &AccountId,
+    ) -> Result<(PeerId, Vec<PeerId>), FindRouteError> {
+        let owner = self.account_owner(account_id)?;
+        let next_hops = self.view_route(&owner).ok_or(FindRouteError::PeerNotFound)?;
+        if next_hops.is_empty() {
+            return Err(FindRouteError::Disconnected);
+        }
+        let mut next_hops = next_hops.clone();
+        next_hops.sort();
+        Ok((owner, next_hops))
+    }
+
     /// Add (account id, peer id) to routing table.
     /// Note: There is at most on peer id per account id.
     pub(crate) fn add_account(&mut self, announce_account: AnnounceAccount) {
@@ -146,24 +410,166 @@ impl RoutingTableView {
         }
     }
 
+    /// Remembers that the response to the message with given `hash` should be
+    /// routed back to `peer_id`.
+    /// Returns false if `peer_id` exceeded its route back insertion rate, in which
+    /// case the entry is not stored, so that a single peer can't flood the shared cache.
     pub(crate) fn add_route_back(
         &mut self,
         clock: &time::Clock,
         hash: CryptoHash,
         peer_id: PeerId,
-    ) {
+    ) -> bool {
+        let now = clock.now();
+        let window = self.route_back_window;
+        if !self.route_back_rate.get(&peer_id).map_or(false, |(start, _)| now - *start < window) {
+            // Starting a new window, drop the windows of the other peers which already expired.
+            self.route_back_rate.retain(|_, (start, _)| now - *start < window);
+            self.route_back_rate.insert(peer_id.clone(), (now, 0));
+        }
+        let (_, count) = self.route_back_rate.get_mut(&peer_id).unwrap();
+        if *count >= self.route_back_limit {
+            return false;
+        }
+        *count += 1;
         self.route_back.insert(clock, hash, peer_id);
+        true
     }
 
     // Find route back with given hash and removes it from cache.
     fn fetch_route_back(&mut self, clock: &time::Clock, hash: CryptoHash) -> Option<PeerId> {
-        self.route_back.remove(clock, &hash)
+        let peer_id = self.route_back.remove(clock, &hash);
+        if peer_id.is_some() {
+            self.route_back_hits += 1;
+        } else {
+            self.route_back_misses += 1;
+        }
+        peer_id
+    }
+
+    pub(crate) fn route_back_stats(&self) -> RouteBackStats {
+        RouteBackStats {
+            len: self.route_back.len(),
+            hits: self.route_back_hits,
+            misses: self.route_back_misses,
+        }
     }
 
     pub(crate) fn compare_route_back(&self, hash: CryptoHash, peer_id: &PeerId) -> bool {
         self.route_back.get(&hash).map_or(false, |value| value == peer_id)
     }
 
+    /// Remembers that a ping with given `nonce` was sent to `target`.
+    pub(crate) fn sending_ping(&mut self, clock: &time::Clock, nonce: u64, target: PeerId) {
+        let now = clock.now();
+        if let Some(pings) = self.waiting_pong.get_mut(&target) {
+            pings.put(nonce, now);
+        } else {
+            let mut pings = LruCache::new(WAITING_PONG_PER_PEER);
+            pings.put(nonce, now);
+            self.waiting_pong.put(target, pings);
+        }
+    }
+
+    /// Matches `pong` against the pings sent to its source.
+    pub(crate) fn add_pong(&mut self, clock: &time::Clock, pong: &Pong) -> PongStatus {
+        let sent = match self.waiting_pong.get_mut(&pong.source).and_then(|p| p.pop(&pong.nonce)) {
+            Some(sent) => sent,
+            None => return PongStatus::Unknown,
+        };
+        let rtt = clock.now() - sent;
+        if self.pong_counts.get(&pong.source).is_none() {
+            self.pong_counts.put(pong.source.clone(), (0, 0));
+        }
+        let (matched, late) = self.pong_counts.get_mut(&pong.source).unwrap();
+        if rtt > self.ping_timeout {
+            *late += 1;
+            PongStatus::Late
+        } else {
+            *matched += 1;
+            if let Some(stats) = self.rtt_stats.get_mut(&pong.source) {
+                stats.add(rtt);
+            } else {
+                self.rtt_stats.put(pong.source.clone(), RttStats::new(rtt));
+            }
+            PongStatus::Matched(rtt)
+        }
+    }
+
+    /// Drops the pings sent to `peer_id`, its pong counts and its round robin nonce, e.g.
+    /// once it disconnects, so that they don't take cache slots of active peers. A pong
+    /// for a ping sent before won't be matched anymore.
+    pub(crate) fn forget_peer_liveness(&mut self, peer_id: &PeerId) {
+        self.waiting_pong.pop(peer_id);
+        self.pong_counts.pop(peer_id);
+        self.rtt_stats.pop(peer_id);
+        self.route_nonce.pop(peer_id);
+    }
+
+    /// Round trip times of the pongs received from `peer_id` within `ping_timeout`.
+    pub(crate) fn rtt_stats(&self, peer_id: &PeerId) -> Option<RttStats> {
+        self.rtt_stats.peek(peer_id).cloned()
+    }
+
+    /// Round trip times, ping loss and reachability of `peer_id` combined into a single
+    /// score, e.g. for dashboards. Returns None if we know nothing about the peer.
+    pub(crate) fn peer_health(&self, clock: &time::Clock, peer_id: &PeerId) -> Option<PeerHealth> {
+        let rtt = self.rtt_stats(peer_id);
+        let ping_loss = self.ping_loss(clock, peer_id);
+        let next_hops = self.peer_forwarding.get(peer_id);
+        let reachable = next_hops.map_or(false, |next_hops| !next_hops.is_empty());
+        let direct = next_hops.map_or(false, |next_hops| next_hops.contains(peer_id));
+        if rtt.is_none() && ping_loss.is_none() && !reachable {
+            return None;
+        }
+        let score = if reachable {
+            let delivery = ping_loss.map_or(0.5, |loss| 1. - loss);
+            let latency = rtt.map_or(0.5, |rtt| {
+                let ratio = rtt.mean().as_seconds_f64() / self.ping_timeout.as_seconds_f64();
+                (1. - ratio).max(0.)
+            });
+            let direct = if direct { 1. } else { 0. };
+            (30. + 10. * direct + 40. * delivery + 20. * latency).round() as u8
+        } else {
+            0
+        };
+        Some(PeerHealth { rtt, ping_loss, reachable, direct, score })
+    }
+
+    /// Fraction of pings sent to `peer_id` which didn't get a pong within `ping_timeout`.
+    /// Pings sent less than `ping_timeout` ago are not taken into account.
+    /// Returns None if there are no pings to compute the loss from.
+    pub(crate) fn ping_loss(&self, clock: &time::Clock, peer_id: &PeerId) -> Option<f64> {
+        let now = clock.now();
+        let overdue = self.waiting_pong.peek(peer_id).map_or(0, |pings| {
+            pings.iter().filter(|(_, &sent)| now - sent > self.ping_timeout).count()
+        });
+        let (matched, late) = self.pong_counts.peek(peer_id).cloned().unwrap_or_default();
+        let total = matched + late + overdue;
+        if total == 0 {
+            return None;
+        }
+        Some((late + overdue) as f64 / total as f64)
+    }
+
+    /// Peers which haven't answered a ping sent more than `timeout` ago, sorted. Unlike
+    /// `ping_timeout`, the timeout is up to the caller, e.g. to disconnect unresponsive peers.
+    pub(crate) fn overdue_pings(
+        &self,
+        clock: &time::Clock,
+        timeout: time::Duration,
+    ) -> Vec<PeerId> {
+        let now = clock.now();
+        let mut peers: Vec<PeerId> = self
+            .waiting_pong
+            .iter()
+            .filter(|(_, pings)| pings.iter().any(|(_, &sent)| now - sent > timeout))
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+        peers.sort();
+        peers
+    }
+
     pub(crate) fn info(&self) -> RoutingTableInfo {
         let account_peers = self
             .get_announce_accounts()
@@ -171,7 +577,11 @@ impl RoutingTableView {
                 (announce_account.account_id.clone(), announce_account.peer_id.clone())
             })
             .collect();
-        RoutingTableInfo { account_peers, peer_forwarding: self.peer_forwarding.clone() }
+        RoutingTableInfo {
+            account_peers,
+            peer_forwarding: self.peer_forwarding.clone(),
+            route_usage: self.route_usage(),
+        }
     }
 
     /// Public interface for `account_peers`.
@@ -208,10 +618,34 @@ impl RoutingTableView {
     pub(crate) fn get_local_edge(&self, other_peer: &PeerId) -> Option<&Edge> {
         self.local_edges_info.get(other_peer)
     }
+
+    /// Get the local edge between `peer0` and `peer1`, in any order. Unlike `get_local_edge`
+    /// this doesn't assume that one of the peers is us: for a pair which isn't a local edge,
+    /// `None` is returned. Meant for callers which may be handed arbitrary pairs, e.g. edges
+    /// received from peers or read-only tooling.
+    pub(crate) fn try_get_local_edge(&self, peer0: &PeerId, peer1: &PeerId) -> Option<&Edge> {
+        (self.local_edges_info.get(peer0)).or_else(|| self.local_edges_info.get(peer1)).filter(
+            |edge| {
+                let (key0, key1) = edge.key();
+                (key0, key1) == Edge::key_order(peer0, peer1)
+            },
+        )
+    }
+
+    /// Checks whether `edge` is not older than the local edge we store for its key, i.e. whether
+    /// it isn't a replay of a stale edge. An edge we don't have a local edge for is considered
+    /// current. Counterpart of `is_local_edge_newer` which also accepts the same nonce.
+    pub(crate) fn edge_is_current(&self, edge: &Edge) -> bool {
+        let (peer0, peer1) = edge.key();
+        self.try_get_local_edge(peer0, peer1).map_or(true, |local| local.nonce() <= edge.nonce())
+    }
 }
 
 #[derive(Debug)]
 pub struct RoutingTableInfo {
     pub account_peers: HashMap<AccountId, PeerId>,
     pub peer_forwarding: Arc<HashMap<PeerId, Vec<PeerId>>>,
+    /// Approximate number of messages routed through each next hop, see
+    /// `RoutingTableView::route_usage`.
+    pub route_usage: HashMap<PeerId, usize>,
 }
@@ -1,12 +1,12 @@
 use crate::network_protocol::Encoding;
 use near_metrics::{
-    exponential_buckets, try_create_histogram, try_create_histogram_vec, try_create_int_counter,
-    try_create_int_counter_vec, try_create_int_gauge, Histogram, HistogramVec, IntCounter,
-    IntCounterVec, IntGauge, IntGaugeVec,
+    exponential_buckets, try_create_gauge, try_create_histogram, try_create_histogram_vec,
+    try_create_int_counter, try_create_int_counter_vec, try_create_int_gauge, Gauge, Histogram,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
-use near_network_primitives::types::{PeerType, RoutedMessageBody};
+use near_network_primitives::types::{PeerType, RoutedMessageBody, TrustLevel};
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 static PEER_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
     near_metrics::try_create_int_gauge_vec(
@@ -25,6 +25,42 @@ pub fn set_peer_connections(values: HashMap<(PeerType, Option<Encoding>), i64>)
     }
 }
 
+static PEER_STORE_ADDRESSES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge_vec(
+        "near_peer_store_addresses",
+        "Number of known peer addresses by trust level",
+        &["trust_level"],
+    )
+    .unwrap()
+});
+
+pub fn set_peer_store_addresses(counts: HashMap<TrustLevel, usize>) {
+    for level in [TrustLevel::Indirect, TrustLevel::Direct, TrustLevel::Signed] {
+        let label = match level {
+            TrustLevel::Indirect => "indirect",
+            TrustLevel::Direct => "direct",
+            TrustLevel::Signed => "signed",
+        };
+        let count = counts.get(&level).copied().unwrap_or(0);
+        PEER_STORE_ADDRESSES.with_label_values(&[label]).set(count as i64);
+    }
+}
+
+static PEER_STORE_LAST_SEEN: Lazy<IntGaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge_vec(
+        "near_peer_store_last_seen",
+        "Number of known peers by the time since they were last seen",
+        &["last_seen"],
+    )
+    .unwrap()
+});
+
+pub fn set_peer_store_last_seen(histogram: BTreeMap<&'static str, usize>) {
+    for (bucket, count) in histogram {
+        PEER_STORE_LAST_SEEN.with_label_values(&[bucket]).set(count as i64);
+    }
+}
+
 #[cfg(feature = "test_features")]
 use std::sync::{Arc, Mutex};
 
@@ -97,6 +133,44 @@ pub static ROUTING_TABLE_RECALCULATION_HISTOGRAM: Lazy<Histogram> = Lazy::new(||
     )
     .unwrap()
 });
+pub static PEER_STORE_PEERS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge("near_peer_store_peers", "Number of peers known to the peer store")
+        .unwrap()
+});
+pub static PEER_STORE_SIZE_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge("near_peer_store_size_bytes", "Approximate size of the peer store")
+        .unwrap()
+});
+pub static PEER_STORE_ADDRESSLESS_PEERS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_peer_store_addressless_peers",
+        "Number of known peers which aren't banned, but whose address is unknown",
+    )
+    .unwrap()
+});
+pub static ROUTE_BACK_CACHE_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge("near_route_back_cache_size", "Number of entries in the route back cache")
+        .unwrap()
+});
+pub static ROUTE_BACK_CACHE_HITS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge("near_route_back_cache_hits", "Route back lookups which found a peer")
+        .unwrap()
+});
+pub static ROUTE_BACK_CACHE_MISSES: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge("near_route_back_cache_misses", "Route back lookups which found no peer")
+        .unwrap()
+});
+pub static PEER_PING_LOSS: Lazy<Gauge> = Lazy::new(|| {
+    try_create_gauge("near_peer_ping_loss", "Mean fraction of pings lost by connected peers")
+        .unwrap()
+});
+pub static PEER_HEALTH_SCORE: Lazy<Gauge> = Lazy::new(|| {
+    try_create_gauge(
+        "near_peer_health_score",
+        "Mean health score of connected peers, between 0 and 100",
+    )
+    .unwrap()
+});
 pub static EDGE_UPDATES: Lazy<IntCounter> =
     Lazy::new(|| try_create_int_counter("near_edge_updates", "Unique edge updates").unwrap());
 pub static EDGE_ACTIVE: Lazy<IntGauge> = Lazy::new(|| {
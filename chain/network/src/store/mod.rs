@@ -145,4 +145,11 @@ impl Store {
     pub fn list_peer_states(&self) -> Result<Vec<(PeerId, KnownPeerState)>, Error> {
         self.0.iter::<schema::Peers>().collect::<Result<_, _>>().map_err(Error)
     }
+
+    /// Number of bytes of the Peers column row written by `set_peer_state`.
+    pub fn peer_state_size(peer_id: &PeerId, peer_state: &KnownPeerState) -> usize {
+        use schema::{Column, Peers};
+        schema::to_vec::<<Peers as Column>::Key>(peer_id).len()
+            + schema::to_vec::<<Peers as Column>::Value>(peer_state).len()
+    }
 }
@@ -60,6 +60,57 @@ impl From<KnownPeerStatus> for primitives::KnownPeerStatus {
     }
 }
 
+#[derive(BorshSerialize, BorshDeserialize)]
+enum PeerType {
+    Inbound,
+    Outbound,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+enum TrustLevel {
+    Indirect,
+    Direct,
+    Signed,
+}
+
+impl From<primitives::TrustLevel> for TrustLevel {
+    fn from(t: primitives::TrustLevel) -> Self {
+        match t {
+            primitives::TrustLevel::Indirect => Self::Indirect,
+            primitives::TrustLevel::Direct => Self::Direct,
+            primitives::TrustLevel::Signed => Self::Signed,
+        }
+    }
+}
+
+impl From<TrustLevel> for primitives::TrustLevel {
+    fn from(t: TrustLevel) -> primitives::TrustLevel {
+        match t {
+            TrustLevel::Indirect => primitives::TrustLevel::Indirect,
+            TrustLevel::Direct => primitives::TrustLevel::Direct,
+            TrustLevel::Signed => primitives::TrustLevel::Signed,
+        }
+    }
+}
+
+impl From<primitives::PeerType> for PeerType {
+    fn from(t: primitives::PeerType) -> Self {
+        match t {
+            primitives::PeerType::Inbound => Self::Inbound,
+            primitives::PeerType::Outbound => Self::Outbound,
+        }
+    }
+}
+
+impl From<PeerType> for primitives::PeerType {
+    fn from(t: PeerType) -> primitives::PeerType {
+        match t {
+            PeerType::Inbound => primitives::PeerType::Inbound,
+            PeerType::Outbound => primitives::PeerType::Outbound,
+        }
+    }
+}
+
 /// A Borsh representation of the primitives::KnownPeerState.
 /// TODO: Currently primitives::KnownPeerState implements Borsh serialization
 /// directly, but eventually direct serialization should be removed
@@ -67,13 +118,40 @@ impl From<KnownPeerStatus> for primitives::KnownPeerStatus {
 /// TODO: Currently primitives::KnownPeerState is identical
 /// to the KnownPeerStateRepr, but in the following PR the
 /// timestamp type (currently u64), will be replaced with time::Utc.
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize)]
 pub struct KnownPeerStateRepr {
     peer_info: primitives::PeerInfo,
     status: KnownPeerStatus,
     /// UNIX timestamps in nanos.
     first_seen: u64,
     last_seen: u64,
+    /// Added later, so it is missing in the entries written by older versions.
+    direction: Option<PeerType>,
+    /// Added later, entries written by older versions are read as `Indirect`.
+    trust_level: TrustLevel,
+    /// Added later, entries written by older versions are read as 0 for banned peers
+    /// and as the maximal reputation otherwise.
+    reputation: u32,
+}
+
+impl BorshDeserialize for KnownPeerStateRepr {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        let peer_info = BorshDeserialize::deserialize(buf)?;
+        let status: KnownPeerStatus = BorshDeserialize::deserialize(buf)?;
+        let first_seen = BorshDeserialize::deserialize(buf)?;
+        let last_seen = BorshDeserialize::deserialize(buf)?;
+        let direction = if buf.is_empty() { None } else { BorshDeserialize::deserialize(buf)? };
+        let trust_level =
+            if buf.is_empty() { TrustLevel::Indirect } else { BorshDeserialize::deserialize(buf)? };
+        let reputation = if !buf.is_empty() {
+            BorshDeserialize::deserialize(buf)?
+        } else if let KnownPeerStatus::Banned(_, _) = status {
+            0
+        } else {
+            primitives::KnownPeerState::MAX_REPUTATION
+        };
+        Ok(Self { peer_info, status, first_seen, last_seen, direction, trust_level, reputation })
+    }
 }
 
 impl BorshRepr for KnownPeerStateRepr {
@@ -84,6 +162,9 @@ impl BorshRepr for KnownPeerStateRepr {
             status: s.status.clone().into(),
             first_seen: s.first_seen.unix_timestamp_nanos() as u64,
             last_seen: s.last_seen.unix_timestamp_nanos() as u64,
+            direction: s.direction.map(Into::into),
+            trust_level: s.trust_level.clone().into(),
+            reputation: s.reputation,
         }
     }
 
@@ -95,6 +176,9 @@ impl BorshRepr for KnownPeerStateRepr {
                 .map_err(invalid_data)?,
             last_seen: time::Utc::from_unix_timestamp_nanos(s.last_seen as i128)
                 .map_err(invalid_data)?,
+            direction: s.direction.map(Into::into),
+            trust_level: s.trust_level.into(),
+            reputation: s.reputation,
         })
     }
 }
@@ -180,7 +264,7 @@ pub trait Format {
     fn decode(a: &[u8]) -> io::Result<Self::T>;
 }
 
-fn to_vec<F: Format>(a: &F::T) -> Vec<u8> {
+pub fn to_vec<F: Format>(a: &F::T) -> Vec<u8> {
     let mut out = Vec::new();
     F::encode(a, &mut out).unwrap();
     out
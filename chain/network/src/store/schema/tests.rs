@@ -11,3 +11,43 @@ fn borsh_wrapper_is_transparent() {
     let e = data::make_edge(rng, &s1, &s2);
     assert_eq!(Borsh(e.clone()).try_to_vec().unwrap(), e.try_to_vec().unwrap());
 }
+
+#[test]
+fn known_peer_state_without_trust_level() {
+    let mut rng = util::make_rng(423423);
+    let peer_info = data::make_peer_info(&mut rng);
+    // Layout written before `trust_level` was added.
+    let legacy =
+        (peer_info.clone(), KnownPeerStatus::NotConnected, 1u64, 2u64, Some(PeerType::Inbound));
+    let s = KnownPeerStateRepr::decode(&legacy.try_to_vec().unwrap()).unwrap();
+    assert_eq!(s.peer_info, peer_info);
+    assert_eq!(s.direction, Some(primitives::PeerType::Inbound));
+    assert_eq!(s.trust_level, primitives::TrustLevel::Indirect);
+
+    let s = primitives::KnownPeerState { trust_level: primitives::TrustLevel::Signed, ..s };
+    let s = KnownPeerStateRepr::decode(&to_vec::<KnownPeerStateRepr>(&s)).unwrap();
+    assert_eq!(s.trust_level, primitives::TrustLevel::Signed);
+}
+
+#[test]
+fn known_peer_state_without_reputation() {
+    let mut rng = util::make_rng(423423);
+    let peer_info = data::make_peer_info(&mut rng);
+    let max = primitives::KnownPeerState::MAX_REPUTATION;
+    // Layout written before `reputation` was added.
+    let legacy = |status: KnownPeerStatus| {
+        (peer_info.clone(), status, 1u64, 2u64, None::<PeerType>, TrustLevel::Direct)
+            .try_to_vec()
+            .unwrap()
+    };
+    let s = KnownPeerStateRepr::decode(&legacy(KnownPeerStatus::NotConnected)).unwrap();
+    assert_eq!(s.trust_level, primitives::TrustLevel::Direct);
+    assert_eq!(s.reputation, max);
+    let banned = KnownPeerStatus::Banned(primitives::ReasonForBan::Abusive, 3);
+    let s = KnownPeerStateRepr::decode(&legacy(banned)).unwrap();
+    assert_eq!(s.reputation, 0);
+
+    let s = primitives::KnownPeerState { reputation: max / 2, ..s };
+    let s = KnownPeerStateRepr::decode(&to_vec::<KnownPeerStateRepr>(&s)).unwrap();
+    assert_eq!(s.reputation, max / 2);
+}
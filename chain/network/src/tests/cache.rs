@@ -1,9 +1,19 @@
-use crate::routing::routing_table_view::RoutingTableView;
+use crate::routing::routing_table_view::{
+    FindRouteError, PongStatus, RouteBackStats, RoutingStateSnapshot, RoutingTableConfig,
+    RoutingTableView, RttStats,
+};
 use crate::store;
 use crate::test_utils::{random_epoch_id, random_peer_id};
+use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::Signature;
+use near_network_primitives::time;
+use near_network_primitives::types::{Edge, PeerIdOrHash, Pong};
+use near_primitives::hash::hash;
 use near_primitives::network::AnnounceAccount;
+use near_primitives::types::AccountId;
 use near_store::test_utils::create_test_store;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[test]
 fn announcement_same_epoch() {
@@ -13,7 +23,7 @@ fn announcement_same_epoch() {
     let peer_id1 = random_peer_id();
     let epoch_id0 = random_epoch_id();
 
-    let mut routing_table = RoutingTableView::new(store);
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
 
     let announce0 = AnnounceAccount {
         account_id: "near0".parse().unwrap(),
@@ -49,7 +59,8 @@ fn dont_load_on_build() {
     let epoch_id0 = random_epoch_id();
     let epoch_id1 = random_epoch_id();
 
-    let mut routing_table = RoutingTableView::new(store::Store::new(store.clone()));
+    let mut routing_table =
+        RoutingTableView::new(store::Store::new(store.clone()), RoutingTableConfig::default());
 
     let announce0 = AnnounceAccount {
         account_id: "near0".parse().unwrap(),
@@ -72,7 +83,8 @@ fn dont_load_on_build() {
     assert!(vec![announce0, announce1].iter().all(|announce| { accounts.contains(&announce) }));
     assert_eq!(accounts.len(), 2);
 
-    let routing_table1 = RoutingTableView::new(store::Store::new(store));
+    let routing_table1 =
+        RoutingTableView::new(store::Store::new(store), RoutingTableConfig::default());
     assert_eq!(routing_table1.get_announce_accounts().count(), 0);
 }
 
@@ -83,8 +95,10 @@ fn load_from_disk() {
     let peer_id0 = random_peer_id();
     let epoch_id0 = random_epoch_id();
 
-    let mut routing_table = RoutingTableView::new(store::Store::new(store.clone()));
-    let mut routing_table1 = RoutingTableView::new(store::Store::new(store));
+    let mut routing_table =
+        RoutingTableView::new(store::Store::new(store.clone()), RoutingTableConfig::default());
+    let mut routing_table1 =
+        RoutingTableView::new(store::Store::new(store), RoutingTableConfig::default());
 
     let announce0 = AnnounceAccount {
         account_id: "near0".parse().unwrap(),
@@ -103,3 +117,497 @@ fn load_from_disk() {
     // Cache of second routing table should contain account loaded from disk
     assert_eq!(routing_table1.get_announce_accounts().count(), 1);
 }
+
+#[test]
+fn export_import_state() {
+    let clock = time::FakeClock::default();
+    let mut routing_table = RoutingTableView::new(
+        store::Store::new(create_test_store()),
+        RoutingTableConfig::default(),
+    );
+
+    let me = random_peer_id();
+    for _ in 0..3 {
+        let other = random_peer_id();
+        let (peer0, peer1) = Edge::make_key(me.clone(), other.clone());
+        routing_table.local_edges_info.insert(other, Edge::make_fake_edge(peer0, peer1, 1));
+    }
+    for i in 0..3 {
+        routing_table.add_account(AnnounceAccount {
+            account_id: format!("near{}", i).parse().unwrap(),
+            peer_id: random_peer_id(),
+            epoch_id: random_epoch_id(),
+            signature: Signature::default(),
+        });
+    }
+    let route_back_peer = random_peer_id();
+    for i in 0..2 {
+        routing_table.add_route_back(&clock.clock(), hash(&[i]), route_back_peer.clone());
+        clock.advance(time::Duration::seconds(1));
+    }
+
+    let snapshot = routing_table.export_state();
+    let bytes = snapshot.try_to_vec().unwrap();
+    assert_eq!(RoutingStateSnapshot::try_from_slice(&bytes).unwrap(), snapshot);
+
+    // Simulate a restart with an empty store.
+    let mut restored = RoutingTableView::new(
+        store::Store::new(create_test_store()),
+        RoutingTableConfig::default(),
+    );
+    restored.import_state(&clock.clock(), snapshot);
+    assert_eq!(restored.local_edges_info, routing_table.local_edges_info);
+    assert_eq!(
+        restored.get_announce_accounts().collect::<Vec<_>>(),
+        routing_table.get_announce_accounts().collect::<Vec<_>>()
+    );
+    assert!(restored.compare_route_back(hash(&[0]), &route_back_peer));
+    assert!(restored.compare_route_back(hash(&[1]), &route_back_peer));
+
+    // Announcements are persisted, not only cached.
+    let account_id = "near0".parse().unwrap();
+    restored.import_state(
+        &clock.clock(),
+        RoutingStateSnapshot { local_edges: vec![], accounts: vec![], route_back: vec![] },
+    );
+    assert!(restored.local_edges_info.is_empty());
+    assert_eq!(restored.get_announce_accounts().count(), 0);
+    assert_eq!(
+        restored.account_owner(&account_id).unwrap(),
+        routing_table.account_owner(&account_id).unwrap()
+    );
+}
+
+#[test]
+fn route_back_rate_limit() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(
+        store,
+        RoutingTableConfig {
+            route_back_limit: 2,
+            route_back_window: time::Duration::seconds(1),
+            ..RoutingTableConfig::default()
+        },
+    );
+
+    let peer_id0 = random_peer_id();
+    let peer_id1 = random_peer_id();
+
+    assert!(routing_table.add_route_back(&clock.clock(), hash(&[0]), peer_id0.clone()));
+    assert!(routing_table.add_route_back(&clock.clock(), hash(&[1]), peer_id0.clone()));
+    // peer_id0 exceeded its rate, while peer_id1 is not affected.
+    assert!(!routing_table.add_route_back(&clock.clock(), hash(&[2]), peer_id0.clone()));
+    assert!(routing_table.add_route_back(&clock.clock(), hash(&[3]), peer_id1.clone()));
+    assert!(routing_table.compare_route_back(hash(&[1]), &peer_id0));
+    assert!(!routing_table.compare_route_back(hash(&[2]), &peer_id0));
+
+    // Limit is lifted once the window passes.
+    clock.advance(time::Duration::seconds(2));
+    assert!(routing_table.add_route_back(&clock.clock(), hash(&[4]), peer_id0.clone()));
+}
+
+#[test]
+fn late_pong() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(
+        store,
+        RoutingTableConfig {
+            ping_timeout: time::Duration::seconds(5),
+            ..RoutingTableConfig::default()
+        },
+    );
+
+    let peer_id = random_peer_id();
+    let pong = |nonce| Pong { nonce, source: peer_id.clone() };
+
+    routing_table.sending_ping(&clock.clock(), 0, peer_id.clone());
+    clock.advance(time::Duration::seconds(1));
+    assert_eq!(
+        routing_table.add_pong(&clock.clock(), &pong(0)),
+        PongStatus::Matched(time::Duration::seconds(1))
+    );
+    // Pong for a ping we have never sent.
+    assert_eq!(routing_table.add_pong(&clock.clock(), &pong(1)), PongStatus::Unknown);
+
+    routing_table.sending_ping(&clock.clock(), 2, peer_id.clone());
+    clock.advance(time::Duration::seconds(6));
+    assert_eq!(routing_table.ping_loss(&clock.clock(), &peer_id), Some(0.5));
+    assert_eq!(routing_table.add_pong(&clock.clock(), &pong(2)), PongStatus::Late);
+    assert_eq!(routing_table.ping_loss(&clock.clock(), &peer_id), Some(0.5));
+}
+
+#[test]
+fn overdue_pings() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+    let timeout = time::Duration::seconds(5);
+
+    let peer_id = random_peer_id();
+    let other = random_peer_id();
+    routing_table.sending_ping(&clock.clock(), 0, peer_id.clone());
+    assert!(routing_table.overdue_pings(&clock.clock(), timeout).is_empty());
+
+    clock.advance(time::Duration::seconds(3));
+    routing_table.sending_ping(&clock.clock(), 1, peer_id.clone());
+    routing_table.sending_ping(&clock.clock(), 0, other.clone());
+    clock.advance(time::Duration::seconds(3));
+    assert_eq!(routing_table.overdue_pings(&clock.clock(), timeout), vec![peer_id.clone()]);
+
+    // Answering the oldest ping leaves only a ping which is not overdue yet.
+    routing_table.add_pong(&clock.clock(), &Pong { nonce: 0, source: peer_id.clone() });
+    assert!(routing_table.overdue_pings(&clock.clock(), timeout).is_empty());
+
+    clock.advance(time::Duration::seconds(3));
+    let mut expected = vec![peer_id.clone(), other.clone()];
+    expected.sort();
+    assert_eq!(routing_table.overdue_pings(&clock.clock(), timeout), expected);
+}
+
+#[test]
+fn overdue_pings_forgotten_on_disconnect() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+    let timeout = time::Duration::seconds(5);
+
+    let peer_id = random_peer_id();
+    let other = random_peer_id();
+    routing_table.sending_ping(&clock.clock(), 0, peer_id.clone());
+    routing_table.sending_ping(&clock.clock(), 0, other.clone());
+    clock.advance(time::Duration::seconds(6));
+    let mut expected = vec![peer_id.clone(), other.clone()];
+    expected.sort();
+    assert_eq!(routing_table.overdue_pings(&clock.clock(), timeout), expected);
+
+    // The peer manager forgets the pings of a peer it disconnected from, so that it doesn't
+    // disconnect the peer again as soon as it reconnects.
+    routing_table.forget_peer_liveness(&peer_id);
+    assert_eq!(routing_table.overdue_pings(&clock.clock(), timeout), vec![other.clone()]);
+    routing_table.sending_ping(&clock.clock(), 1, peer_id);
+    assert_eq!(routing_table.overdue_pings(&clock.clock(), timeout), vec![other]);
+}
+
+#[test]
+fn peer_health() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+
+    let good = random_peer_id();
+    let lossy = random_peer_id();
+    let unreachable = random_peer_id();
+    assert_eq!(routing_table.peer_health(&clock.clock(), &good), None);
+    routing_table.peer_forwarding = Arc::new(HashMap::from([
+        (good.clone(), vec![good.clone()]),
+        (lossy.clone(), vec![lossy.clone()]),
+    ]));
+
+    for nonce in 0..4 {
+        for peer_id in [&good, &lossy, &unreachable] {
+            routing_table.sending_ping(&clock.clock(), nonce, peer_id.clone());
+        }
+    }
+    clock.advance(time::Duration::milliseconds(100));
+    for nonce in 0..4 {
+        routing_table.add_pong(&clock.clock(), &Pong { nonce, source: good.clone() });
+    }
+    routing_table.add_pong(&clock.clock(), &Pong { nonce: 0, source: lossy.clone() });
+    // The remaining pings are lost.
+    clock.advance(time::Duration::seconds(20));
+
+    let rtt = time::Duration::milliseconds(100);
+    let good_health = routing_table.peer_health(&clock.clock(), &good).unwrap();
+    assert_eq!(good_health.rtt, Some(RttStats { count: 4, min: rtt, max: rtt, total: rtt * 4 }));
+    assert_eq!(good_health.ping_loss, Some(0.));
+    assert!(good_health.reachable && good_health.direct);
+    assert_eq!(good_health.score, 100);
+
+    let lossy_health = routing_table.peer_health(&clock.clock(), &lossy).unwrap();
+    assert_eq!(lossy_health.ping_loss, Some(0.75));
+    assert_eq!(lossy_health.score, 70);
+    assert!(lossy_health.score < good_health.score);
+
+    let unreachable_health = routing_table.peer_health(&clock.clock(), &unreachable).unwrap();
+    assert!(!unreachable_health.reachable);
+    assert_eq!(unreachable_health.score, 0);
+
+    routing_table.forget_peer_liveness(&good);
+    assert_eq!(routing_table.rtt_stats(&good), None);
+}
+
+#[test]
+fn deterministic_routing() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(
+        store,
+        RoutingTableConfig { deterministic_routing: true, ..RoutingTableConfig::default() },
+    );
+
+    let target = random_peer_id();
+    let mut next_hops: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+    routing_table.peer_forwarding = Arc::new(HashMap::from([(target.clone(), next_hops.clone())]));
+    next_hops.sort();
+    for _ in 0..5 {
+        let next_hop =
+            routing_table.find_route(&clock.clock(), &PeerIdOrHash::PeerId(target.clone()));
+        assert_eq!(next_hop.unwrap(), next_hops[0]);
+    }
+    // The round robin bookkeeping is bypassed.
+    assert!(routing_table.route_usage().is_empty());
+}
+
+#[test]
+fn preview_account_route() {
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+
+    let validator = random_peer_id();
+    let relay = random_peer_id();
+    let account_id: AccountId = "validator.near".parse().unwrap();
+    assert!(matches!(
+        routing_table.preview_account_route(&account_id),
+        Err(FindRouteError::AccountNotFound)
+    ));
+
+    routing_table.add_account(AnnounceAccount {
+        account_id: account_id.clone(),
+        peer_id: validator.clone(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    });
+    assert!(matches!(
+        routing_table.preview_account_route(&account_id),
+        Err(FindRouteError::PeerNotFound)
+    ));
+
+    // The validator is only reachable through the relay.
+    routing_table.peer_forwarding = Arc::new(HashMap::from([
+        (relay.clone(), vec![relay.clone()]),
+        (validator.clone(), vec![relay.clone()]),
+    ]));
+    let (owner, next_hops) = routing_table.preview_account_route(&account_id).unwrap();
+    assert_eq!(owner, validator);
+    assert_eq!(next_hops, vec![relay]);
+    // Previewing doesn't count as routing a message.
+    assert!(routing_table.route_usage().is_empty());
+}
+
+#[test]
+fn forget_peer_liveness() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+
+    let peer_id = random_peer_id();
+    let other = random_peer_id();
+    let pong = |nonce, source: &PeerId| Pong { nonce, source: source.clone() };
+
+    routing_table.sending_ping(&clock.clock(), 0, peer_id.clone());
+    routing_table.sending_ping(&clock.clock(), 1, peer_id.clone());
+    routing_table.sending_ping(&clock.clock(), 0, other.clone());
+    routing_table.peer_forwarding =
+        Arc::new(HashMap::from([(other.clone(), vec![peer_id.clone(), other.clone()])]));
+    routing_table.find_route(&clock.clock(), &PeerIdOrHash::PeerId(other.clone())).unwrap();
+    routing_table.find_route(&clock.clock(), &PeerIdOrHash::PeerId(other.clone())).unwrap();
+    assert!(matches!(
+        routing_table.add_pong(&clock.clock(), &pong(0, &peer_id)),
+        PongStatus::Matched(_)
+    ));
+
+    routing_table.forget_peer_liveness(&peer_id);
+    assert_eq!(routing_table.add_pong(&clock.clock(), &pong(1, &peer_id)), PongStatus::Unknown);
+    assert_eq!(routing_table.ping_loss(&clock.clock(), &peer_id), None);
+    assert_eq!(routing_table.route_usage(), HashMap::from([(other.clone(), 1)]));
+    // Other peers are not affected.
+    assert!(matches!(
+        routing_table.add_pong(&clock.clock(), &pong(0, &other)),
+        PongStatus::Matched(_)
+    ));
+}
+
+#[test]
+fn filter_newer_local_edges() {
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+
+    let me = random_peer_id();
+    let peers: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+    let edge = |other: usize, nonce| {
+        let (peer0, peer1) = Edge::make_key(me.clone(), peers[other].clone());
+        Edge::make_fake_edge(peer0, peer1, nonce)
+    };
+    routing_table.local_edges_info.insert(peers[0].clone(), edge(0, 3));
+    routing_table.local_edges_info.insert(peers[1].clone(), edge(1, 5));
+
+    let candidates = [edge(0, 5), edge(1, 5), edge(0, 1), edge(2, 1), edge(1, 6)];
+    assert_eq!(routing_table.filter_newer_local_edges(&candidates), vec![0, 3, 4]);
+    for (i, candidate) in candidates.iter().enumerate() {
+        let other = candidate.other(&me).unwrap();
+        assert_eq!(
+            routing_table.is_local_edge_newer(other, candidate.nonce()),
+            [0, 3, 4].contains(&i)
+        );
+    }
+}
+
+#[test]
+fn try_get_local_edge() {
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+
+    let me = random_peer_id();
+    let peers: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+    let (peer0, peer1) = Edge::make_key(me.clone(), peers[0].clone());
+    let edge = Edge::make_fake_edge(peer0, peer1, 1);
+    routing_table.local_edges_info.insert(peers[0].clone(), edge.clone());
+
+    assert_eq!(routing_table.try_get_local_edge(&me, &peers[0]), Some(&edge));
+    assert_eq!(routing_table.try_get_local_edge(&peers[0], &me), Some(&edge));
+    // Neither peer is us, even though one of them has a local edge.
+    assert_eq!(routing_table.try_get_local_edge(&peers[0], &peers[1]), None);
+    assert_eq!(routing_table.try_get_local_edge(&peers[1], &peers[2]), None);
+    assert_eq!(routing_table.try_get_local_edge(&me, &peers[1]), None);
+}
+
+#[test]
+fn edge_is_current() {
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+
+    let me = random_peer_id();
+    let other = random_peer_id();
+    let (peer0, peer1) = Edge::make_key(me.clone(), other.clone());
+    routing_table
+        .local_edges_info
+        .insert(other.clone(), Edge::make_fake_edge(peer0.clone(), peer1.clone(), 3));
+
+    assert!(!routing_table.edge_is_current(&Edge::make_fake_edge(peer0.clone(), peer1.clone(), 1)));
+    assert!(routing_table.edge_is_current(&Edge::make_fake_edge(peer0.clone(), peer1.clone(), 3)));
+    assert!(routing_table.edge_is_current(&Edge::make_fake_edge(peer0, peer1, 5)));
+    // No local edge for this key.
+    let (peer0, peer1) = Edge::make_key(me, random_peer_id());
+    assert!(routing_table.edge_is_current(&Edge::make_fake_edge(peer0, peer1, 1)));
+}
+
+#[test]
+fn custom_cache_sizes() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let config = RoutingTableConfig {
+        announce_account_cache_size: 2,
+        ping_pong_cache_size: 1,
+        ..RoutingTableConfig::default()
+    };
+    let mut routing_table = RoutingTableView::new(store, config);
+
+    for i in 0..3 {
+        routing_table.add_account(AnnounceAccount {
+            account_id: format!("near{}", i).parse().unwrap(),
+            peer_id: random_peer_id(),
+            epoch_id: random_epoch_id(),
+            signature: Signature::default(),
+        });
+    }
+    assert_eq!(routing_table.get_announce_accounts().count(), 2);
+
+    // Pings to the first peer are evicted by pings to the second one.
+    let peer_id0 = random_peer_id();
+    let peer_id1 = random_peer_id();
+    routing_table.sending_ping(&clock.clock(), 0, peer_id0.clone());
+    routing_table.sending_ping(&clock.clock(), 1, peer_id1.clone());
+    let pong0 = Pong { nonce: 0, source: peer_id0 };
+    let pong1 = Pong { nonce: 1, source: peer_id1 };
+    assert_eq!(routing_table.add_pong(&clock.clock(), &pong0), PongStatus::Unknown);
+    assert_eq!(
+        routing_table.add_pong(&clock.clock(), &pong1),
+        PongStatus::Matched(time::Duration::ZERO)
+    );
+}
+
+#[test]
+fn route_usage() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+
+    let next_hops: Vec<_> = (0..2).map(|_| random_peer_id()).collect();
+    let target = random_peer_id();
+    let other_target = random_peer_id();
+    routing_table.peer_forwarding = Arc::new(HashMap::from([
+        (target.clone(), next_hops.clone()),
+        (other_target.clone(), vec![next_hops[0].clone()]),
+    ]));
+    assert!(routing_table.route_usage().is_empty());
+
+    for _ in 0..4 {
+        routing_table.find_route(&clock.clock(), &PeerIdOrHash::PeerId(target.clone())).unwrap();
+    }
+    let expected = HashMap::from([(next_hops[0].clone(), 2), (next_hops[1].clone(), 2)]);
+    assert_eq!(routing_table.route_usage(), expected);
+
+    routing_table.find_route(&clock.clock(), &PeerIdOrHash::PeerId(other_target)).unwrap();
+    let expected = HashMap::from([(next_hops[0].clone(), 3), (next_hops[1].clone(), 2)]);
+    assert_eq!(routing_table.route_usage(), expected);
+}
+
+#[test]
+fn route_back_stats() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let mut routing_table = RoutingTableView::new(store, RoutingTableConfig::default());
+    let stats = |len, hits, misses| RouteBackStats { len, hits, misses };
+    assert_eq!(routing_table.route_back_stats(), stats(0, 0, 0));
+
+    let peer_id = random_peer_id();
+    for i in 0..3 {
+        assert!(routing_table.add_route_back(&clock.clock(), hash(&[i]), peer_id.clone()));
+    }
+    assert_eq!(routing_table.route_back_stats(), stats(3, 0, 0));
+
+    let route = |routing_table: &mut RoutingTableView, i: u8| {
+        routing_table.find_route(&clock.clock(), &PeerIdOrHash::Hash(hash(&[i])))
+    };
+    assert_eq!(route(&mut routing_table, 0).unwrap(), peer_id);
+    assert!(route(&mut routing_table, 5).is_err());
+    assert_eq!(routing_table.route_back_stats(), stats(2, 1, 1));
+
+    // Fetching removes the entry, so the second lookup misses.
+    assert!(route(&mut routing_table, 0).is_err());
+    assert_eq!(route(&mut routing_table, 1).unwrap(), peer_id);
+    assert_eq!(routing_table.route_back_stats(), stats(1, 2, 2));
+}
+
+#[test]
+fn max_next_hops() {
+    let clock = time::FakeClock::default();
+    let store = store::Store::new(create_test_store());
+    let config = RoutingTableConfig { max_next_hops: Some(2), ..RoutingTableConfig::default() };
+    let mut routing_table = RoutingTableView::new(store, config);
+
+    let next_hops: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+    let target = random_peer_id();
+    let other_target = random_peer_id();
+    let peer_forwarding = Arc::new(HashMap::from([
+        (target.clone(), next_hops.clone()),
+        (other_target.clone(), vec![next_hops[3].clone()]),
+    ]));
+    routing_table.set_peer_forwarding(peer_forwarding.clone());
+    assert!(routing_table.peer_forwarding.values().all(|hops| hops.len() <= 2));
+    assert_eq!(routing_table.view_route(&target).unwrap().len(), 2);
+    assert_eq!(routing_table.view_route(&other_target).unwrap(), &vec![next_hops[3].clone()]);
+
+    // Use the kept next hops, the others are preferred by the next update.
+    let used: Vec<_> = (0..2)
+        .map(|_| {
+            routing_table.find_route(&clock.clock(), &PeerIdOrHash::PeerId(target.clone())).unwrap()
+        })
+        .collect();
+    routing_table.set_peer_forwarding(peer_forwarding);
+    let kept = routing_table.view_route(&target).unwrap();
+    assert_eq!(kept.len(), 2);
+    assert!(kept.iter().all(|peer_id| !used.contains(peer_id)));
+}
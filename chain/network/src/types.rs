@@ -118,6 +118,16 @@ pub enum PeerManagerMessageRequest {
     InboundTcpConnect(InboundTcpConnect),
     Unregister(Unregister),
     Ban(Ban),
+    /// Removes a peer which isn't connected from the peer store right away, instead of
+    /// waiting for it to expire, e.g. a node which is known to be shut down for good.
+    ForgetPeer(PeerId),
+    /// Adds boot nodes without restarting the node, see `PeerStore::add_boot_nodes`.
+    AddBootNodes(Vec<PeerInfo>),
+    /// Dumps the peer store as JSON, see `PeerStore::to_json`.
+    ExportPeerStore,
+    /// Replaces the peer store with one dumped by `ExportPeerStore`, e.g. to reproduce the
+    /// peers of another node while debugging. Refused while there are connections.
+    ImportPeerStore(String),
     /// TEST-ONLY
     SetAdvOptions(crate::test_utils::SetAdvOptions),
     /// TEST-ONLY allows for modifying the internal routing table.
@@ -159,6 +169,13 @@ pub enum PeerManagerMessageResponse {
     InboundTcpConnect(()),
     Unregister(()),
     Ban(()),
+    /// Whether the peer was removed from the peer store.
+    ForgetPeer(bool),
+    /// Whether the boot nodes were added.
+    AddBootNodes(bool),
+    ExportPeerStore(String),
+    /// Whether the peer store was replaced.
+    ImportPeerStore(bool),
     /// TEST-ONLY
     SetAdvOptions(()),
     /// TEST-ONLY
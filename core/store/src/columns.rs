@@ -12,6 +12,8 @@ use std::fmt;
     Eq,
     BorshDeserialize,
     BorshSerialize,
+    serde::Deserialize,
+    serde::Serialize,
     enum_map::Enum,
     strum::EnumIter,
     strum::IntoStaticStr,
@@ -1,10 +1,14 @@
-use near_primitives::shard_layout::ShardUId;
+use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::version::DbVersion;
 
 use crate::db::Mode;
 
 const STORE_PATH: &str = "data";
 
+/// Write buffer size of the columns not listed in `StoreConfig::col_write_buffer_sizes`.  Same
+/// as set by `optimize_level_style_compaction` with the memtable budget we use.
+const DEFAULT_COL_WRITE_BUFFER_SIZE: bytesize::ByteSize = bytesize::ByteSize::mib(32);
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(default)]
 pub struct StoreConfig {
@@ -42,11 +46,25 @@ pub struct StoreConfig {
     /// the performance of the storage
     pub block_size: bytesize::ByteSize,
 
+    /// Write buffer (memtable) sizes of individual columns.
+    /// Default value: empty, i.e. all columns use 32MiB.
+    /// Columns with heavy writes, such as DBCol::State, benefit from larger write buffers,
+    /// while for small columns they mostly waste memory.  See `col_write_buffer_size`.
+    pub col_write_buffer_sizes: std::collections::HashMap<crate::DBCol, bytesize::ByteSize>,
+
     /// Trie cache capacities
     /// Default value: ShardUId {version: 1, shard_id: 3} -> 2_000_000. TODO: clarify
     /// We're still experimenting with this parameter and it seems decreasing its value can improve
     /// the performance of the storage
     pub trie_cache_capacities: Vec<(ShardUId, usize)>,
+
+    /// Whether trie caches are used at all.
+    /// Default value: true.
+    /// When disabled, every shard gets a zero-capacity trie cache regardless of
+    /// `trie_cache_capacities`.  Useful for nodes which rarely re-read recent
+    /// trie nodes, e.g. archival nodes, and would rather spend the memory on
+    /// RocksDB caches.
+    pub trie_cache_enabled: bool,
 }
 
 impl StoreConfig {
@@ -60,7 +78,223 @@ impl StoreConfig {
         Self { max_open_files: 512, ..Self::default() }
     }
 
+    /// Returns a conservative configuration for nodes with little RAM.
+    ///
+    /// Shrinks the [`DBCol::State`] cache, which dominates the memory use of
+    /// the storage, to 128 MiB (instead of 512 MiB) and limits the number of
+    /// open files.  Expect roughly 0.5 GiB less RAM used than with the default
+    /// configuration at the cost of more disk reads.
+    ///
+    /// [`DBCol::State`]: crate::DBCol::State
+    pub fn low_memory() -> Self {
+        Self {
+            max_open_files: 1_000,
+            col_state_cache_size: bytesize::ByteSize::mib(128),
+            ..Self::default()
+        }
+    }
+
+    /// Returns an aggressive configuration for nodes with plenty of RAM.
+    ///
+    /// Grows the [`DBCol::State`] cache to 2 GiB (instead of 512 MiB) and
+    /// allows more open files.  Expect roughly 1.5 GiB more RAM used than
+    /// with the default configuration; make sure `ulimit -n` is high enough.
+    ///
+    /// [`DBCol::State`]: crate::DBCol::State
+    pub fn high_throughput() -> Self {
+        Self {
+            max_open_files: 50_000,
+            col_state_cache_size: bytesize::ByteSize::gib(2),
+            ..Self::default()
+        }
+    }
+
+    /// Applies per-node overrides on top of this configuration.
+    ///
+    /// Only the fields which are set in `partial` are changed, everything
+    /// else keeps its current value.  This allows operators to keep a shared
+    /// base configuration and a small per-machine override file.
+    pub fn merge_overrides(&mut self, partial: &PartialStoreConfig) {
+        if let Some(path) = &partial.path {
+            self.path = Some(path.clone());
+        }
+        if let Some(enable_statistics) = partial.enable_statistics {
+            self.enable_statistics = enable_statistics;
+        }
+        if let Some(enable_statistics_export) = partial.enable_statistics_export {
+            self.enable_statistics_export = enable_statistics_export;
+        }
+        if let Some(max_open_files) = partial.max_open_files {
+            self.max_open_files = max_open_files;
+        }
+        if let Some(col_state_cache_size) = partial.col_state_cache_size {
+            self.col_state_cache_size = col_state_cache_size;
+        }
+        if let Some(block_size) = partial.block_size {
+            self.block_size = block_size;
+        }
+        if let Some(col_write_buffer_sizes) = &partial.col_write_buffer_sizes {
+            self.col_write_buffer_sizes = col_write_buffer_sizes.clone();
+        }
+        if let Some(trie_cache_capacities) = &partial.trie_cache_capacities {
+            self.trie_cache_capacities = trie_cache_capacities.clone();
+        }
+        if let Some(trie_cache_enabled) = partial.trie_cache_enabled {
+            self.trie_cache_enabled = trie_cache_enabled;
+        }
+    }
+
+    /// Returns a flat key/value description of the effective RocksDB settings.
+    ///
+    /// Sizes are in bytes.  Meant for dumping the configuration the database
+    /// is opened with, e.g. in status output.
+    pub fn describe(&self) -> Vec<(String, String)> {
+        use strum::IntoEnumIterator;
+
+        let path = match &self.path {
+            Some(path) => path.display().to_string(),
+            None => STORE_PATH.to_string(),
+        };
+        let mut res = vec![
+            ("path".to_string(), path),
+            ("enable_statistics".to_string(), self.enable_statistics.to_string()),
+            ("enable_statistics_export".to_string(), self.enable_statistics_export.to_string()),
+            ("max_open_files".to_string(), self.max_open_files.to_string()),
+            ("block_size".to_string(), self.block_size.as_u64().to_string()),
+            (
+                "compression".to_string(),
+                format!("{:?}", crate::db::COMPRESSION_TYPE).to_lowercase(),
+            ),
+            (
+                "bottommost_compression".to_string(),
+                format!("{:?}", crate::db::BOTTOMMOST_COMPRESSION_TYPE).to_lowercase(),
+            ),
+            ("trie_cache_enabled".to_string(), self.trie_cache_enabled.to_string()),
+        ];
+        for (shard_uid, capacity) in &self.trie_cache_capacities {
+            let capacity = if self.trie_cache_enabled { *capacity } else { 0 };
+            res.push((
+                format!("trie_cache_capacity.s{}.v{}", shard_uid.shard_id, shard_uid.version),
+                capacity.to_string(),
+            ));
+        }
+        for col in crate::DBCol::iter() {
+            let name: &'static str = col.into();
+            res.push((
+                format!("col_cache_size.{}", name),
+                self.col_cache_size(col).as_u64().to_string(),
+            ));
+        }
+        for col in crate::DBCol::iter() {
+            let name: &'static str = col.into();
+            res.push((
+                format!("col_write_buffer_size.{}", name),
+                self.col_write_buffer_size(col).as_u64().to_string(),
+            ));
+        }
+        res
+    }
+
+    /// Checks that every shard in `trie_cache_capacities` exists in `shard_layout`
+    /// and that the capacities aren't set while the trie cache is disabled.
+    ///
+    /// A capacity for an unknown shard has no effect, which usually means a
+    /// typo or a stale entry left after resharding.  Likewise, capacities are
+    /// ignored if `trie_cache_enabled` is false.  With `strict` such entries
+    /// are an error, otherwise they are only logged as a warning.
+    ///
+    /// Zero sizes in `col_write_buffer_sizes` are always an error.
+    pub fn validate(&self, shard_layout: &ShardLayout, strict: bool) -> Result<(), String> {
+        let mut zero_write_buffers: Vec<&'static str> = self
+            .col_write_buffer_sizes
+            .iter()
+            .filter(|(_, size)| size.as_u64() == 0)
+            .map(|(col, _)| (*col).into())
+            .collect();
+        if !zero_write_buffers.is_empty() {
+            zero_write_buffers.sort();
+            return Err(format!(
+                "col_write_buffer_sizes has zero sizes for columns: {:?}",
+                zero_write_buffers
+            ));
+        }
+        let message = if !self.trie_cache_enabled && !self.trie_cache_capacities.is_empty() {
+            "trie_cache_capacities is set but has no effect since trie_cache_enabled is false"
+                .to_string()
+        } else {
+            let unknown = self.unknown_trie_cache_shards(shard_layout);
+            if unknown.is_empty() {
+                return Ok(());
+            }
+            format!(
+                "trie_cache_capacities refers to shards which are not in the shard layout \
+                 (version {}): {:?}",
+                shard_layout.version(),
+                unknown
+            )
+        };
+        if strict {
+            return Err(message);
+        }
+        tracing::warn!(target: "near", "{}", message);
+        Ok(())
+    }
+
+    /// Returns shards from `trie_cache_capacities` which don't exist in
+    /// `shard_layout`.
+    pub fn unknown_trie_cache_shards(&self, shard_layout: &ShardLayout) -> Vec<ShardUId> {
+        let shard_uids = shard_layout.get_shard_uids();
+        self.trie_cache_capacities
+            .iter()
+            .map(|(shard_uid, _)| *shard_uid)
+            .filter(|shard_uid| !shard_uids.contains(shard_uid))
+            .collect()
+    }
+
+    /// Returns write buffer size for given column, see `col_write_buffer_sizes`.
+    pub fn col_write_buffer_size(&self, col: crate::DBCol) -> bytesize::ByteSize {
+        self.col_write_buffer_sizes.get(&col).copied().unwrap_or(DEFAULT_COL_WRITE_BUFFER_SIZE)
+    }
+
+    /// Returns a rough estimate of the memory the store will use with this
+    /// configuration when tracking `shards`, for pre-flight capacity checks.
+    ///
+    /// Sums the block cache and one write buffer of every column and, if
+    /// enabled, the trie caches of `shards`, assuming every cached trie node is
+    /// as large as it may be and counting both the regular and the view client
+    /// caches.  Shards without an entry in `trie_cache_capacities` get the
+    /// default capacity.  RocksDB keeps more than one write buffer per column
+    /// while flushing, and the process needs memory for much more than the
+    /// store, so leave some headroom on top of this.
+    pub fn estimated_memory_budget(&self, shards: &[ShardUId]) -> bytesize::ByteSize {
+        use strum::IntoEnumIterator;
+
+        let columns: u64 = crate::DBCol::iter()
+            .map(|col| self.col_cache_size(col).as_u64() + self.col_write_buffer_size(col).as_u64())
+            .sum();
+        let trie_cache_entries: u64 = if self.trie_cache_enabled {
+            shards
+                .iter()
+                .map(|shard_uid| {
+                    self.trie_cache_capacities
+                        .iter()
+                        .find(|(uid, _)| uid == shard_uid)
+                        .map_or(crate::trie::TRIE_DEFAULT_SHARD_CACHE_SIZE, |(_, capacity)| {
+                            *capacity
+                        }) as u64
+                })
+                .sum()
+        } else {
+            0
+        };
+        let trie_caches = trie_cache_entries * crate::trie::TRIE_LIMIT_CACHED_VALUE_SIZE as u64 * 2;
+        bytesize::ByteSize::b(columns + trie_caches)
+    }
+
     /// Returns cache size for given column.
+    ///
+    /// This is the RocksDB block cache and is independent of the trie cache,
+    /// i.e. it's not affected by `trie_cache_enabled`.
     pub const fn col_cache_size(&self, col: crate::DBCol) -> bytesize::ByteSize {
         match col {
             crate::DBCol::State => self.col_state_cache_size,
@@ -97,11 +331,33 @@ impl Default for StoreConfig {
             // we use it since then.
             block_size: bytesize::ByteSize::kib(16),
 
+            col_write_buffer_sizes: Default::default(),
+
             trie_cache_capacities: Default::default(),
+
+            trie_cache_enabled: true,
         }
     }
 }
 
+/// Subset of [`StoreConfig`] fields which override the base configuration.
+///
+/// Every field mirrors the one in [`StoreConfig`]; `None` means the base value
+/// is kept.  See [`StoreConfig::merge_overrides`].
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PartialStoreConfig {
+    pub path: Option<std::path::PathBuf>,
+    pub enable_statistics: Option<bool>,
+    pub enable_statistics_export: Option<bool>,
+    pub max_open_files: Option<u32>,
+    pub col_state_cache_size: Option<bytesize::ByteSize>,
+    pub block_size: Option<bytesize::ByteSize>,
+    pub col_write_buffer_sizes: Option<std::collections::HashMap<crate::DBCol, bytesize::ByteSize>>,
+    pub trie_cache_capacities: Option<Vec<(ShardUId, usize)>>,
+    pub trie_cache_enabled: Option<bool>,
+}
+
 /// Builder for opening a RocksDB database.
 ///
 /// Typical usage:
@@ -123,6 +379,10 @@ pub struct StoreOpener<'a> {
 
     /// Which mode to open storeg in.
     mode: Mode,
+
+    /// If set, the database is opened as a secondary instance keeping its
+    /// files in this directory, see [`Self::secondary`].
+    secondary_path: Option<std::path::PathBuf>,
 }
 
 impl<'a> StoreOpener<'a> {
@@ -130,7 +390,7 @@ impl<'a> StoreOpener<'a> {
     pub(crate) fn new(home_dir: &std::path::Path, config: &'a StoreConfig) -> Self {
         let path =
             home_dir.join(config.path.as_deref().unwrap_or(std::path::Path::new(STORE_PATH)));
-        Self { path, config, mode: Mode::ReadWrite }
+        Self { path, config, mode: Mode::ReadWrite, secondary_path: None }
     }
 
     /// Configure which mode the database should be opened in.
@@ -139,6 +399,18 @@ impl<'a> StoreOpener<'a> {
         self
     }
 
+    /// Opens the database as a read-only replica of a database which may be
+    /// open by another process, e.g. a running node, instead of opening it
+    /// directly.
+    ///
+    /// The replica keeps its own files in `secondary_path` and sees new writes
+    /// of the primary after [`crate::Store::catch_up_with_primary`].  `mode` is
+    /// ignored.
+    pub fn secondary(mut self, secondary_path: &std::path::Path) -> Self {
+        self.secondary_path = Some(secondary_path.to_path_buf());
+        self
+    }
+
     /// Returns whether database exists.
     ///
     /// It performs only basic file-system-level checks and may result in false
@@ -171,14 +443,222 @@ impl<'a> StoreOpener<'a> {
     pub fn open(&self) -> crate::Store {
         if self.check_if_exists() {
             tracing::info!(target: "near", path=%self.path.display(), "Opening RocksDB database");
-        } else if matches!(self.mode, Mode::ReadOnly) {
+        } else if matches!(self.mode, Mode::ReadOnly) || self.secondary_path.is_some() {
             tracing::error!(target: "near", path=%self.path.display(), "Database does not exist");
             panic!("Failed to open non-existent database for reading");
         } else {
             tracing::info!(target: "near", path=%self.path.display(), "Creating new RocksDB database");
         }
-        let db = crate::RocksDB::open(&self.path, &self.config, self.mode)
-            .expect("Failed to open the database");
+        let db = match &self.secondary_path {
+            Some(secondary_path) => {
+                crate::RocksDB::open_secondary(&self.path, secondary_path, &self.config)
+            }
+            None => crate::RocksDB::open(&self.path, &self.config, self.mode),
+        }
+        .expect("Failed to open the database");
         crate::Store::new(std::sync::Arc::new(db))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_overrides() {
+        let mut config = StoreConfig::default();
+        let partial: PartialStoreConfig = serde_json::from_str(r#"{"block_size": 65536}"#).unwrap();
+        config.merge_overrides(&partial);
+
+        let default = StoreConfig::default();
+        assert_eq!(bytesize::ByteSize::kib(64), config.block_size);
+        assert_eq!(default.path, config.path);
+        assert_eq!(default.enable_statistics, config.enable_statistics);
+        assert_eq!(default.enable_statistics_export, config.enable_statistics_export);
+        assert_eq!(default.max_open_files, config.max_open_files);
+        assert_eq!(default.col_state_cache_size, config.col_state_cache_size);
+        assert_eq!(default.col_write_buffer_sizes, config.col_write_buffer_sizes);
+        assert_eq!(default.trie_cache_capacities, config.trie_cache_capacities);
+        assert_eq!(default.trie_cache_enabled, config.trie_cache_enabled);
+    }
+
+    #[test]
+    fn test_open_secondary() {
+        let config = StoreConfig::test_config();
+        let primary_dir = tempfile::tempdir().unwrap();
+        let secondary_dir = tempfile::tempdir().unwrap();
+        let primary = crate::Store::opener(primary_dir.path(), &config).open();
+        let mut update = primary.store_update();
+        update.set(crate::DBCol::BlockMisc, b"foo", b"bar");
+        update.increment_refcount(crate::DBCol::State, b"baz", b"qux");
+        update.commit().unwrap();
+
+        let secondary = crate::Store::opener(primary_dir.path(), &config)
+            .secondary(secondary_dir.path())
+            .open();
+        assert_eq!(secondary.get(crate::DBCol::BlockMisc, b"foo").unwrap(), Some(b"bar".to_vec()));
+        assert_eq!(secondary.get(crate::DBCol::State, b"baz").unwrap(), Some(b"qux".to_vec()));
+
+        // Writes done after opening are visible once the secondary catches up.
+        let mut update = primary.store_update();
+        update.set(crate::DBCol::BlockMisc, b"new", b"value");
+        update.commit().unwrap();
+        secondary.catch_up_with_primary().unwrap();
+        assert_eq!(
+            secondary.get(crate::DBCol::BlockMisc, b"new").unwrap(),
+            Some(b"value".to_vec())
+        );
+        assert!(primary.catch_up_with_primary().is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        use strum::IntoEnumIterator;
+
+        let description = StoreConfig::default().describe();
+        let expected = [
+            ("path", "data"),
+            ("enable_statistics", "false"),
+            ("enable_statistics_export", "true"),
+            ("max_open_files", "10000"),
+            ("block_size", "16384"),
+            ("compression", "lz4"),
+            ("bottommost_compression", "zstd"),
+            ("trie_cache_enabled", "true"),
+        ];
+        let expected: Vec<_> =
+            expected.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(expected[..], description[..expected.len()]);
+
+        let columns = &description[expected.len()..];
+        assert_eq!(2 * crate::DBCol::iter().count(), columns.len());
+        assert!(columns.contains(&("col_cache_size.State".to_string(), "536870912".to_string())));
+        assert!(columns.contains(&("col_cache_size.Block".to_string(), "33554432".to_string())));
+        assert!(
+            columns.contains(&("col_write_buffer_size.State".to_string(), "33554432".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let shard_layout = ShardLayout::v1_test();
+        let known = ShardUId { version: 1, shard_id: 2 };
+        let bogus = ShardUId { version: 1, shard_id: 7 };
+        let stale = ShardUId { version: 0, shard_id: 0 };
+
+        let mut config =
+            StoreConfig { trie_cache_capacities: vec![(known, 100)], ..StoreConfig::default() };
+        assert!(config.unknown_trie_cache_shards(&shard_layout).is_empty());
+        assert_eq!(Ok(()), config.validate(&shard_layout, true));
+
+        config.trie_cache_capacities = vec![(bogus, 100), (known, 100), (stale, 100)];
+        assert_eq!(vec![bogus, stale], config.unknown_trie_cache_shards(&shard_layout));
+        assert_eq!(Ok(()), config.validate(&shard_layout, false));
+        assert!(config.validate(&shard_layout, true).is_err());
+
+        let config = StoreConfig {
+            trie_cache_capacities: vec![(known, 100)],
+            trie_cache_enabled: false,
+            ..StoreConfig::default()
+        };
+        assert_eq!(Ok(()), config.validate(&shard_layout, false));
+        assert!(config.validate(&shard_layout, true).is_err());
+    }
+
+    #[test]
+    fn test_col_write_buffer_sizes() {
+        let partial: PartialStoreConfig =
+            serde_json::from_str(r#"{"col_write_buffer_sizes": {"State": 268435456}}"#).unwrap();
+        let mut config = StoreConfig::default();
+        config.merge_overrides(&partial);
+        assert_eq!(bytesize::ByteSize::mib(256), config.col_write_buffer_size(crate::DBCol::State));
+        assert_eq!(
+            DEFAULT_COL_WRITE_BUFFER_SIZE,
+            config.col_write_buffer_size(crate::DBCol::Block)
+        );
+
+        let shard_layout = ShardLayout::v1_test();
+        assert_eq!(Ok(()), config.validate(&shard_layout, true));
+        config.col_write_buffer_sizes.insert(crate::DBCol::Block, bytesize::ByteSize::b(0));
+        assert!(config.validate(&shard_layout, false).is_err());
+    }
+
+    #[test]
+    fn test_trie_cache_disabled() {
+        let shard_uid = ShardUId { version: 1, shard_id: 2 };
+        let mut config =
+            StoreConfig { trie_cache_capacities: vec![(shard_uid, 100)], ..StoreConfig::default() };
+        let factory = crate::TrieCacheFactory::from_config(&config, 1, 4);
+        assert_eq!(100, factory.capacity(&shard_uid));
+        assert_ne!(0, factory.capacity(&ShardUId { version: 1, shard_id: 3 }));
+
+        config.trie_cache_enabled = false;
+        let factory = crate::TrieCacheFactory::from_config(&config, 1, 4);
+        for shard_id in 0..4 {
+            assert_eq!(0, factory.capacity(&ShardUId { version: 1, shard_id }));
+        }
+        assert!(config
+            .describe()
+            .contains(&("trie_cache_enabled".to_string(), "false".to_string())));
+    }
+
+    #[test]
+    fn test_estimated_memory_budget() {
+        use strum::IntoEnumIterator;
+
+        let default = StoreConfig::default();
+        let budget = default.estimated_memory_budget(&[]);
+        // 512 MiB for the State cache plus 32 MiB of cache and 32 MiB of write buffer for
+        // every column, including State.
+        let columns = crate::DBCol::iter().count() as u64;
+        assert!(budget > bytesize::ByteSize::mib(512 + 64 * (columns - 1)));
+        assert!(budget < bytesize::ByteSize::gib(8));
+
+        let bigger_state_cache = StoreConfig {
+            col_state_cache_size: bytesize::ByteSize::gib(2),
+            ..StoreConfig::default()
+        };
+        assert_eq!(
+            budget + bytesize::ByteSize::mib(1536),
+            bigger_state_cache.estimated_memory_budget(&[])
+        );
+
+        // Shards without an override count with the default capacity.
+        let shard_uid = ShardUId { version: 1, shard_id: 0 };
+        let other_uid = ShardUId { version: 1, shard_id: 1 };
+        let node_size = crate::trie::TRIE_LIMIT_CACHED_VALUE_SIZE as u64 * 2;
+        let default_cache = crate::trie::TRIE_DEFAULT_SHARD_CACHE_SIZE as u64 * node_size;
+        assert_eq!(
+            default.estimated_memory_budget(&[shard_uid, other_uid]),
+            budget + bytesize::ByteSize::b(2 * default_cache)
+        );
+
+        let mut config = StoreConfig {
+            trie_cache_capacities: vec![(shard_uid, 1000)],
+            ..StoreConfig::default()
+        };
+        // Overrides of shards which aren't tracked don't count.
+        assert_eq!(config.estimated_memory_budget(&[]), budget);
+        assert_eq!(
+            config.estimated_memory_budget(&[shard_uid, other_uid]),
+            budget + bytesize::ByteSize::b(1000 * node_size + default_cache)
+        );
+        config.trie_cache_enabled = false;
+        assert_eq!(budget, config.estimated_memory_budget(&[shard_uid, other_uid]));
+    }
+
+    #[test]
+    fn test_presets() {
+        let low = StoreConfig::low_memory();
+        let default = StoreConfig::default();
+        let high = StoreConfig::high_throughput();
+        for col in [crate::DBCol::State, crate::DBCol::Block] {
+            assert!(low.col_cache_size(col) <= default.col_cache_size(col));
+            assert!(default.col_cache_size(col) <= high.col_cache_size(col));
+        }
+        assert!(low.col_state_cache_size < default.col_state_cache_size);
+        assert!(default.col_state_cache_size < high.col_state_cache_size);
+        assert!(low.max_open_files < default.max_open_files);
+        assert!(default.max_open_files < high.max_open_files);
+    }
+}
@@ -172,9 +172,34 @@ impl RocksDB {
     pub fn open(path: &Path, store_config: &StoreConfig, mode: Mode) -> io::Result<RocksDB> {
         ensure_max_open_files_limit(store_config.max_open_files).map_err(other_error)?;
         let (db, db_opt) = Self::open_db(path, store_config, mode)?;
-        let cf_handles = Self::get_cf_handles(&db);
+        Ok(Self::from_db(db, db_opt))
+    }
+
+    /// Opens the database at `primary_path` as a RocksDB secondary instance, i.e. a read-only
+    /// replica of a database which may still be open for writing by another process. The
+    /// secondary keeps its own logs in `secondary_path` and sees the writes done by the
+    /// primary after `Database::catch_up_with_primary`.
+    pub fn open_secondary(
+        primary_path: &Path,
+        secondary_path: &Path,
+        store_config: &StoreConfig,
+    ) -> io::Result<RocksDB> {
+        let mut options = rocksdb_options(store_config, Mode::ReadOnly);
+        // Secondary instances have to keep all files open.
+        options.set_max_open_files(-1);
+        let db = DB::open_cf_descriptors_as_secondary(
+            &options,
+            primary_path,
+            secondary_path,
+            Self::cf_descriptors(store_config),
+        )
+        .map_err(into_other)?;
+        Ok(Self::from_db(db, options))
+    }
 
-        Ok(Self {
+    fn from_db(db: DB, db_opt: Options) -> RocksDB {
+        let cf_handles = Self::get_cf_handles(&db);
+        Self {
             db,
             db_opt,
             cf_handles,
@@ -182,20 +207,24 @@ impl RocksDB {
             check_free_space_counter: std::sync::atomic::AtomicU16::new(0),
             free_space_threshold: bytesize::ByteSize::mb(16),
             _instance_counter: InstanceCounter::new(),
-        })
+        }
     }
 
-    /// Opens the database with all column families configured.
-    fn open_db(path: &Path, store_config: &StoreConfig, mode: Mode) -> io::Result<(DB, Options)> {
-        let options = rocksdb_options(store_config, mode);
-        let cf_descriptors = DBCol::iter()
+    fn cf_descriptors(store_config: &StoreConfig) -> Vec<rocksdb::ColumnFamilyDescriptor> {
+        DBCol::iter()
             .map(|col| {
                 rocksdb::ColumnFamilyDescriptor::new(
                     col_name(col),
                     rocksdb_column_options(col, store_config),
                 )
             })
-            .collect::<Vec<_>>();
+            .collect()
+    }
+
+    /// Opens the database with all column families configured.
+    fn open_db(path: &Path, store_config: &StoreConfig, mode: Mode) -> io::Result<(DB, Options)> {
+        let options = rocksdb_options(store_config, mode);
+        let cf_descriptors = Self::cf_descriptors(store_config);
         let db = match mode {
             Mode::ReadOnly => {
                 DB::open_cf_descriptors_read_only(&options, path, cf_descriptors, false)
@@ -305,6 +334,11 @@ pub(crate) trait Database: Sync + Send {
 
     /// Returns statistics about the database if available.
     fn get_store_statistics(&self) -> Option<StoreStatistics>;
+
+    /// Reads the changes done by the primary instance since the last call, see
+    /// [`RocksDB::open_secondary`].  Fails for databases which aren't secondary
+    /// instances.
+    fn catch_up_with_primary(&self) -> io::Result<()>;
 }
 
 impl Database for RocksDB {
@@ -407,6 +441,10 @@ impl Database for RocksDB {
         self.db.flush().map_err(into_other)
     }
 
+    fn catch_up_with_primary(&self) -> io::Result<()> {
+        self.db.try_catch_up_with_primary().map_err(into_other)
+    }
+
     fn get_store_statistics(&self) -> Option<StoreStatistics> {
         if let Some(stats_str) = self.db_opt.get_statistics() {
             match parse_statistics(&stats_str) {
@@ -501,6 +539,10 @@ impl Database for TestDB {
     fn get_store_statistics(&self) -> Option<StoreStatistics> {
         None
     }
+
+    fn catch_up_with_primary(&self) -> io::Result<()> {
+        Err(other_error("TestDB is not a secondary instance".into()))
+    }
 }
 
 fn assert_no_overwrite(col: DBCol, key: &[u8], value: &[u8], old_value: &[u8]) {
@@ -516,9 +558,15 @@ new value: {value:?}
     )
 }
 
+/// Compression of all levels but the last one, see `set_compression_options`.
+pub(crate) const COMPRESSION_TYPE: rocksdb::DBCompressionType = rocksdb::DBCompressionType::Lz4;
+/// Compression of the last level, see `set_compression_options`.
+pub(crate) const BOTTOMMOST_COMPRESSION_TYPE: rocksdb::DBCompressionType =
+    rocksdb::DBCompressionType::Zstd;
+
 fn set_compression_options(opts: &mut Options) {
-    opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
-    opts.set_bottommost_compression_type(rocksdb::DBCompressionType::Zstd);
+    opts.set_compression_type(COMPRESSION_TYPE);
+    opts.set_bottommost_compression_type(BOTTOMMOST_COMPRESSION_TYPE);
     // RocksDB documenation says that 16KB is a typical dictionary size.
     // We've empirically tuned the dicionary size to twice of that 'typical' size.
     // Having train data size x100 from dictionary size is a recommendation from RocksDB.
@@ -624,6 +672,9 @@ fn rocksdb_column_options(col: DBCol, store_config: &StoreConfig) -> Options {
     //      https://github.com/facebook/rocksdb/blob/c18c4a081c74251798ad2a1abf83bad417518481/options/options.cc#L588.
     let memtable_memory_budget = 128 * bytesize::MIB as usize;
     opts.optimize_level_style_compaction(memtable_memory_budget);
+    opts.set_write_buffer_size(
+        store_config.col_write_buffer_size(col).as_u64().try_into().unwrap_or(usize::MAX),
+    );
 
     opts.set_target_file_size_base(64 * bytesize::MIB);
     if col.is_rc() {
@@ -46,7 +46,7 @@ pub mod migrations;
 pub mod test_utils;
 mod trie;
 
-pub use crate::config::{StoreConfig, StoreOpener};
+pub use crate::config::{PartialStoreConfig, StoreConfig, StoreOpener};
 
 #[derive(Clone)]
 pub struct Store {
@@ -179,6 +179,12 @@ impl Store {
     pub fn get_store_statistics(&self) -> Option<StoreStatistics> {
         self.storage.get_store_statistics()
     }
+
+    /// Makes the writes done by the primary visible in a store opened with
+    /// [`StoreOpener::secondary`].  Fails for other stores.
+    pub fn catch_up_with_primary(&self) -> io::Result<()> {
+        self.storage.catch_up_with_primary()
+    }
 }
 
 /// Keeps track of current changes to the database and can commit all of them to the database.
@@ -21,6 +21,9 @@ pub use crate::trie::shard_tries::{
 };
 pub use crate::trie::trie_storage::{TrieCache, TrieCachingStorage, TrieStorage};
 use crate::trie::trie_storage::{TrieMemoryPartialStorage, TrieRecordingStorage};
+pub(crate) use crate::trie::trie_storage::{
+    TRIE_DEFAULT_SHARD_CACHE_SIZE, TRIE_LIMIT_CACHED_VALUE_SIZE,
+};
 use crate::StorageError;
 pub use near_primitives::types::TrieNodesCount;
 
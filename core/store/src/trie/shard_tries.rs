@@ -12,17 +12,24 @@ use near_primitives::types::{
     NumShards, RawStateChange, RawStateChangesWithTrieKey, StateChangeCause, StateRoot,
 };
 
-use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
+use crate::trie::trie_storage::{TrieCache, TrieCachingStorage, TRIE_DEFAULT_SHARD_CACHE_SIZE};
 use crate::trie::{TrieRefcountChange, POISONED_LOCK_ERR};
 use crate::{DBCol, DBOp, DBTransaction};
-use crate::{Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
+use crate::{Store, StoreConfig, StoreUpdate, Trie, TrieChanges, TrieUpdate};
 
 /// Responsible for creation of trie caches, stores necessary configuration for it.
-#[derive(Default)]
 pub struct TrieCacheFactory {
     capacities: HashMap<ShardUId, usize>,
     shard_version: ShardVersion,
     num_shards: NumShards,
+    /// If false, all created caches have zero capacity.
+    enabled: bool,
+}
+
+impl Default for TrieCacheFactory {
+    fn default() -> Self {
+        Self::new(Default::default(), Default::default(), Default::default())
+    }
 }
 
 impl TrieCacheFactory {
@@ -31,15 +38,36 @@ impl TrieCacheFactory {
         shard_version: ShardVersion,
         num_shards: NumShards,
     ) -> Self {
-        Self { capacities, shard_version, num_shards }
+        Self { capacities, shard_version, num_shards, enabled: true }
+    }
+
+    /// Creates factory with capacities taken from the store configuration.
+    pub fn from_config(
+        config: &StoreConfig,
+        shard_version: ShardVersion,
+        num_shards: NumShards,
+    ) -> Self {
+        Self {
+            enabled: config.trie_cache_enabled,
+            ..Self::new(
+                config.trie_cache_capacities.iter().copied().collect(),
+                shard_version,
+                num_shards,
+            )
+        }
+    }
+
+    /// Returns capacity of the cache created for the given shard uid.
+    pub fn capacity(&self, shard_uid: &ShardUId) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+        self.capacities.get(shard_uid).copied().unwrap_or(TRIE_DEFAULT_SHARD_CACHE_SIZE)
     }
 
     /// Create new cache for the given shard uid.
     pub fn create_cache(&self, shard_uid: &ShardUId) -> TrieCache {
-        match self.capacities.get(shard_uid) {
-            Some(capacity) => TrieCache::with_capacity(*capacity),
-            None => TrieCache::new(),
-        }
+        TrieCache::with_capacity(self.capacity(shard_uid))
     }
 
     /// Create caches on the initialization of storage structures.
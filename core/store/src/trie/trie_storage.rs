@@ -149,10 +149,10 @@ impl TrieStorage for TrieMemoryPartialStorage {
 /// In our tests on a single shard, it barely occupied 40 MB, which is dominated by state cache size
 /// with 512 MB limit. The total RAM usage for a single shard was 1 GB.
 #[cfg(not(feature = "no_cache"))]
-const TRIE_DEFAULT_SHARD_CACHE_SIZE: usize = 50000;
+pub(crate) const TRIE_DEFAULT_SHARD_CACHE_SIZE: usize = 50000;
 
 #[cfg(feature = "no_cache")]
-const TRIE_DEFAULT_SHARD_CACHE_SIZE: usize = 1;
+pub(crate) const TRIE_DEFAULT_SHARD_CACHE_SIZE: usize = 1;
 
 /// Values above this size (in bytes) are never cached.
 /// Note that most of Trie inner nodes are smaller than this - e.g. branches use around 32 * 16 = 512 bytes.
@@ -52,7 +52,7 @@ use near_store::split_state::get_delayed_receipts;
 use near_store::{
     get_genesis_hash, get_genesis_state_roots, set_genesis_hash, set_genesis_state_roots,
     ApplyStatePartResult, DBCol, PartialStorage, ShardTries, Store, StoreCompiledContractCache,
-    StoreUpdate, Trie, TrieCacheFactory, WrappedTrieChanges,
+    StoreConfig, StoreUpdate, Trie, TrieCacheFactory, WrappedTrieChanges,
 };
 use near_vm_runner::precompile_contract;
 use node_runtime::adapter::ViewRuntimeAdapter;
@@ -155,7 +155,7 @@ impl NightshadeRuntime {
             config.client_config.max_gas_burnt_view,
             None,
             config.config.gc.gc_num_epochs_to_keep(),
-            config.config.store.trie_cache_capacities.clone(),
+            &config.config.store,
         )
     }
 
@@ -168,7 +168,7 @@ impl NightshadeRuntime {
         max_gas_burnt_view: Option<Gas>,
         runtime_config_store: Option<RuntimeConfigStore>,
         gc_num_epochs_to_keep: u64,
-        trie_cache_capacities: Vec<(ShardUId, usize)>,
+        store_config: &StoreConfig,
     ) -> Self {
         let runtime_config_store = match runtime_config_store {
             Some(store) => store,
@@ -187,8 +187,8 @@ impl NightshadeRuntime {
         );
         let state_roots =
             Self::initialize_genesis_state_if_needed(store.clone(), home_dir, genesis);
-        let trie_cache_factory = TrieCacheFactory::new(
-            trie_cache_capacities.into_iter().collect(),
+        let trie_cache_factory = TrieCacheFactory::from_config(
+            store_config,
             genesis_config.shard_layout.version(),
             genesis.config.num_block_producer_seats_per_shard.len() as NumShards,
         );
@@ -229,7 +229,7 @@ impl NightshadeRuntime {
             None,
             Some(runtime_config_store),
             DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
-            Default::default(),
+            &StoreConfig::default(),
         )
     }
 
@@ -2168,7 +2168,7 @@ mod test {
                 None,
                 Some(RuntimeConfigStore::free()),
                 DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
-                Default::default(),
+                &StoreConfig::default(),
             );
             let (_store, state_roots) = runtime.genesis_state();
             let genesis_hash = hash(&vec![0]);
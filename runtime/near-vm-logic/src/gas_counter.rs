@@ -11,20 +11,30 @@ use near_primitives_core::{
 use std::collections::HashMap;
 use std::fmt;
 
+#[cfg(feature = "costs_counting")]
+thread_local! {
+    static EXT_COSTS_COUNTER: std::cell::RefCell<HashMap<ExtCosts, u64>> = Default::default();
+    static EXT_COSTS_COUNTING: std::cell::Cell<bool> = std::cell::Cell::new(true);
+}
+
 #[inline]
 pub fn with_ext_cost_counter(f: impl FnOnce(&mut HashMap<ExtCosts, u64>)) {
     #[cfg(feature = "costs_counting")]
-    {
-        thread_local! {
-            static EXT_COSTS_COUNTER: std::cell::RefCell<HashMap<ExtCosts, u64>> =
-                Default::default();
-        }
-        EXT_COSTS_COUNTER.with(|rc| f(&mut *rc.borrow_mut()));
-    }
+    EXT_COSTS_COUNTER.with(|rc| f(&mut *rc.borrow_mut()));
     #[cfg(not(feature = "costs_counting"))]
     let _ = f;
 }
 
+/// Enables or disables counting ext costs on the current thread, e.g. to keep its
+/// overhead out of time measurements. Counting is enabled by default.
+#[inline]
+pub fn set_ext_cost_counting(enabled: bool) {
+    #[cfg(feature = "costs_counting")]
+    EXT_COSTS_COUNTING.with(|counting| counting.set(enabled));
+    #[cfg(not(feature = "costs_counting"))]
+    let _ = enabled;
+}
+
 type Result<T> = ::std::result::Result<T, VMLogicError>;
 
 /// Fast gas counter with very simple structure, could be exposed to compiled code in the VM.
@@ -182,7 +192,12 @@ impl GasCounter {
 
     #[inline]
     fn inc_ext_costs_counter(&mut self, cost: ExtCosts, value: u64) {
-        with_ext_cost_counter(|cc| *cc.entry(cost).or_default() += value)
+        #[cfg(feature = "costs_counting")]
+        if EXT_COSTS_COUNTING.with(|counting| counting.get()) {
+            with_ext_cost_counter(|cc| *cc.entry(cost).or_default() += value)
+        }
+        #[cfg(not(feature = "costs_counting"))]
+        let _ = (cost, value);
     }
 
     #[inline]
@@ -24,4 +24,4 @@ pub use near_vm_errors::{HostError, VMLogicError};
 pub use receipt_manager::ReceiptMetadata;
 pub use types::ReturnData;
 
-pub use gas_counter::with_ext_cost_counter;
+pub use gas_counter::{set_ext_cost_counting, with_ext_cost_counter};
@@ -1,9 +1,12 @@
 use near_vm_runner::internal::VMKind;
+use num_rational::Ratio;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 use crate::rocksdb::RocksDBTestConfig;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum GasMetric {
     // If we measure gas in number of executed instructions, must run under simulator.
     ICount,
@@ -11,6 +14,17 @@ pub enum GasMetric {
     Time,
 }
 
+impl GasMetric {
+    /// Default for `Config::noise_floor`: a few nanoseconds for `Time` and a
+    /// handful of instructions for `ICount`.
+    pub fn default_noise_floor(self) -> u64 {
+        match self {
+            GasMetric::ICount => 10,
+            GasMetric::Time => 5,
+        }
+    }
+}
+
 /// Configuration which we use to run measurements.
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -20,8 +34,10 @@ pub struct Config {
     pub iter_per_block: usize,
     /// Total active accounts.
     pub active_accounts: usize,
-    /// Number of the transactions in the block.
-    pub block_sizes: Vec<usize>,
+    /// Number of transactions in a measured block, for estimations that
+    /// don't require a specific block size. Defaults to 100. Estimations with
+    /// megabyte sized transactions use at most 5.
+    pub block_size: usize,
     /// Where state dump is located in case we need to create a testbed.
     pub state_dump_path: PathBuf,
     /// Metric used for counting.
@@ -38,4 +54,62 @@ pub struct Config {
     pub json_output: bool,
     /// Clear all OS caches between measured blocks.
     pub drop_os_cache: bool,
+    /// Pin the thread applying measured blocks to this CPU core. Only used
+    /// with the time metric.
+    pub pin_to_core: Option<usize>,
+    /// Record the peak resident set size of the process for each measured
+    /// block. Adds some overhead, so it's off by default.
+    pub probe_memory: bool,
+    /// Estimates below this value are dominated by measurement noise and get
+    /// flagged as uncertain. In nanoseconds for `Time` and in instructions for
+    /// `ICount`.
+    pub noise_floor: Ratio<u64>,
+    /// Count the ext costs of measured blocks. Without it, measured blocks
+    /// report empty ext cost maps, but timing isn't biased by the counting
+    /// overhead. Estimations which need the counts panic.
+    pub collect_ext_costs: bool,
+    /// Seed of the random choices made when generating transactions. If not
+    /// set, `run` picks one and reports it with the results.
+    pub seed: Option<u64>,
+    /// Apply the blocks of transaction cost estimations once on a separate
+    /// testbed before measuring them, and abort listing the transactions
+    /// which fail.
+    pub dry_run: bool,
+}
+
+impl Config {
+    /// Settings which determine the measured workload, to be reported along
+    /// with the results measured with `seed`.
+    pub fn provenance(&self, seed: u64) -> Provenance {
+        Provenance {
+            seed,
+            metric: self.metric,
+            active_accounts: self.active_accounts,
+            iter_per_block: self.iter_per_block,
+            warmup_iters_per_block: self.warmup_iters_per_block,
+        }
+    }
+
+    /// Overrides the settings recorded in `provenance`, to reproduce the
+    /// results it was reported with.
+    pub fn with_provenance(self, provenance: &Provenance) -> Config {
+        Config {
+            seed: Some(provenance.seed),
+            metric: provenance.metric,
+            active_accounts: provenance.active_accounts,
+            iter_per_block: provenance.iter_per_block,
+            warmup_iters_per_block: provenance.warmup_iters_per_block,
+            ..self
+        }
+    }
+}
+
+/// See `Config::provenance`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub seed: u64,
+    pub metric: GasMetric,
+    pub active_accounts: usize,
+    pub iter_per_block: usize,
+    pub warmup_iters_per_block: usize,
 }
@@ -1,14 +1,15 @@
 use near_primitives::shard_layout::ShardUId;
 use std::collections::HashMap;
+use std::path::Path;
 
 use near_primitives::transaction::SignedTransaction;
 use near_store::{TrieCache, TrieCachingStorage};
 use near_vm_logic::ExtCosts;
 
 use crate::config::{Config, GasMetric};
-use crate::gas_cost::GasCost;
+use crate::gas_cost::{GasCost, NonNegativeTolerance};
 use crate::testbed::RuntimeTestbed;
-use crate::utils::get_account_id;
+use crate::utils::{aggregate_per_block_measurements, get_account_id, noop_function_call_cost};
 
 use super::transaction_builder::TransactionBuilder;
 
@@ -16,6 +17,8 @@ use super::transaction_builder::TransactionBuilder;
 pub(crate) struct EstimatorContext<'c> {
     pub(crate) config: &'c Config,
     pub(crate) cached: CachedCosts,
+    /// When set, testbeds use a `TransactionBuilder` seeded with this value.
+    pub(crate) seed: Option<u64>,
 }
 
 #[derive(Default)]
@@ -38,17 +41,135 @@ pub(crate) struct CachedCosts {
 impl<'c> EstimatorContext<'c> {
     pub(crate) fn new(config: &'c Config) -> Self {
         let cached = CachedCosts::default();
-        Self { cached, config }
+        Self { cached, config, seed: config.seed }
+    }
+
+    /// Subtracts the cost of a function call doing nothing from `measured`,
+    /// measuring that baseline first if it isn't cached yet. The result is
+    /// clamped at zero and marked uncertain if `measured` is below the
+    /// baseline by more than the usual tolerance.
+    pub(crate) fn cost_above_noop(&mut self, measured: GasCost) -> GasCost {
+        let noop = noop_function_call_cost(self);
+        measured.saturating_sub(&noop, &NonNegativeTolerance::PER_MILLE)
     }
 
     pub(crate) fn testbed(&mut self) -> Testbed<'_> {
-        let inner = RuntimeTestbed::from_state_dump(&self.config.state_dump_path);
+        let config = self.config;
+        self.testbed_from_dump(&config.state_dump_path)
+    }
+
+    /// Like `testbed`, but loads the state dump from `path` instead of
+    /// `Config::state_dump_path`. Allows measuring the same cost on state dumps
+    /// of different sizes.
+    pub(crate) fn testbed_from_dump(&mut self, path: &Path) -> Testbed<'_> {
+        let inner = RuntimeTestbed::from_state_dump(path);
+        let accounts = (0..self.config.active_accounts).map(get_account_id).collect();
+        let transaction_builder = match self.seed {
+            Some(seed) => TransactionBuilder::new_seeded(accounts, seed),
+            None => TransactionBuilder::new(accounts),
+        };
         Testbed {
             config: self.config,
             inner,
-            transaction_builder: TransactionBuilder::new(
-                (0..self.config.active_accounts).map(get_account_id).collect(),
-            ),
+            transaction_builder,
+            empty_block_overhead: None,
+            subtract_block_overhead: false,
+        }
+    }
+
+    /// Runs `measure_fn` twice with the same seeded `TransactionBuilder` and
+    /// panics if the two results differ by more than `tolerance`. Cached costs
+    /// are cleared before each run, so that nothing is reused between them.
+    /// Only meaningful with the `ICount` metric, time measurements are noisy.
+    #[cfg(test)]
+    #[track_caller]
+    pub(crate) fn assert_deterministic(
+        &mut self,
+        measure_fn: impl Fn(&mut EstimatorContext) -> GasCost,
+        tolerance: &NonNegativeTolerance,
+    ) -> GasCost {
+        const SEED: u64 = 0x5eed;
+        let prev_seed = self.seed.replace(SEED);
+        let run = |ctx: &mut Self| {
+            ctx.cached = CachedCosts::default();
+            measure_fn(ctx)
+        };
+        let first = run(self);
+        let second = run(self);
+        self.seed = prev_seed;
+
+        let (smaller, larger) = if first <= second { (&first, &second) } else { (&second, &first) };
+        let diff =
+            GasCost::from_gas((larger.to_gas() - smaller.to_gas()).into(), self.config.metric);
+        if diff.to_gas() > 0 && !tolerance.tolerates(larger, &diff) {
+            panic!(
+                "measurement is not deterministic: {:?} vs {:?} ({} gas vs {} gas)",
+                first,
+                second,
+                first.to_gas(),
+                second.to_gas()
+            );
+        }
+        first
+    }
+}
+
+/// Results of `Testbed::measure_blocks`, one per input block, together with the metric
+/// they were measured with.
+pub(crate) struct MeasuredBlocks {
+    pub(crate) metric: GasMetric,
+    pub(crate) blocks: Vec<(GasCost, HashMap<ExtCosts, u64>)>,
+}
+
+impl MeasuredBlocks {
+    /// Returns the measurements. They can only be combined with costs measured with
+    /// the same `metric`.
+    pub(crate) fn into_blocks(self) -> Vec<(GasCost, HashMap<ExtCosts, u64>)> {
+        debug_assert!(self.blocks.iter().all(|(gas_cost, _)| gas_cost.metric() == self.metric));
+        self.blocks
+    }
+
+    /// Subtracts `overhead` from the cost of every block. A block cheaper than
+    /// `overhead` is clamped at zero and marked uncertain.
+    #[track_caller]
+    pub(crate) fn net_of(mut self, overhead: &GasCost) -> Self {
+        for (gas_cost, _) in &mut self.blocks {
+            *gas_cost = match gas_cost.checked_sub(overhead) {
+                Some(net) => net,
+                None => {
+                    let mut net = GasCost::zero(self.metric);
+                    net.set_uncertain("BLOCK-OVERHEAD-UNDERFLOW");
+                    net
+                }
+            };
+        }
+        self
+    }
+}
+
+/// A transaction which failed in `Testbed::dry_run`.
+#[derive(Debug)]
+pub(crate) struct TxError {
+    /// Index of the block containing the transaction.
+    pub(crate) block: usize,
+    pub(crate) tx_hash: near_primitives::hash::CryptoHash,
+    pub(crate) kind: TxErrorKind,
+}
+
+#[derive(Debug)]
+pub(crate) enum TxErrorKind {
+    /// The runtime rejected the transaction, e.g. because of a bad nonce or signature.
+    Rejected(near_primitives::errors::RuntimeError),
+    /// The transaction or one of the receipts it produced failed.
+    Failed(near_primitives::errors::TxExecutionError),
+}
+
+impl std::fmt::Display for TxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "block {}, transaction {}: ", self.block, self.tx_hash)?;
+        match &self.kind {
+            TxErrorKind::Rejected(err) => write!(f, "rejected with {:?}", err),
+            TxErrorKind::Failed(err) => write!(f, "failed with {:?}", err),
         }
     }
 }
@@ -60,6 +181,12 @@ pub(crate) struct Testbed<'c> {
     pub(crate) config: &'c Config,
     inner: RuntimeTestbed,
     transaction_builder: TransactionBuilder,
+    /// Cached result of `measure_empty_block_overhead`.
+    empty_block_overhead: Option<GasCost>,
+    /// When set, `measure_blocks` returns the cost of each block net of
+    /// `measure_empty_block_overhead`, i.e. only the marginal cost of its
+    /// transactions.
+    pub(crate) subtract_block_overhead: bool,
 }
 
 impl<'c> Testbed<'c> {
@@ -79,21 +206,90 @@ impl<'c> Testbed<'c> {
         &'a mut self,
         blocks: Vec<Vec<SignedTransaction>>,
         block_latency: usize,
-    ) -> Vec<(GasCost, HashMap<ExtCosts, u64>)> {
+    ) -> MeasuredBlocks {
+        let results = self.measure_blocks_with_memory(blocks, block_latency);
+        if self.config.probe_memory {
+            let peak_rss: Vec<_> = results.iter().map(|(_, _, peak_rss)| *peak_rss).collect();
+            eprintln!("Peak RSS in bytes per measured block: {peak_rss:?}");
+        }
+        let measured = MeasuredBlocks {
+            metric: self.config.metric,
+            blocks: results
+                .into_iter()
+                .map(|(gas_cost, ext_costs, _)| (gas_cost, ext_costs))
+                .collect(),
+        };
+        if self.subtract_block_overhead {
+            let overhead = self.measure_empty_block_overhead();
+            measured.net_of(&overhead)
+        } else {
+            measured
+        }
+    }
+
+    /// Fixed cost paid by every block in `measure_blocks` regardless of its
+    /// transactions, i.e. the average cost of processing an empty block.
+    /// Measured on the first call and cached for the lifetime of the testbed.
+    pub(crate) fn measure_empty_block_overhead(&mut self) -> GasCost {
+        if let Some(overhead) = &self.empty_block_overhead {
+            return overhead.clone();
+        }
+        let n_blocks = self.config.warmup_iters_per_block + self.config.iter_per_block;
+        let measurements = self
+            .measure_blocks_with_memory(vec![vec![]; n_blocks], 0)
+            .into_iter()
+            .skip(self.config.warmup_iters_per_block)
+            .map(|(gas_cost, ext_costs, _)| (gas_cost, ext_costs))
+            .collect();
+        let (overhead, _ext_costs) =
+            aggregate_per_block_measurements(self.config, 1, measurements, None);
+        self.empty_block_overhead = Some(overhead.clone());
+        overhead
+    }
+
+    /// Like `measure_blocks`, but also returns the peak resident set size of
+    /// the process in bytes while processing each block. It is only recorded
+    /// if `Config::probe_memory` is set, and is `None` otherwise or if the
+    /// platform doesn't support it.
+    #[track_caller]
+    pub(crate) fn measure_blocks_with_memory(
+        &mut self,
+        blocks: Vec<Vec<SignedTransaction>>,
+        block_latency: usize,
+    ) -> Vec<(GasCost, HashMap<ExtCosts, u64>, Option<u64>)> {
         let allow_failures = false;
 
+        // Keeps the thread pinned until the end of the measurement.
+        let _affinity = match (self.config.metric, self.config.pin_to_core) {
+            (GasMetric::Time, Some(core)) => match crate::utils::pin_current_thread_to_core(core) {
+                Ok(guard) => Some(guard),
+                Err(err) => {
+                    eprintln!("WARNING: failed to pin measurement thread to core {core}: {err}");
+                    None
+                }
+            },
+            _ => None,
+        };
+
         let mut res = Vec::with_capacity(blocks.len());
 
+        near_vm_logic::set_ext_cost_counting(self.config.collect_ext_costs);
         for block in blocks {
             node_runtime::with_ext_cost_counter(|cc| cc.clear());
             let extra_blocks;
             let gas_cost = {
                 self.clear_caches();
+                // Without a reset the peak covers the whole process lifetime.
+                if self.config.probe_memory {
+                    let _ = crate::utils::reset_peak_rss();
+                }
                 let start = GasCost::measure(self.config.metric);
                 self.inner.process_block(&block, allow_failures);
                 extra_blocks = self.inner.process_blocks_until_no_receipts(allow_failures);
                 start.elapsed()
             };
+            let peak_rss =
+                if self.config.probe_memory { crate::utils::peak_rss_bytes().ok() } else { None };
             assert_eq!(block_latency, extra_blocks);
 
             let mut ext_costs: HashMap<ExtCosts, u64> = HashMap::new();
@@ -102,12 +298,133 @@ impl<'c> Testbed<'c> {
                     ext_costs.insert(c, v);
                 }
             });
-            res.push((gas_cost, ext_costs));
+            res.push((gas_cost, ext_costs, peak_rss));
         }
+        near_vm_logic::set_ext_cost_counting(true);
 
         res
     }
 
+    /// Measures `tx` alone in a block, averaged over `iter_per_block` blocks after
+    /// `warmup_iters_per_block` warm up blocks. Each block holds a copy of `tx` signed
+    /// again by the `TransactionBuilder` with a fresh nonce, so the signer must be one of
+    /// the testbed accounts. A transaction to another account is expected to take one
+    /// extra block for its receipt, a transaction to the signer itself none.
+    #[track_caller]
+    pub(crate) fn measure_transaction(
+        &mut self,
+        tx: SignedTransaction,
+    ) -> (GasCost, HashMap<ExtCosts, u64>) {
+        let tx = tx.transaction;
+        let block_latency = if tx.signer_id == tx.receiver_id { 0 } else { 1 };
+        let n_blocks = self.config.warmup_iters_per_block + self.config.iter_per_block;
+        let blocks = (0..n_blocks)
+            .map(|_| {
+                vec![self.transaction_builder.transaction_from_actions(
+                    tx.signer_id.clone(),
+                    tx.receiver_id.clone(),
+                    tx.actions.clone(),
+                )]
+            })
+            .collect();
+
+        let measurements = self.measure_blocks(blocks, block_latency).into_blocks();
+        let measurements =
+            measurements.into_iter().skip(self.config.warmup_iters_per_block).collect();
+        aggregate_per_block_measurements(self.config, 1, measurements, None)
+    }
+
+    /// Compares two workloads on the same testbed. Each round measures one
+    /// block built by `a` and one built by `b`, alternating which goes first,
+    /// so that noise shared by both, e.g. from caches warming up or background
+    /// load, mostly cancels out. This is much tighter than comparing the
+    /// results of two separate runs.
+    ///
+    /// The first `warmup_iters_per_block` rounds are discarded, the costs of
+    /// the following `rounds` rounds are aggregated per workload like in
+    /// `measure_transaction`, which also gives the ext costs of each workload.
+    /// Blocks of both workloads must take `block_latency` extra blocks, see
+    /// `measure_blocks`.
+    #[track_caller]
+    pub(crate) fn measure_ab(
+        &mut self,
+        a: impl Fn(&mut TransactionBuilder) -> Vec<SignedTransaction>,
+        b: impl Fn(&mut TransactionBuilder) -> Vec<SignedTransaction>,
+        block_latency: usize,
+        rounds: usize,
+    ) -> ((GasCost, HashMap<ExtCosts, u64>), (GasCost, HashMap<ExtCosts, u64>)) {
+        assert!(rounds > 0, "A/B measurement needs at least one round");
+        let mut measurements_a = Vec::with_capacity(rounds);
+        let mut measurements_b = Vec::with_capacity(rounds);
+        for round in 0..self.config.warmup_iters_per_block + rounds {
+            let a_first = round % 2 == 0;
+            let mut measured = vec![];
+            for is_a in [a_first, !a_first] {
+                let block = if is_a {
+                    a(&mut self.transaction_builder)
+                } else {
+                    b(&mut self.transaction_builder)
+                };
+                let mut blocks = self.measure_blocks(vec![block], block_latency).blocks;
+                measured.push((is_a, blocks.pop().unwrap()));
+            }
+            if round < self.config.warmup_iters_per_block {
+                continue;
+            }
+            for (is_a, measurement) in measured {
+                if is_a {
+                    measurements_a.push(measurement);
+                } else {
+                    measurements_b.push(measurement);
+                }
+            }
+        }
+        (
+            aggregate_per_block_measurements(self.config, 1, measurements_a, None),
+            aggregate_per_block_measurements(self.config, 1, measurements_b, None),
+        )
+    }
+
+    /// Processes `blocks` once without timing, reporting failed transactions
+    /// instead of panicking like `measure_blocks` does. Meant to validate a
+    /// workload cheaply before a long measurement.
+    ///
+    /// Transactions are applied one at a time so that each failure can be
+    /// attributed to the transaction causing it. The testbed state changes
+    /// (e.g. nonces are used up), so the measurement itself should use a
+    /// fresh testbed.
+    pub(crate) fn dry_run(
+        &mut self,
+        blocks: &[Vec<SignedTransaction>],
+    ) -> Result<(), Vec<TxError>> {
+        let mut errors = vec![];
+        for (block, transactions) in blocks.iter().enumerate() {
+            for tx in transactions {
+                let tx_hash = tx.get_hash();
+                match self.inner.try_process_block_until_no_receipts(std::slice::from_ref(tx)) {
+                    Ok(failures) => {
+                        errors.extend(failures.into_iter().filter_map(|outcome| {
+                            match outcome.outcome.status {
+                                near_primitives::transaction::ExecutionStatus::Failure(err) => {
+                                    Some(TxError { block, tx_hash, kind: TxErrorKind::Failed(err) })
+                                }
+                                _ => None,
+                            }
+                        }));
+                    }
+                    Err(err) => {
+                        errors.push(TxError { block, tx_hash, kind: TxErrorKind::Rejected(err) })
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     pub(crate) fn process_block<'a>(
         &'a mut self,
         block: Vec<SignedTransaction>,
@@ -143,3 +460,414 @@ impl<'c> Testbed<'c> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{EstimatorContext, TxErrorKind};
+    use crate::config::{Config, GasMetric};
+    use crate::gas_cost::{GasCost, NonNegativeTolerance};
+    use crate::rocksdb::RocksDBTestConfig;
+    use crate::transaction_builder::TransactionBuilder;
+    use crate::utils::{aggregate_per_block_measurements, generate_blocks, get_account_id};
+    use clap::Parser;
+    use genesis_populate::GenesisBuilder;
+    use near_chain_configs::GenesisValidationMode;
+    use near_primitives::transaction::{Action, DeployContractAction};
+    use near_vm_runner::internal::VMKind;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn test_config() -> Config {
+        Config {
+            warmup_iters_per_block: 0,
+            iter_per_block: 1,
+            active_accounts: 1,
+            block_size: 100,
+            state_dump_path: Default::default(),
+            metric: GasMetric::Time,
+            vm_kind: VMKind::Wasmer2,
+            costs_to_measure: None,
+            rocksdb_test_config: RocksDBTestConfig::parse_from(["test"]),
+            debug: false,
+            json_output: false,
+            drop_os_cache: false,
+            pin_to_core: None,
+            probe_memory: false,
+            noise_floor: 0.into(),
+            collect_ext_costs: true,
+            seed: None,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn test_cost_above_noop() {
+        let config = test_config();
+        let mut ctx = EstimatorContext::new(&config);
+        // Avoid measuring the real noop cost, which requires a state dump.
+        ctx.cached.noop_function_call_cost = Some(GasCost::new_time_based(100));
+
+        let above = ctx.cost_above_noop(GasCost::new_time_based(150));
+        assert_eq!(above.to_gas(), GasCost::new_time_based(50).to_gas());
+        assert!(!above.is_uncertain());
+
+        let below = ctx.cost_above_noop(GasCost::new_time_based(40));
+        assert_eq!(below.to_gas(), 0);
+        assert!(below.is_uncertain());
+    }
+
+    /// Stands in for an estimation, which needs a state dump to run a testbed.
+    fn random_cost(ctx: &mut EstimatorContext) -> GasCost {
+        let accounts = (0..10).map(get_account_id).collect();
+        let mut tb = match ctx.seed {
+            Some(seed) => TransactionBuilder::new_seeded(accounts, seed),
+            None => TransactionBuilder::new(accounts),
+        };
+        let bytes = tb.random_vec(4);
+        let instructions = u32::from_le_bytes(bytes.try_into().unwrap());
+        GasCost::from_gas((instructions as u64 + 1).into(), ctx.config.metric)
+    }
+
+    #[test]
+    fn test_assert_deterministic() {
+        let config = test_config();
+        let mut ctx = EstimatorContext::new(&config);
+        let cost = ctx.assert_deterministic(random_cost, &NonNegativeTolerance::Strict);
+        assert!(cost.to_gas() > 0);
+        assert_eq!(ctx.seed, None);
+    }
+
+    /// Counting instructions only works inside QEMU with the counter plugin,
+    /// see `QemuCommandBuilder`, outside of it every measurement is zero.
+    #[test]
+    #[ignore = "needs to run inside QEMU to count instructions"]
+    fn test_provenance_reproduces_results() {
+        let dir = tempfile::tempdir().unwrap();
+        create_state_dump(dir.path(), 10, None);
+        let config = Config {
+            metric: GasMetric::ICount,
+            active_accounts: 10,
+            iter_per_block: 2,
+            ..test_config()
+        };
+        let provenance = config.provenance(42);
+        let json = serde_json::to_string(&provenance).unwrap();
+
+        let mut results = vec![];
+        for _ in 0..2 {
+            let provenance = serde_json::from_str(&json).unwrap();
+            let config = Config { state_dump_path: dir.path().to_path_buf(), ..test_config() }
+                .with_provenance(&provenance);
+            assert_eq!(config.provenance(42), provenance);
+            let mut ctx = EstimatorContext::new(&config);
+            results.push(crate::action_receipt_creation(&mut ctx));
+        }
+        let (first, second) = (results[0].to_gas(), results[1].to_gas());
+        assert!(first > 0);
+        assert!(first.abs_diff(second) <= first / 1000, "{first} vs {second}");
+    }
+
+    /// The part of `test_provenance_reproduces_results` which doesn't need
+    /// QEMU: the recorded provenance reproduces the measured workload.
+    #[test]
+    fn test_provenance_reproduces_workload() {
+        let config = Config { active_accounts: 10, iter_per_block: 2, ..test_config() };
+        let json = serde_json::to_string(&config.provenance(42)).unwrap();
+
+        let generate = |seed: Option<u64>| {
+            let provenance = serde_json::from_str(&json).unwrap();
+            let config = test_config().with_provenance(&provenance);
+            // Set up like `EstimatorContext::testbed_from_dump` does.
+            let accounts = (0..config.active_accounts).map(get_account_id).collect();
+            let mut tb = TransactionBuilder::new_seeded(accounts, seed.or(config.seed).unwrap());
+            let mut make_transaction = |tb: &mut TransactionBuilder| {
+                let (sender, receiver) = tb.random_account_pair();
+                tb.transaction_from_actions(sender, receiver, vec![])
+            };
+            let blocks = generate_blocks(&config, 3, &mut tb, &mut make_transaction);
+            assert_eq!(blocks.len(), 2);
+            blocks.iter().flatten().map(|tx| tx.get_hash()).collect::<Vec<_>>()
+        };
+        assert_eq!(generate(None), generate(None));
+        assert_ne!(generate(None), generate(Some(43)));
+    }
+
+    #[test]
+    #[should_panic(expected = "not deterministic")]
+    fn test_assert_deterministic_fails() {
+        let config = test_config();
+        let mut ctx = EstimatorContext::new(&config);
+        let runs = Cell::new(0u64);
+        let measure = |_: &mut EstimatorContext| {
+            runs.set(runs.get() + 1);
+            GasCost::new_time_based(100 * runs.get())
+        };
+        ctx.assert_deterministic(measure, &NonNegativeTolerance::PER_MILLE);
+    }
+
+    #[test]
+    fn test_below_noise_floor() {
+        let config = Config { noise_floor: 5.into(), ..test_config() };
+        // 100 transactions per block measured at 300ns: 3ns per transaction.
+        let measurements = vec![(GasCost::new_time_based(300), HashMap::new()); 3];
+        let (cost, _) = aggregate_per_block_measurements(&config, 100, measurements, None);
+        assert!(cost.is_uncertain());
+        assert!(cost.uncertain_message().unwrap().starts_with("BELOW-NOISE-FLOOR"));
+
+        let measurements = vec![(GasCost::new_time_based(1_000), HashMap::new()); 3];
+        let (cost, _) = aggregate_per_block_measurements(&config, 100, measurements, None);
+        assert!(!cost.is_uncertain());
+    }
+
+    #[test]
+    fn test_generate_blocks_follows_config() {
+        let config = Config { warmup_iters_per_block: 2, iter_per_block: 3, ..test_config() };
+        let block_size = 7;
+        let mut tb = TransactionBuilder::new((0..10).map(get_account_id).collect());
+        let mut make_transaction = |tb: &mut TransactionBuilder| {
+            let (sender, receiver) = tb.random_account_pair();
+            tb.transaction_from_actions(sender, receiver, vec![])
+        };
+
+        let blocks = generate_blocks(&config, block_size, &mut tb, &mut make_transaction);
+        assert_eq!(blocks.len(), 5);
+        assert!(blocks.iter().all(|block| block.len() == block_size));
+    }
+
+    /// Creates a state dump in `dir` like the estimator does by default, with
+    /// `accounts` additional accounts, which get `contract` deployed if given.
+    fn create_state_dump(dir: &Path, accounts: u64, contract: Option<Vec<u8>>) {
+        nearcore::init_configs(
+            dir,
+            None,
+            Some("test.near".parse().unwrap()),
+            Some("alice.near"),
+            1,
+            true,
+            None,
+            false,
+            None,
+            false,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let near_config = nearcore::load_config(dir, GenesisValidationMode::Full).unwrap();
+        let store = near_store::Store::opener(dir, &near_config.config.store).open();
+        let mut builder = GenesisBuilder::from_config_and_store(dir, near_config, store)
+            .add_additional_accounts(accounts);
+        if let Some(contract) = contract {
+            builder = builder.add_additional_accounts_contract(contract);
+        }
+        builder.build().unwrap().dump_state().unwrap();
+    }
+
+    #[test]
+    fn test_testbed_from_dump() {
+        let small = tempfile::tempdir().unwrap();
+        let large = tempfile::tempdir().unwrap();
+        create_state_dump(small.path(), 2, None);
+        create_state_dump(large.path(), 5, None);
+
+        let config = test_config();
+        let mut ctx = EstimatorContext::new(&config);
+        {
+            let testbed = ctx.testbed_from_dump(small.path());
+            assert!(testbed.inner.get_account(&get_account_id(1)).is_some());
+            assert!(testbed.inner.get_account(&get_account_id(4)).is_none());
+        }
+        {
+            let testbed = ctx.testbed_from_dump(large.path());
+            assert!(testbed.inner.get_account(&get_account_id(1)).is_some());
+            assert!(testbed.inner.get_account(&get_account_id(4)).is_some());
+            assert!(testbed.inner.get_account(&get_account_id(5)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_measure_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let contract = wat::parse_str(r#"(module (func (export "main")))"#).unwrap();
+        create_state_dump(dir.path(), 2, Some(contract));
+
+        let config = Config { warmup_iters_per_block: 1, iter_per_block: 2, ..test_config() };
+        let mut ctx = EstimatorContext::new(&config);
+        let mut testbed = ctx.testbed_from_dump(dir.path());
+        let tx = testbed.transaction_builder().transaction_from_function_call(
+            get_account_id(0),
+            "main",
+            vec![],
+        );
+        let (cost, _ext_costs) = testbed.measure_transaction(tx);
+        assert!(cost.to_gas() > 0);
+    }
+
+    #[test]
+    fn test_measure_blocks_without_ext_costs() {
+        let dir = tempfile::tempdir().unwrap();
+        let contract = wat::parse_str(
+            r#"(module
+                (import "env" "used_gas" (func $used_gas (result i64)))
+                (func (export "main") (drop (call $used_gas))))"#,
+        )
+        .unwrap();
+        create_state_dump(dir.path(), 2, Some(contract));
+
+        for collect_ext_costs in [true, false] {
+            let config = Config { collect_ext_costs, ..test_config() };
+            let mut ctx = EstimatorContext::new(&config);
+            let mut testbed = ctx.testbed_from_dump(dir.path());
+            let tx = testbed.transaction_builder().transaction_from_function_call(
+                get_account_id(0),
+                "main",
+                vec![],
+            );
+            let blocks = testbed.measure_blocks(vec![vec![tx]], 0).into_blocks();
+            let (cost, ext_costs) = &blocks[0];
+            assert!(cost.to_gas() > 0);
+            assert_eq!(ext_costs.is_empty(), !collect_ext_costs);
+        }
+    }
+
+    #[test]
+    fn test_measure_blocks_net_of_overhead() {
+        let dir = tempfile::tempdir().unwrap();
+        create_state_dump(dir.path(), 2, None);
+        let config = test_config();
+        let mut ctx = EstimatorContext::new(&config);
+        let mut testbed = ctx.testbed_from_dump(dir.path());
+
+        let overhead = testbed.measure_empty_block_overhead();
+        assert!(overhead.to_gas() > 0);
+        // Cached, not measured again.
+        assert_eq!(testbed.measure_empty_block_overhead(), overhead);
+
+        let block = |testbed: &mut super::Testbed| {
+            let tx = testbed.transaction_builder().transaction_from_actions(
+                get_account_id(0),
+                get_account_id(1),
+                vec![],
+            );
+            vec![tx]
+        };
+        let blocks = vec![block(&mut testbed)];
+        let gross = testbed.measure_blocks(blocks, 1);
+        let gross_cost = gross.blocks[0].0.clone();
+        let net = gross.net_of(&overhead).into_blocks();
+        let net_cost = &net[0].0;
+        assert!(*net_cost < gross_cost);
+        // Up to rounding, the difference is exactly the overhead.
+        let subtracted = gross_cost.to_gas() - net_cost.to_gas();
+        assert!(subtracted.abs_diff(overhead.to_gas()) <= overhead.to_gas() / 1000 + 1);
+
+        testbed.subtract_block_overhead = true;
+        let blocks = vec![block(&mut testbed)];
+        let net = testbed.measure_blocks(blocks, 1).into_blocks();
+        assert_eq!(net.len(), 1);
+        assert_eq!(testbed.measure_empty_block_overhead(), overhead);
+    }
+
+    #[test]
+    fn test_measure_ab() {
+        let dir = tempfile::tempdir().unwrap();
+        let contract = wat::parse_str(
+            r#"(module
+                (func (export "light"))
+                (func (export "heavy") (local $i i32)
+                    (loop $l
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br_if $l (i32.lt_u (local.get $i) (i32.const 100000))))))"#,
+        )
+        .unwrap();
+        create_state_dump(dir.path(), 2, Some(contract));
+
+        let config = Config { warmup_iters_per_block: 1, ..test_config() };
+        let mut ctx = EstimatorContext::new(&config);
+        let mut testbed = ctx.testbed_from_dump(dir.path());
+        let call = |method: &'static str| {
+            move |tb: &mut TransactionBuilder| {
+                vec![tb.transaction_from_function_call(get_account_id(0), method, vec![])]
+            }
+        };
+        for _ in 0..3 {
+            let ((heavy, _), (light, _)) = testbed.measure_ab(call("heavy"), call("light"), 0, 3);
+            assert!(heavy > light, "{heavy:?} should be more than {light:?}");
+        }
+    }
+
+    #[test]
+    fn test_dry_run_reports_invalid_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        create_state_dump(dir.path(), 2, None);
+
+        let config = test_config();
+        let mut ctx = EstimatorContext::new(&config);
+        let mut testbed = ctx.testbed_from_dump(dir.path());
+        let tb = testbed.transaction_builder();
+        let valid = tb.transaction_from_actions(get_account_id(0), get_account_id(1), vec![]);
+        // The signer account is not in the state dump.
+        let invalid = tb.transaction_from_actions(get_account_id(100), get_account_id(0), vec![]);
+        let invalid_hash = invalid.get_hash();
+
+        let blocks = vec![vec![valid], vec![invalid]];
+        let errors = testbed.dry_run(&blocks).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].block, 1);
+        assert_eq!(errors[0].tx_hash, invalid_hash);
+        assert!(matches!(errors[0].kind, TxErrorKind::Rejected(_)));
+    }
+
+    #[test]
+    fn test_measure_blocks_with_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        create_state_dump(dir.path(), 2, None);
+        let code = wat::parse_str(format!(
+            r#"(module (memory 16) (data (i32.const 0) "{}"))"#,
+            "x".repeat(100_000)
+        ))
+        .unwrap();
+
+        for probe_memory in [true, false] {
+            let config = Config { probe_memory, ..test_config() };
+            let mut ctx = EstimatorContext::new(&config);
+            let mut testbed = ctx.testbed_from_dump(dir.path());
+            let blocks = (0..2)
+                .map(|i| {
+                    let account = get_account_id(i);
+                    let deploy =
+                        Action::DeployContract(DeployContractAction { code: code.clone() });
+                    vec![testbed.transaction_builder().transaction_from_actions(
+                        account.clone(),
+                        account,
+                        vec![deploy],
+                    )]
+                })
+                .collect();
+            let results = testbed.measure_blocks_with_memory(blocks, 0);
+            assert_eq!(results.len(), 2);
+            for (gas_cost, _ext_costs, peak_rss) in results {
+                assert!(gas_cost.to_gas() > 0);
+                if probe_memory && cfg!(target_os = "linux") {
+                    assert!(peak_rss.unwrap() > 0);
+                } else {
+                    assert_eq!(peak_rss, None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_measure_blocks_metric() {
+        let dir = tempfile::tempdir().unwrap();
+        create_state_dump(dir.path(), 2, None);
+
+        let config = test_config();
+        let mut ctx = EstimatorContext::new(&config);
+        let mut testbed = ctx.testbed_from_dump(dir.path());
+        let measured = testbed.measure_blocks(vec![vec![]; 2], 0);
+        assert_eq!(measured.metric, config.metric);
+        assert_eq!(measured.into_blocks().len(), 2);
+    }
+}
@@ -6,6 +6,7 @@ use std::{fmt, ops};
 use near_primitives::types::Gas;
 use num_rational::Ratio;
 use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::config::GasMetric;
@@ -51,6 +52,10 @@ struct MeasurementUncertainty {
 }
 
 impl GasCost {
+    pub(crate) fn metric(&self) -> GasMetric {
+        self.metric
+    }
+
     pub(crate) fn zero(metric: GasMetric) -> GasCost {
         GasCost {
             metric,
@@ -92,6 +97,16 @@ impl GasCost {
     pub(crate) fn set_uncertain(&mut self, reason: &'static str) {
         self.uncertain = Some(MeasurementUncertainty { reason, location: Location::caller() });
     }
+    /// Whether the measured value is too small to be told apart from noise.
+    ///
+    /// `floor` is in nanoseconds for `GasMetric::Time` and in instructions for
+    /// `GasMetric::ICount`. IO bytes are not taken into account.
+    pub(crate) fn is_below_noise_floor(&self, floor: Ratio<u64>) -> bool {
+        match self.metric {
+            GasMetric::ICount => self.instructions < floor,
+            GasMetric::Time => self.time_ns < floor,
+        }
+    }
     /// Performs least squares using a separate variable for each component of the gas cost.
     ///
     /// Least-squares linear regression sometimes to produces negative
@@ -148,6 +163,23 @@ impl GasCost {
         pos
     }
 
+    /// Subtracts two gas costs from each other, returning `None` if any of the
+    /// measured values would underflow.
+    pub(crate) fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        assert_eq!(self.metric, rhs.metric);
+        let mut result = GasCost {
+            time_ns: checked_sub(self.time_ns, rhs.time_ns)?,
+            instructions: checked_sub(self.instructions, rhs.instructions)?,
+            io_r_bytes: checked_sub(self.io_r_bytes, rhs.io_r_bytes)?,
+            io_w_bytes: checked_sub(self.io_w_bytes, rhs.io_w_bytes)?,
+            metric: self.metric,
+            uncertain: None,
+        };
+        result.combine_uncertain(self);
+        result.combine_uncertain(rhs);
+        Some(result)
+    }
+
     fn saturating_sub_no_uncertain_check(&self, rhs: &Self) -> Self {
         assert_eq!(self.metric, rhs.metric);
         GasCost {
@@ -256,7 +288,7 @@ impl NonNegativeTolerance {
     pub(crate) const PER_MILLE: NonNegativeTolerance =
         NonNegativeTolerance::RelativeTolerance(0.001);
 
-    fn tolerates(&self, pos: &GasCost, neg: &GasCost) -> bool {
+    pub(crate) fn tolerates(&self, pos: &GasCost, neg: &GasCost) -> bool {
         match self {
             NonNegativeTolerance::Strict => neg.to_gas() == 0,
             NonNegativeTolerance::RelativeTolerance(rel_tolerance) => {
@@ -478,6 +510,14 @@ impl ops::Div<u64> for GasCost {
     }
 }
 
+fn checked_sub(a: Ratio<u64>, b: Ratio<u64>) -> Option<Ratio<u64>> {
+    if a < b {
+        None
+    } else {
+        Some(a - b)
+    }
+}
+
 fn saturating_sub(a: Ratio<u64>, b: Ratio<u64>) -> Ratio<u64> {
     if a < b {
         0.into()
@@ -512,9 +552,128 @@ impl GasCost {
     }
 }
 
+/// A `GasCost` together with the name of the parameter it was measured for.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) struct NamedGasCost {
+    pub(crate) name: String,
+    #[serde(with = "gas_cost_serde")]
+    pub(crate) cost: GasCost,
+}
+
+impl NamedGasCost {
+    pub(crate) fn new(name: impl Into<String>, cost: GasCost) -> Self {
+        NamedGasCost { name: name.into(), cost }
+    }
+
+    pub(crate) fn sort_by_name(costs: &mut [NamedGasCost]) {
+        costs.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Parses the lines printed by `run` with `Config::json_output` set. Only the
+    /// last measurement of each name is kept. Sorted by name.
+    pub(crate) fn parse_json_lines(json_lines: &str) -> anyhow::Result<Vec<NamedGasCost>> {
+        let mut costs = json_lines
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line))
+            .collect::<Result<Vec<NamedGasCost>, _>>()?;
+        // The sort is stable, so after reversing the last measurement of each name comes first.
+        costs.reverse();
+        Self::sort_by_name(&mut costs);
+        costs.dedup_by(|a, b| a.name == b.name);
+        Ok(costs)
+    }
+
+    /// Compares two sets of results by name and returns the names for which
+    /// the gas differs, with the old and the new gas. Names present in only
+    /// one of the sets have `None` on the other side. Sorted by name.
+    pub(crate) fn diff_by_name(
+        old: &[NamedGasCost],
+        new: &[NamedGasCost],
+    ) -> Vec<(String, Option<Gas>, Option<Gas>)> {
+        use std::collections::BTreeMap;
+
+        let mut gas: BTreeMap<&str, (Option<Gas>, Option<Gas>)> = BTreeMap::new();
+        for c in old {
+            gas.entry(&c.name).or_default().0 = Some(c.cost.to_gas());
+        }
+        for c in new {
+            gas.entry(&c.name).or_default().1 = Some(c.cost.to_gas());
+        }
+        gas.into_iter()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(name, (old, new))| (name.to_string(), old, new))
+            .collect()
+    }
+}
+
+/// (De)serialization of all the fields of `GasCost`, unlike `GasCost::to_json`
+/// which only exports the values relevant for the metric.
+mod gas_cost_serde {
+    use super::{GasCost, MeasurementUncertainty};
+    use crate::config::GasMetric;
+    use num_rational::Ratio;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::panic::Location;
+
+    #[derive(Serialize, Deserialize)]
+    struct GasCostRepr {
+        metric: GasMetric,
+        time_ns: (u64, u64),
+        instructions: (u64, u64),
+        io_r_bytes: (u64, u64),
+        io_w_bytes: (u64, u64),
+        uncertain_reason: Option<String>,
+    }
+
+    fn to_pair(r: &Ratio<u64>) -> (u64, u64) {
+        (*r.numer(), *r.denom())
+    }
+
+    fn from_pair<E: Error>((numer, denom): (u64, u64)) -> Result<Ratio<u64>, E> {
+        if denom == 0 {
+            return Err(E::custom("zero denominator"));
+        }
+        Ok(Ratio::new(numer, denom))
+    }
+
+    pub(super) fn serialize<S: Serializer>(cost: &GasCost, s: S) -> Result<S::Ok, S::Error> {
+        GasCostRepr {
+            metric: cost.metric,
+            time_ns: to_pair(&cost.time_ns),
+            instructions: to_pair(&cost.instructions),
+            io_r_bytes: to_pair(&cost.io_r_bytes),
+            io_w_bytes: to_pair(&cost.io_w_bytes),
+            uncertain_reason: cost.uncertain.map(|u| u.reason.to_string()),
+        }
+        .serialize(s)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<GasCost, D::Error> {
+        let repr = GasCostRepr::deserialize(d)?;
+        Ok(GasCost {
+            metric: repr.metric,
+            time_ns: from_pair(repr.time_ns)?,
+            instructions: from_pair(repr.instructions)?,
+            io_r_bytes: from_pair(repr.io_r_bytes)?,
+            io_w_bytes: from_pair(repr.io_w_bytes)?,
+            // Reasons are `&'static str`. Leaking is fine, there are only a
+            // few uncertain costs per estimation run. The original location
+            // is lost.
+            uncertain: repr.uncertain_reason.map(|reason| MeasurementUncertainty {
+                reason: Box::leak(reason.into_boxed_str()),
+                location: Location::caller(),
+            }),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{least_squares_method_gas_cost_pos_neg, GasCost, LeastSquaresTolerance};
+    use super::{
+        least_squares_method_gas_cost_pos_neg, GasCost, LeastSquaresTolerance, NamedGasCost,
+    };
     use crate::{
         config::GasMetric,
         estimator_params::{GAS_IN_INSTR, GAS_IN_NS, IO_READ_BYTE_COST, IO_WRITE_BYTE_COST},
@@ -564,6 +723,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn below_noise_floor() {
+        let floor = Ratio::from(5);
+        assert!(GasCost::new_time_based(Ratio::new(9, 2)).is_below_noise_floor(floor));
+        assert!(!GasCost::new_time_based(5).is_below_noise_floor(floor));
+        assert!(!GasCost::new_time_based(1_000).is_below_noise_floor(floor));
+
+        // IO bytes do not lift an icount measurement above the floor.
+        assert!(GasCost::new_icount_based(4, 100, 100).is_below_noise_floor(floor));
+        assert!(!GasCost::new_icount_based(5, 0, 0).is_below_noise_floor(floor));
+        assert!(!GasCost::zero(GasMetric::Time).is_below_noise_floor(0.into()));
+    }
+
     fn abs_tolerance(base: Gas, factor: Gas) -> LeastSquaresTolerance {
         LeastSquaresTolerance::default().base_abs_nn_tolerance(base).factor_abs_nn_tolerance(factor)
     }
@@ -784,4 +956,69 @@ mod tests {
             true,
         );
     }
+
+    #[test]
+    fn named_gas_cost_serde_round_trip() {
+        let mut uncertain = GasCost::new_icount_based(Ratio::new(7, 3), 100, 0);
+        uncertain.set_uncertain("TEST");
+        let costs = vec![
+            NamedGasCost::new("ActionTransfer", GasCost::new_time_based(Ratio::new(11, 10))),
+            NamedGasCost::new("ActionCreateAccount", uncertain),
+        ];
+        let json = serde_json::to_string(&costs).unwrap();
+        let got: Vec<NamedGasCost> = serde_json::from_str(&json).unwrap();
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0], costs[0]);
+        assert_eq!(got[1].name, costs[1].name);
+        assert_eq!(got[1].cost.to_gas(), costs[1].cost.to_gas());
+        assert!(got[1].cost.uncertain_message().unwrap().starts_with("TEST: "));
+    }
+
+    #[test]
+    fn named_gas_cost_sort_and_diff() {
+        let mut old = vec![
+            NamedGasCost::new("b", GasCost::new_time_based(2)),
+            NamedGasCost::new("a", GasCost::new_time_based(1)),
+            NamedGasCost::new("c", GasCost::new_time_based(3)),
+        ];
+        NamedGasCost::sort_by_name(&mut old);
+        let names: Vec<_> = old.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+
+        let gas = |ns: u64| GasCost::new_time_based(ns).to_gas();
+        let new = vec![
+            NamedGasCost::new("d", GasCost::new_time_based(4)),
+            NamedGasCost::new("a", GasCost::new_time_based(1)),
+            NamedGasCost::new("b", GasCost::new_time_based(5)),
+        ];
+        assert_eq!(
+            NamedGasCost::diff_by_name(&old, &new),
+            vec![
+                ("b".to_string(), Some(gas(2)), Some(gas(5))),
+                ("c".to_string(), Some(gas(3)), None),
+                ("d".to_string(), None, Some(gas(4))),
+            ]
+        );
+    }
+
+    #[test]
+    fn named_gas_cost_parse_json_lines() {
+        let line = |name: &str, ns: u64| {
+            let mut json =
+                serde_json::to_value(NamedGasCost::new(name, GasCost::new_time_based(ns))).unwrap();
+            // Extra fields printed by `run` are ignored.
+            json["result"] = GasCost::new_time_based(ns).to_json();
+            json.to_string()
+        };
+        let output = [line("b", 1), line("a", 2), String::new(), line("b", 3)].join("\n");
+        let costs = NamedGasCost::parse_json_lines(&output).unwrap();
+        assert_eq!(
+            costs,
+            vec![
+                NamedGasCost::new("a", GasCost::new_time_based(2)),
+                NamedGasCost::new("b", GasCost::new_time_based(3)),
+            ]
+        );
+        assert!(NamedGasCost::parse_json_lines("a 1").is_err());
+    }
 }
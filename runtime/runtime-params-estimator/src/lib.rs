@@ -103,7 +103,7 @@ use vm_estimator::{compile_single_contract_cost, compute_compile_cost_vm};
 use crate::config::Config;
 use crate::cost_table::format_gas;
 use crate::estimator_context::EstimatorContext;
-use crate::gas_cost::GasCost;
+use crate::gas_cost::{GasCost, NamedGasCost};
 use crate::rocksdb::{rocks_db_inserts_cost, rocks_db_read_cost};
 use crate::transaction_builder::TransactionBuilder;
 use crate::vm_estimator::create_context;
@@ -114,6 +114,11 @@ pub use crate::costs_to_runtime_config::costs_to_runtime_config;
 pub use crate::qemu::QemuCommandBuilder;
 pub use crate::rocksdb::RocksDBTestConfig;
 
+/// Upper bound on the number of transactions per measured block for estimations
+/// where each transaction carries megabytes of code or arguments. These are gas
+/// heavy and slow to apply, so `Config::block_size` can only lower it.
+const HEAVY_BLOCK_SIZE: usize = 5;
+
 static ALL_COSTS: &[(Cost, fn(&mut EstimatorContext) -> GasCost)] = &[
     (Cost::ActionReceiptCreation, action_receipt_creation),
     (Cost::ActionSirReceiptCreation, action_sir_receipt_creation),
@@ -209,6 +214,10 @@ static REAL_CONTRACTS_SAMPLE: [(&str, &str); 4] = [
 ];
 
 pub fn run(config: Config) -> CostTable {
+    let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let config = Config { seed: Some(seed), ..config };
+    let provenance = config.provenance(seed);
+    eprintln!("Provenance: {provenance:?}");
     let mut ctx = EstimatorContext::new(&config);
     let mut res = CostTable::default();
 
@@ -222,29 +231,29 @@ pub fn run(config: Config) -> CostTable {
         }
 
         let start = Instant::now();
-        let measurement = f(&mut ctx);
+        let measurement = NamedGasCost::new(cost.to_string(), f(&mut ctx));
         let time = start.elapsed();
-        let name = cost.to_string();
-        let uncertain = if measurement.is_uncertain() { "UNCERTAIN " } else { "" };
-        let gas = measurement.to_gas();
+        let uncertain = if measurement.cost.is_uncertain() { "UNCERTAIN " } else { "" };
+        let gas = measurement.cost.to_gas();
         res.add(cost, gas);
 
         eprintln!(
             "{:<40} {:>25} gas [{:>25}] {:<10}(computed in {:.2?}) {}",
-            name,
+            measurement.name,
             format_gas(gas),
-            format!("{:?}", measurement),
+            format!("{:?}", measurement.cost),
             uncertain,
             time,
-            measurement.uncertain_message().unwrap_or_default(),
+            measurement.cost.uncertain_message().unwrap_or_default(),
         );
 
         if config.json_output {
-            let json = json! ({
-                "name": name,
-                "result": measurement.to_json(),
-                "computed_in": time,
-            });
+            // `name` and `cost` are the serde form of `NamedGasCost`, which is what
+            // `diff_json_output` reads back. `result` has only the values relevant for the metric.
+            let mut json = serde_json::to_value(&measurement).unwrap();
+            json["result"] = measurement.cost.to_json();
+            json["computed_in"] = json!(time);
+            json["provenance"] = json!(provenance);
             println!("{json}");
         }
     }
@@ -253,6 +262,21 @@ pub fn run(config: Config) -> CostTable {
     res
 }
 
+/// Compares two outputs of `run` with `Config::json_output` set and lists the
+/// estimations whose gas differs, sorted by name. An estimation measured several
+/// times within one output is compared by its last measurement.
+pub fn diff_json_output(baseline: &str, compare_to: &str) -> anyhow::Result<String> {
+    let baseline = NamedGasCost::parse_json_lines(baseline)?;
+    let compare_to = NamedGasCost::parse_json_lines(compare_to)?;
+    let mut res = format!("{:<35} {:>25} {:>25}\n", "Cost", "First", "Second");
+    for (name, first, second) in NamedGasCost::diff_by_name(&baseline, &compare_to) {
+        let first = first.map_or_else(|| "-".to_string(), format_gas);
+        let second = second.map_or_else(|| "-".to_string(), format_gas);
+        res += &format!("{:<35} {:>25} {:>25}\n", name, first, second);
+    }
+    Ok(res)
+}
+
 fn action_receipt_creation(ctx: &mut EstimatorContext) -> GasCost {
     if let Some(cached) = ctx.cached.action_receipt_creation.clone() {
         return cached;
@@ -263,7 +287,7 @@ fn action_receipt_creation(ctx: &mut EstimatorContext) -> GasCost {
 
         tb.transaction_from_actions(sender, receiver, vec![])
     };
-    let block_size = 100;
+    let block_size = ctx.config.block_size;
     // Sender != Receiver means this will be executed over two blocks.
     let block_latency = 1;
     let cost = transaction_cost_ext(ctx, block_size, &mut make_transaction, block_latency).0;
@@ -300,7 +324,7 @@ fn action_transfer(ctx: &mut EstimatorContext) -> GasCost {
             let actions = vec![Action::Transfer(TransferAction { deposit: 1 })];
             tb.transaction_from_actions(sender, receiver, actions)
         };
-        let block_size = 100;
+        let block_size = ctx.config.block_size;
         // Transferring from one account to another may touch two shards, thus executes over two blocks.
         let block_latency = 1;
         transaction_cost_ext(ctx, block_size, &mut make_transaction, block_latency).0
@@ -324,7 +348,7 @@ fn action_create_account(ctx: &mut EstimatorContext) -> GasCost {
             ];
             tb.transaction_from_actions(sender, new_account, actions)
         };
-        let block_size = 100;
+        let block_size = ctx.config.block_size;
         // Creating a new account is initiated by an account that potentially is on a different shard. Thus, it executes over two blocks.
         let block_latency = 1;
         transaction_cost_ext(ctx, block_size, &mut make_transaction, block_latency).0
@@ -345,7 +369,7 @@ fn action_delete_account(ctx: &mut EstimatorContext) -> GasCost {
             let actions = vec![Action::DeleteAccount(DeleteAccountAction { beneficiary_id })];
             tb.transaction_from_actions(sender, receiver, actions)
         };
-        let block_size = 100;
+        let block_size = ctx.config.block_size;
         // Deleting an account is initiated by an account that potentially is on a different shard. Thus, it executes over two blocks.
         let block_latency = 1;
         transaction_cost_ext(ctx, block_size, &mut make_transaction, block_latency).0
@@ -590,8 +614,7 @@ fn deploy_contract_cost(
         let actions = vec![Action::DeployContract(DeployContractAction { code: code_factory() })];
         tb.transaction_from_actions(sender, receiver, actions)
     };
-    // Use a small block size since deployments are gas heavy.
-    let block_size = 5;
+    let block_size = ctx.config.block_size.min(HEAVY_BLOCK_SIZE);
     let (total_cost, _ext) = transaction_cost_ext(ctx, block_size, &mut make_transaction, 0);
     let base_cost = action_sir_receipt_creation(ctx);
 
@@ -692,7 +715,7 @@ fn inner_action_function_call_per_byte(ctx: &mut EstimatorContext, arg_len: usiz
         let args = tb.random_vec(arg_len);
         tb.transaction_from_function_call(sender, "noop", args)
     };
-    let block_size = 5;
+    let block_size = ctx.config.block_size.min(HEAVY_BLOCK_SIZE);
     let block_latency = 0;
     transaction_cost_ext(ctx, block_size, &mut make_transaction, block_latency).0
 }
@@ -716,7 +739,7 @@ fn contract_loading_base_per_byte(ctx: &mut EstimatorContext) -> (GasCost, GasCo
 }
 fn function_call_per_storage_byte(ctx: &mut EstimatorContext) -> GasCost {
     let vm_config = VMConfig::test();
-    let block_size = 5;
+    let block_size = ctx.config.block_size.min(HEAVY_BLOCK_SIZE);
 
     let small_code = generate_data_only_contract(0, &vm_config);
     let small_cost = fn_cost_in_contract(ctx, "main", &small_code, block_size);
@@ -762,9 +785,7 @@ fn host_function_call(ctx: &mut EstimatorContext) -> GasCost {
     let (total_cost, count) = fn_cost_count(ctx, "base_1M", ExtCosts::base, block_latency);
     assert_eq!(count, 1_000_000);
 
-    let base_cost = noop_function_call_cost(ctx);
-
-    total_cost.saturating_sub(&base_cost, &NonNegativeTolerance::PER_MILLE) / count
+    ctx.cost_above_noop(total_cost) / count
 }
 
 fn wasm_instruction(ctx: &mut EstimatorContext) -> GasCost {
@@ -1064,12 +1085,10 @@ fn touching_trie_node_read(ctx: &mut EstimatorContext) -> GasCost {
     if let Some(cost) = ctx.cached.touching_trie_node_read.clone() {
         return cost;
     }
-    let warmup_iters = ctx.config.warmup_iters_per_block;
-    let measured_iters = ctx.config.iter_per_block;
     // Number of bytes in the final key. Will create 2x that many nodes.
     // Picked somewhat arbitrarily, balancing estimation time vs accuracy.
     let final_key_len = 1000;
-    let cost = trie::read_node_from_db(ctx, warmup_iters, measured_iters, final_key_len);
+    let cost = trie::read_node_from_db(ctx, final_key_len);
 
     ctx.cached.touching_trie_node_read = Some(cost.clone());
     cost
@@ -1079,12 +1098,10 @@ fn touching_trie_node_write(ctx: &mut EstimatorContext) -> GasCost {
     if let Some(cost) = ctx.cached.touching_trie_node_write.clone() {
         return cost;
     }
-    let warmup_iters = ctx.config.warmup_iters_per_block;
-    let measured_iters = ctx.config.iter_per_block;
     // Number of bytes in the final key. Will create 2x that many nodes.
     // Picked somewhat arbitrarily, balancing estimation time vs accuracy.
     let final_key_len = 1000;
-    let cost = trie::write_node(ctx, warmup_iters, measured_iters, final_key_len);
+    let cost = trie::write_node(ctx, final_key_len);
 
     ctx.cached.touching_trie_node_write = Some(cost.clone());
     cost
@@ -1112,7 +1129,7 @@ fn apply_block_cost(ctx: &mut EstimatorContext) -> GasCost {
     let n_blocks = testbed.config.warmup_iters_per_block + testbed.config.iter_per_block;
     let blocks = vec![vec![]; n_blocks];
 
-    let measurements = testbed.measure_blocks(blocks, 0);
+    let measurements = testbed.measure_blocks(blocks, 0).into_blocks();
     let measurements =
         measurements.into_iter().skip(testbed.config.warmup_iters_per_block).collect::<Vec<_>>();
     let (gas_cost, _ext_costs) =
@@ -37,6 +37,10 @@ struct CliArgs {
     /// Number of additional accounts to add to the state, among which active accounts are selected.
     #[clap(long, default_value = "200000")]
     additional_accounts_num: u64,
+    /// Number of transactions per measured block, for estimations that don't
+    /// depend on a specific block size.
+    #[clap(long, default_value = "100")]
+    block_size: usize,
     /// Skip building test contract which is used in metrics computation.
     #[clap(long)]
     skip_build_test_contract: bool,
@@ -49,7 +53,8 @@ struct CliArgs {
     /// Render existing `costs.txt` as `RuntimeConfig`.
     #[clap(long)]
     costs_file: Option<PathBuf>,
-    /// Compare baseline `costs-file` with a different costs file.
+    /// Compare baseline `costs-file` with a different costs file. Both files can
+    /// also hold the `--json-output` of a run instead.
     #[clap(long, requires("costs-file"))]
     compare_to: Option<PathBuf>,
     /// Coma-separated lists of a subset of costs to estimate.
@@ -69,6 +74,33 @@ struct CliArgs {
     /// Drop OS cache before measurements for better IO accuracy. Requires sudo.
     #[clap(long)]
     drop_os_cache: bool,
+    /// Pin the measurement thread to the given CPU core to reduce jitter of
+    /// time based measurements. Only supported on Linux.
+    #[clap(long)]
+    pin_to_core: Option<usize>,
+    /// Report the peak memory usage (RSS) for each measured block. Only
+    /// supported on Linux.
+    #[clap(long)]
+    probe_memory: bool,
+    /// Flag estimates below this value as not reliably measurable. In
+    /// nanoseconds for the time metric and in instructions for icount.
+    /// Defaults to a small metric-specific value.
+    #[clap(long)]
+    noise_floor: Option<u64>,
+    /// Don't count ext costs of the measured blocks, to remove the counting
+    /// overhead from time based measurements. Estimations which rely on the
+    /// counts will fail.
+    #[clap(long)]
+    skip_ext_costs: bool,
+    /// Seed for generating transactions, to reproduce results reported with
+    /// it. A random one is used by default.
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Check that the generated transactions succeed before measuring them,
+    /// failing early with a list of the broken ones instead of panicking in
+    /// the middle of a long measurement.
+    #[clap(long)]
+    dry_run: bool,
     /// Print extra debug information.
     #[clap(long)]
     debug: bool,
@@ -175,6 +207,13 @@ fn main() -> anyhow::Result<()> {
     if let Some(compare_to) = cli_args.compare_to {
         let baseline = cli_args.costs_file.unwrap();
 
+        if is_json_output(&baseline)? {
+            let baseline = fs::read_to_string(&baseline)?;
+            let compare_to = fs::read_to_string(&compare_to)?;
+            println!("{}", runtime_params_estimator::diff_json_output(&baseline, &compare_to)?);
+            return Ok(());
+        }
+
         let compare_to = read_costs_table(&compare_to)?;
         let baseline = read_costs_table(&baseline)?;
         println!("{}", baseline.diff(&compare_to));
@@ -235,7 +274,7 @@ fn main() -> anyhow::Result<()> {
         warmup_iters_per_block,
         iter_per_block,
         active_accounts,
-        block_sizes: vec![],
+        block_size: cli_args.block_size,
         state_dump_path: state_dump_path,
         metric,
         vm_kind,
@@ -244,6 +283,12 @@ fn main() -> anyhow::Result<()> {
         debug: cli_args.debug,
         json_output: cli_args.json_output,
         drop_os_cache: cli_args.drop_os_cache,
+        pin_to_core: cli_args.pin_to_core,
+        probe_memory: cli_args.probe_memory,
+        noise_floor: cli_args.noise_floor.unwrap_or(metric.default_noise_floor()).into(),
+        collect_ext_costs: !cli_args.skip_ext_costs,
+        seed: cli_args.seed,
+        dry_run: cli_args.dry_run,
     };
     let cost_table = runtime_params_estimator::run(config);
 
@@ -388,6 +433,13 @@ fn read_costs_table(path: &Path) -> anyhow::Result<CostTable> {
         .map_err(|()| anyhow::format_err!("failed to parse costs file: {}", path.display()))
 }
 
+/// Whether the file holds the `--json-output` of a run rather than a costs table.
+fn is_json_output(path: &Path) -> anyhow::Result<bool> {
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read costs file: {}", path.display()))?;
+    Ok(contents.trim_start().starts_with('{'))
+}
+
 fn exec(command: &str) -> anyhow::Result<String> {
     let args = command.split_ascii_whitespace().collect::<Vec<_>>();
     let (cmd, args) = args.split_first().unwrap();
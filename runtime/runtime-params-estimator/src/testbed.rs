@@ -1,14 +1,16 @@
 use genesis_populate::state_dump::StateDump;
+use near_primitives::account::Account;
+use near_primitives::errors::RuntimeError;
 use near_primitives::receipt::Receipt;
 use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
 use near_primitives::test_utils::MockEpochInfoProvider;
-use near_primitives::transaction::{ExecutionStatus, SignedTransaction};
-use near_primitives::types::{Gas, MerkleHash};
+use near_primitives::transaction::{ExecutionOutcomeWithId, ExecutionStatus, SignedTransaction};
+use near_primitives::types::{AccountId, Gas, MerkleHash};
 use near_primitives::version::PROTOCOL_VERSION;
 use near_store::{ShardTries, ShardUId, Store, StoreCompiledContractCache};
 use near_vm_logic::VMLimitConfig;
-use node_runtime::{ApplyState, Runtime};
+use node_runtime::{ApplyResult, ApplyState, Runtime};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -89,30 +91,35 @@ impl RuntimeTestbed {
         }
     }
 
-    pub fn process_block(
-        &mut self,
-        transactions: &[SignedTransaction],
-        allow_failures: bool,
-    ) -> Gas {
-        let apply_result = self
-            .runtime
-            .apply(
-                self.tries.get_trie_for_shard(ShardUId::single_shard()),
-                self.root,
-                &None,
-                &self.apply_state,
-                &self.prev_receipts,
-                transactions,
-                &self.epoch_info_provider,
-                None,
-            )
-            .unwrap();
+    /// Applies a block with `transactions` on top of the current state. If the
+    /// runtime rejects the block, e.g. because of an invalid transaction, the
+    /// state is left unchanged.
+    fn apply(&mut self, transactions: &[SignedTransaction]) -> Result<ApplyResult, RuntimeError> {
+        let apply_result = self.runtime.apply(
+            self.tries.get_trie_for_shard(ShardUId::single_shard()),
+            self.root,
+            &None,
+            &self.apply_state,
+            &self.prev_receipts,
+            transactions,
+            &self.epoch_info_provider,
+            None,
+        )?;
 
         let (store_update, root) =
             self.tries.apply_all(&apply_result.trie_changes, ShardUId::single_shard());
         self.root = root;
         store_update.commit().unwrap();
         self.apply_state.block_index += 1;
+        Ok(apply_result)
+    }
+
+    pub fn process_block(
+        &mut self,
+        transactions: &[SignedTransaction],
+        allow_failures: bool,
+    ) -> Gas {
+        let apply_result = self.apply(transactions).unwrap();
 
         let mut total_burnt_gas = 0;
         if !allow_failures {
@@ -128,6 +135,30 @@ impl RuntimeTestbed {
         total_burnt_gas
     }
 
+    /// Like `process_block` followed by `process_blocks_until_no_receipts`, but
+    /// never panics on failures. Returns the outcomes with a failed status, or
+    /// the error if the runtime rejected `transactions`.
+    pub fn try_process_block_until_no_receipts(
+        &mut self,
+        transactions: &[SignedTransaction],
+    ) -> Result<Vec<ExecutionOutcomeWithId>, RuntimeError> {
+        let mut failures = vec![];
+        let mut transactions = transactions;
+        loop {
+            let apply_result = self.apply(transactions)?;
+            failures.extend(
+                apply_result.outcomes.into_iter().filter(|outcome| {
+                    matches!(outcome.outcome.status, ExecutionStatus::Failure(_))
+                }),
+            );
+            self.prev_receipts = apply_result.outgoing_receipts;
+            if self.prev_receipts.is_empty() {
+                return Ok(failures);
+            }
+            transactions = &[];
+        }
+    }
+
     /// Returns the number of blocks required to reach quiescence
     pub fn process_blocks_until_no_receipts(&mut self, allow_failures: bool) -> usize {
         let mut n = 0;
@@ -146,4 +177,10 @@ impl RuntimeTestbed {
     pub fn store(&mut self) -> Store {
         self.tries.get_store()
     }
+
+    /// Reads an account from the current state.
+    pub fn get_account(&self, account_id: &AccountId) -> Option<Account> {
+        let state_update = self.tries.new_trie_update(ShardUId::single_shard(), self.root);
+        near_store::get_account(&state_update, account_id).unwrap()
+    }
 }
@@ -4,8 +4,8 @@ use near_crypto::{InMemorySigner, KeyType};
 use near_primitives::hash::CryptoHash;
 use near_primitives::transaction::{Action, FunctionCallAction, SignedTransaction};
 use near_primitives::types::AccountId;
-use rand::prelude::ThreadRng;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
 
 use crate::utils::get_account_id;
 /// A helper to create transaction for processing by a `TestBed`.
@@ -14,11 +14,23 @@ pub(crate) struct TransactionBuilder {
     accounts: Vec<AccountId>,
     nonces: HashMap<AccountId, u64>,
     used_accounts: HashSet<AccountId>,
+    rng: XorShiftRng,
 }
 
 impl TransactionBuilder {
     pub(crate) fn new(accounts: Vec<AccountId>) -> TransactionBuilder {
-        TransactionBuilder { accounts, nonces: HashMap::new(), used_accounts: HashSet::new() }
+        Self::new_seeded(accounts, rand::thread_rng().gen())
+    }
+
+    /// Like `new`, but all random choices are derived from `seed`, so that the
+    /// same sequence of calls produces the same transactions.
+    pub(crate) fn new_seeded(accounts: Vec<AccountId>, seed: u64) -> TransactionBuilder {
+        TransactionBuilder {
+            accounts,
+            nonces: HashMap::new(),
+            used_accounts: HashSet::new(),
+            rng: XorShiftRng::seed_from_u64(seed),
+        }
     }
 
     pub(crate) fn transaction_from_actions(
@@ -83,15 +95,16 @@ impl TransactionBuilder {
         self.transaction_from_function_call(account, "account_storage_has_key", arg)
     }
 
-    pub(crate) fn rng(&mut self) -> ThreadRng {
-        rand::thread_rng()
+    pub(crate) fn rng(&mut self) -> &mut XorShiftRng {
+        &mut self.rng
     }
 
     pub(crate) fn account(&mut self, account_index: usize) -> AccountId {
         get_account_id(account_index)
     }
     pub(crate) fn random_account(&mut self) -> AccountId {
-        let account_index = self.rng().gen_range(0, self.accounts.len());
+        let num_accounts = self.accounts.len();
+        let account_index = self.rng().gen_range(0, num_accounts);
         self.accounts[account_index].clone()
     }
     pub(crate) fn random_unused_account(&mut self) -> AccountId {
@@ -1,23 +1,17 @@
 use crate::estimator_context::{EstimatorContext, Testbed};
 use crate::gas_cost::{GasCost, NonNegativeTolerance};
-use crate::utils::{aggregate_per_block_measurements, overhead_per_measured_block, percentiles};
+use crate::transaction_builder::TransactionBuilder;
+use crate::utils::percentiles;
 use near_primitives::hash::hash;
 use near_primitives::types::TrieCacheMode;
 use near_store::{TrieCachingStorage, TrieStorage};
 use near_vm_logic::ExtCosts;
-use std::iter;
+use std::cell::Cell;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 static SINK: AtomicUsize = AtomicUsize::new(0);
 
-pub(crate) fn write_node(
-    ctx: &mut EstimatorContext,
-    warmup_iters: usize,
-    measured_iters: usize,
-    final_key_len: usize,
-) -> GasCost {
-    let block_latency = 0;
-    let overhead = overhead_per_measured_block(ctx, block_latency);
+pub(crate) fn write_node(ctx: &mut EstimatorContext, final_key_len: usize) -> GasCost {
     let mut testbed = ctx.testbed();
     let tb = testbed.transaction_builder();
     // Prepare a long chain in the trie
@@ -29,36 +23,24 @@ pub(crate) fn write_node(
         let value = b"0";
         setup_block.push(tb.account_insert_key(signer.clone(), key, value));
     }
-    let mut blocks = Vec::with_capacity(1 + 2 * warmup_iters + 2 * measured_iters);
-    blocks.push(setup_block);
-    blocks.extend(
-        [b"1", b"2", b"3"]
-            .iter()
-            .cycle()
-            .map(|value| vec![tb.account_insert_key(signer.clone(), &key.as_bytes()[0..1], *value)])
-            .take(measured_iters + warmup_iters),
-    );
-    blocks.extend(
-        [b"1", b"2", b"3"]
-            .iter()
-            .cycle()
-            .map(|value| vec![tb.account_insert_key(signer.clone(), key.as_bytes(), *value)])
-            .take(measured_iters + warmup_iters),
-    );
-    let results = &testbed.measure_blocks(blocks, block_latency)[1..];
-    let (short_key_results, long_key_results) = results.split_at(measured_iters + warmup_iters);
-    let (cost_short_key, ext_cost_short_key) = aggregate_per_block_measurements(
-        testbed.config,
-        1,
-        short_key_results[warmup_iters..].to_vec(),
-        Some(overhead.clone()),
-    );
-    let (cost_long_key, ext_cost_long_key) = aggregate_per_block_measurements(
-        testbed.config,
-        1,
-        long_key_results[warmup_iters..].to_vec(),
-        Some(overhead),
-    );
+    testbed.process_block(setup_block, 0);
+    // Interleave writes to the short and the long key, so that noise affects both alike. Each
+    // write stores a different value than the previous one under the same key.
+    let values = [b"1", b"2", b"3"];
+    let write = |tb: &mut TransactionBuilder, key: &[u8], writes: &Cell<usize>| {
+        let value = values[writes.get() % values.len()];
+        writes.set(writes.get() + 1);
+        vec![tb.account_insert_key(signer.clone(), key, value)]
+    };
+    let (short_key_writes, long_key_writes) = (Cell::new(0), Cell::new(0));
+    let rounds = testbed.config.iter_per_block;
+    let ((cost_short_key, ext_cost_short_key), (cost_long_key, ext_cost_long_key)) = testbed
+        .measure_ab(
+            |tb| write(tb, &key.as_bytes()[0..1], &short_key_writes),
+            |tb| write(tb, key.as_bytes(), &long_key_writes),
+            0,
+            rounds,
+        );
     let nodes_touched_delta = ext_cost_long_key[&ExtCosts::touching_trie_node]
         - ext_cost_short_key[&ExtCosts::touching_trie_node];
     // The exact number of touched nodes is a implementation that we don't want
@@ -71,14 +53,7 @@ pub(crate) fn write_node(
     cost
 }
 
-pub(crate) fn read_node_from_db(
-    ctx: &mut EstimatorContext,
-    warmup_iters: usize,
-    measured_iters: usize,
-    final_key_len: usize,
-) -> GasCost {
-    let block_latency = 0;
-    let overhead = overhead_per_measured_block(ctx, block_latency);
+pub(crate) fn read_node_from_db(ctx: &mut EstimatorContext, final_key_len: usize) -> GasCost {
     let mut testbed = ctx.testbed();
     let tb = testbed.transaction_builder();
     // Prepare a long chain in the trie
@@ -90,30 +65,13 @@ pub(crate) fn read_node_from_db(
         let value = b"0";
         setup_block.push(tb.account_insert_key(signer.clone(), key, value));
     }
-    let mut blocks = Vec::with_capacity(1 + 2 * warmup_iters + 2 * measured_iters);
-    blocks.push(setup_block);
-    blocks.extend(
-        iter::repeat_with(|| vec![tb.account_has_key(signer.clone(), &key[0..1])])
-            .take(measured_iters + warmup_iters),
-    );
-    blocks.extend(
-        iter::repeat_with(|| vec![tb.account_has_key(signer.clone(), &key)])
-            .take(measured_iters + warmup_iters),
-    );
-    let results = &testbed.measure_blocks(blocks, block_latency)[1..];
-    let (short_key_results, long_key_results) = results.split_at(measured_iters + warmup_iters);
-    let (cost_short_key, ext_cost_short_key) = aggregate_per_block_measurements(
-        testbed.config,
-        1,
-        short_key_results[warmup_iters..].to_vec(),
-        Some(overhead.clone()),
-    );
-    let (cost_long_key, ext_cost_long_key) = aggregate_per_block_measurements(
-        testbed.config,
-        1,
-        long_key_results[warmup_iters..].to_vec(),
-        Some(overhead),
-    );
+    let short_key_tx = tb.account_has_key(signer.clone(), &key[0..1]);
+    let long_key_tx = tb.account_has_key(signer, &key);
+    testbed.process_block(setup_block, 0);
+    // Both measurements include the same per-block overhead, it cancels out in
+    // the difference below.
+    let (cost_short_key, ext_cost_short_key) = testbed.measure_transaction(short_key_tx);
+    let (cost_long_key, ext_cost_long_key) = testbed.measure_transaction(long_key_tx);
     let nodes_touched_delta = ext_cost_long_key[&ExtCosts::touching_trie_node]
         - ext_cost_short_key[&ExtCosts::touching_trie_node];
     // The exact number of touched nodes is a implementation that we don't want
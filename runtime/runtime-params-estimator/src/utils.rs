@@ -1,10 +1,11 @@
 use crate::apply_block_cost;
 use crate::config::Config;
 use crate::estimator_context::EstimatorContext;
-use crate::gas_cost::{GasCost, NonNegativeTolerance};
+use crate::gas_cost::GasCost;
 use crate::transaction_builder::TransactionBuilder;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
 
 use near_primitives::transaction::{Action, DeployContractAction, SignedTransaction};
 use near_primitives::types::AccountId;
@@ -30,12 +31,99 @@ pub fn clear_linux_page_cache() -> std::io::Result<()> {
     std::fs::write("/proc/sys/vm/drop_caches", b"1")
 }
 
+/// Restores the previous CPU affinity of the current thread when dropped.
+pub(crate) struct CoreAffinityGuard {
+    #[cfg(target_os = "linux")]
+    previous: libc::cpu_set_t,
+}
+
+/// Pins the current thread to the given CPU core, until the returned guard is
+/// dropped. Fails on systems other than Linux.
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_current_thread_to_core(core: usize) -> std::io::Result<CoreAffinityGuard> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("core index {core} is out of range"),
+        ));
+    }
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    unsafe {
+        let mut previous: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, size, &mut previous) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, size, &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(CoreAffinityGuard { previous })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin_current_thread_to_core(_core: usize) -> std::io::Result<CoreAffinityGuard> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "setting thread affinity is only supported on Linux",
+    ))
+}
+
+impl Drop for CoreAffinityGuard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &self.previous);
+        }
+    }
+}
+
+/// Resets the peak resident set size of the current process reported by
+/// `peak_rss_bytes`. Fails on systems other than Linux.
+#[cfg(target_os = "linux")]
+pub(crate) fn reset_peak_rss() -> std::io::Result<()> {
+    std::fs::write("/proc/self/clear_refs", b"5")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn reset_peak_rss() -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "resetting peak RSS is only supported on Linux",
+    ))
+}
+
+/// Peak resident set size of the current process in bytes, since it started
+/// or since the last `reset_peak_rss`. Fails on systems other than Linux.
+#[cfg(target_os = "linux")]
+pub(crate) fn peak_rss_bytes() -> std::io::Result<u64> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|kib| kib.trim().parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "no VmHWM in /proc/self/status")
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn peak_rss_bytes() -> std::io::Result<u64> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "measuring peak RSS is only supported on Linux",
+    ))
+}
+
 #[track_caller]
 pub(crate) fn transaction_cost(
     ctx: &mut EstimatorContext,
     make_transaction: &mut dyn FnMut(&mut TransactionBuilder) -> SignedTransaction,
 ) -> GasCost {
-    let block_size = 100;
+    let block_size = ctx.config.block_size;
     let (gas_cost, _ext_costs) = transaction_cost_ext(ctx, block_size, make_transaction, 0);
     gas_cost
 }
@@ -49,22 +137,25 @@ pub(crate) fn transaction_cost_ext(
 ) -> (GasCost, HashMap<ExtCosts, u64>) {
     let measurement_overhead = overhead_per_measured_block(ctx, block_latency);
 
+    let dry_run = ctx.config.dry_run;
     let mut testbed = ctx.testbed();
-    let blocks = {
-        let n_blocks = testbed.config.warmup_iters_per_block + testbed.config.iter_per_block;
-        let mut blocks = Vec::with_capacity(n_blocks);
-        for _ in 0..n_blocks {
-            let mut block = Vec::with_capacity(block_size);
-            for _ in 0..block_size {
-                let tx = make_transaction(testbed.transaction_builder());
-                block.push(tx)
-            }
-            blocks.push(block)
+    let blocks = generate_blocks(
+        testbed.config,
+        block_size,
+        testbed.transaction_builder(),
+        make_transaction,
+    );
+    if dry_run {
+        if let Err(errors) = testbed.dry_run(&blocks) {
+            let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            panic!("dry run failed, {} transactions fail:\n{}", errors.len(), errors.join("\n"));
         }
-        blocks
-    };
+        // The dry run used up the nonces, measure on a fresh testbed.
+        drop(testbed);
+        testbed = ctx.testbed();
+    }
 
-    let measurements = testbed.measure_blocks(blocks, block_latency);
+    let measurements = testbed.measure_blocks(blocks, block_latency).into_blocks();
     let measurements =
         measurements.into_iter().skip(testbed.config.warmup_iters_per_block).collect::<Vec<_>>();
 
@@ -76,6 +167,26 @@ pub(crate) fn transaction_cost_ext(
     )
 }
 
+/// Builds the warm up and measured blocks for a transaction cost estimation,
+/// each block holding `block_size` transactions.
+pub(crate) fn generate_blocks(
+    config: &Config,
+    block_size: usize,
+    tb: &mut TransactionBuilder,
+    make_transaction: &mut dyn FnMut(&mut TransactionBuilder) -> SignedTransaction,
+) -> Vec<Vec<SignedTransaction>> {
+    let n_blocks = config.warmup_iters_per_block + config.iter_per_block;
+    let mut blocks = Vec::with_capacity(n_blocks);
+    for _ in 0..n_blocks {
+        let mut block = Vec::with_capacity(block_size);
+        for _ in 0..block_size {
+            block.push(make_transaction(tb));
+        }
+        blocks.push(block)
+    }
+    blocks
+}
+
 /// Returns the total measurement overhead for a measured block.
 pub(crate) fn overhead_per_measured_block(
     ctx: &mut EstimatorContext,
@@ -99,9 +210,7 @@ pub(crate) fn fn_cost(
     let (total_cost, measured_count) = fn_cost_count(ctx, method, ext_cost, block_latency);
     assert_eq!(measured_count, count);
 
-    let base_cost = noop_function_call_cost(ctx);
-
-    total_cost.saturating_sub(&base_cost, &NonNegativeTolerance::PER_MILLE) / count
+    ctx.cost_above_noop(total_cost) / count
 }
 
 #[track_caller]
@@ -111,6 +220,7 @@ pub(crate) fn fn_cost_count(
     ext_cost: ExtCosts,
     block_latency: usize,
 ) -> (GasCost, u64) {
+    assert!(ctx.config.collect_ext_costs, "counting {ext_cost} requires collect_ext_costs");
     let block_size = 2;
     let mut make_transaction = |tb: &mut TransactionBuilder| -> SignedTransaction {
         let sender = tb.random_unused_account();
@@ -153,6 +263,7 @@ pub(crate) fn fn_cost_with_setup(
     ext_cost: ExtCosts,
     count: u64,
 ) -> GasCost {
+    assert!(ctx.config.collect_ext_costs, "counting {ext_cost} requires collect_ext_costs");
     let (total_cost, measured_count) = {
         let block_latency = 0;
         let overhead = overhead_per_measured_block(ctx, block_latency);
@@ -182,7 +293,7 @@ pub(crate) fn fn_cost_with_setup(
             blocks
         };
 
-        let measurements = testbed.measure_blocks(blocks, 0);
+        let measurements = testbed.measure_blocks(blocks, 0).into_blocks();
         // Filter out setup blocks.
         let measurements: Vec<_> = measurements
             .into_iter()
@@ -198,9 +309,7 @@ pub(crate) fn fn_cost_with_setup(
     };
     assert_eq!(measured_count, count);
 
-    let base_cost = noop_function_call_cost(ctx);
-
-    (total_cost - base_cost) / count
+    ctx.cost_above_noop(total_cost) / count
 }
 
 /// Estimates the cost to call `method`, on given contract.
@@ -248,7 +357,7 @@ pub(crate) fn fn_cost_in_contract(
         blocks
     };
 
-    let mut measurements = testbed.measure_blocks(blocks, 0);
+    let mut measurements = testbed.measure_blocks(blocks, 0).into_blocks();
     measurements.drain(0..ctx.config.warmup_iters_per_block);
 
     let (gas_cost, _ext_costs) =
@@ -262,6 +371,15 @@ pub(crate) fn aggregate_per_block_measurements(
     measurements: Vec<(GasCost, HashMap<ExtCosts, u64>)>,
     overhead: Option<GasCost>,
 ) -> (GasCost, HashMap<ExtCosts, u64>) {
+    let inconsistent = inconsistent_ext_costs(&measurements);
+    if !inconsistent.is_empty() {
+        eprintln!("WARNING: ext cost counts differ between measured blocks: {inconsistent:?}");
+        let stats = ext_cost_stats(&measurements);
+        for cost in &inconsistent {
+            let (mean, variance) = stats[cost];
+            eprintln!("    {cost}: mean {mean:.1}, standard deviation {:.1}", variance.sqrt());
+        }
+    }
     let mut block_costs = Vec::new();
     let mut total_ext_costs: HashMap<ExtCosts, u64> = HashMap::new();
     let mut total = GasCost::zero(config.metric);
@@ -288,9 +406,105 @@ pub(crate) fn aggregate_per_block_measurements(
             gas_cost.set_uncertain("BLOCK-MEASUREMENT-OVERHEAD");
         }
     }
+    if gas_cost.is_below_noise_floor(config.noise_floor) {
+        gas_cost.set_uncertain("BELOW-NOISE-FLOOR");
+    }
     (gas_cost, total_ext_costs)
 }
 
+/// Returns the ext costs whose counts are not identical across all measured
+/// blocks. Each block executes the same workload, so a non-empty result points
+/// to a nondeterministic setup and makes the estimate unreliable. A cost that
+/// is missing from a block counts as zero.
+pub(crate) fn inconsistent_ext_costs(
+    measurements: &[(GasCost, HashMap<ExtCosts, u64>)],
+) -> BTreeSet<ExtCosts> {
+    let all_costs: BTreeSet<ExtCosts> =
+        measurements.iter().flat_map(|(_, ext_costs)| ext_costs.keys().copied()).collect();
+    all_costs
+        .into_iter()
+        .filter(|cost| {
+            let mut counts = measurements
+                .iter()
+                .map(|(_, ext_costs)| ext_costs.get(cost).copied().unwrap_or_default());
+            let first = counts.next();
+            counts.any(|count| Some(count) != first)
+        })
+        .collect()
+}
+
+/// Returns the mean and the (population) variance of the count of each ext
+/// cost across the measured blocks. A cost that is missing from a block counts
+/// as zero. A high variance of a cost expected to be constant points to a
+/// nondeterministic or data-dependent code path.
+pub(crate) fn ext_cost_stats(
+    results: &[(GasCost, HashMap<ExtCosts, u64>)],
+) -> HashMap<ExtCosts, (f64, f64)> {
+    let all_costs: BTreeSet<ExtCosts> =
+        results.iter().flat_map(|(_, ext_costs)| ext_costs.keys().copied()).collect();
+    let n = results.len() as f64;
+    all_costs
+        .into_iter()
+        .map(|cost| {
+            let counts = results
+                .iter()
+                .map(|(_, ext_costs)| ext_costs.get(&cost).copied().unwrap_or_default() as f64);
+            let mean = counts.clone().sum::<f64>() / n;
+            let variance = counts.map(|count| (count - mean).powi(2)).sum::<f64>() / n;
+            (cost, (mean, variance))
+        })
+        .collect()
+}
+
+/// Writes the raw per-block measurements as CSV for offline analysis. Each
+/// row holds the block index, the measured gas and one column per ext cost
+/// observed in any block, in `ExtCosts` order. Absent counts are written as 0.
+pub fn write_measurement_csv(
+    path: &std::path::Path,
+    results: &[(GasCost, HashMap<ExtCosts, u64>)],
+) -> std::io::Result<()> {
+    let columns: BTreeSet<ExtCosts> =
+        results.iter().flat_map(|(_, ext_costs)| ext_costs.keys().copied()).collect();
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write!(out, "block,gas")?;
+    for cost in &columns {
+        write!(out, ",{cost}")?;
+    }
+    writeln!(out)?;
+    for (block, (gas_cost, ext_costs)) in results.iter().enumerate() {
+        write!(out, "{block},{}", gas_cost.to_gas())?;
+        for cost in &columns {
+            write!(out, ",{}", ext_costs.get(cost).copied().unwrap_or_default())?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// Writes ext cost counts in the collapsed stack format understood by
+/// `inferno` and `flamegraph.pl`, one `action;host_fn count` line per ext
+/// cost. `action` may itself hold several `;` separated frames, e.g.
+/// `function_call;receipt`. Counts of the same stack are summed up and zero
+/// counts are skipped. Lines are sorted by action, then in `ExtCosts` order.
+pub fn write_collapsed_stacks(
+    path: &std::path::Path,
+    stacks: &[(&str, HashMap<ExtCosts, u64>)],
+) -> std::io::Result<()> {
+    let mut folded: BTreeMap<(&str, ExtCosts), u64> = BTreeMap::new();
+    for (action, ext_costs) in stacks {
+        for (&cost, &count) in ext_costs {
+            if count > 0 {
+                *folded.entry((*action, cost)).or_default() += count;
+            }
+        }
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for ((action, cost), count) in folded {
+        writeln!(out, "{action};{cost} {count}")?;
+    }
+    out.flush()
+}
+
 pub(crate) fn average_cost(config: &Config, measurements: &[GasCost]) -> GasCost {
     let total = measurements.iter().fold(GasCost::zero(config.metric), |acc, x| acc + x.clone());
     let mut avg = total / measurements.len() as u64;
@@ -298,6 +512,9 @@ pub(crate) fn average_cost(config: &Config, measurements: &[GasCost]) -> GasCost
     if is_high_variance(&scalar_costs) {
         avg.set_uncertain("HIGH-VARIANCE");
     }
+    if avg.is_below_noise_floor(config.noise_floor) {
+        avg.set_uncertain("BELOW-NOISE-FLOOR");
+    }
     avg
 }
 
@@ -373,9 +590,14 @@ pub(crate) fn generate_data_only_contract(data_size: usize, config: &VMConfig) -
 
 #[cfg(test)]
 mod test {
-    use super::percentiles;
+    use super::{
+        ext_cost_stats, inconsistent_ext_costs, peak_rss_bytes, percentiles,
+        pin_current_thread_to_core, write_collapsed_stacks, write_measurement_csv,
+    };
     use crate::{config::GasMetric, gas_cost::GasCost};
+    use near_vm_logic::ExtCosts;
     use rand::prelude::SliceRandom;
+    use std::collections::HashMap;
 
     #[track_caller]
     fn check_percentiles(gas_values: &[u64], p_values: &[f32], expected_gas_results: &[u64]) {
@@ -401,4 +623,126 @@ mod test {
         one_to_one_o_one.shuffle(&mut rand::thread_rng());
         check_percentiles(&one_to_one_o_one, &[0.1, 0.5, 0.995], &[11, 51, 101]);
     }
+
+    #[test]
+    fn test_inconsistent_ext_costs() {
+        let cost = GasCost::from_gas(1u64.into(), GasMetric::Time);
+        let block0 = HashMap::from([(ExtCosts::base, 10), (ExtCosts::read_memory_byte, 100)]);
+        let block1 = HashMap::from([(ExtCosts::base, 10), (ExtCosts::read_memory_byte, 101)]);
+        let measurements = vec![(cost.clone(), block0.clone()), (cost, block1)];
+
+        let flagged = inconsistent_ext_costs(&measurements);
+        assert_eq!(flagged.into_iter().collect::<Vec<_>>(), vec![ExtCosts::read_memory_byte]);
+        assert!(inconsistent_ext_costs(&measurements[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_ext_cost_stats() {
+        let cost = GasCost::from_gas(1u64.into(), GasMetric::Time);
+        let blocks = [
+            HashMap::from([(ExtCosts::base, 10), (ExtCosts::read_memory_byte, 2)]),
+            HashMap::from([(ExtCosts::base, 10), (ExtCosts::read_memory_byte, 4)]),
+            HashMap::from([(ExtCosts::base, 10), (ExtCosts::read_memory_byte, 6)]),
+            HashMap::from([(ExtCosts::base, 10)]),
+        ];
+        let measurements: Vec<_> = blocks.into_iter().map(|block| (cost.clone(), block)).collect();
+
+        let stats = ext_cost_stats(&measurements);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[&ExtCosts::base], (10.0, 0.0));
+        // Counts 2, 4, 6 and 0 for the block without it: mean 3, variance (1 + 1 + 9 + 9) / 4.
+        assert_eq!(stats[&ExtCosts::read_memory_byte], (3.0, 5.0));
+        assert!(ext_cost_stats(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_pin_to_core() {
+        // Pinning may be refused by the platform, but measuring must work either way.
+        let guard = pin_current_thread_to_core(0);
+        if cfg!(not(target_os = "linux")) {
+            assert!(guard.is_err());
+        }
+        let start = GasCost::measure(GasMetric::Time);
+        let cost = start.elapsed();
+        drop(guard);
+        assert!(!cost.is_uncertain());
+
+        assert!(pin_current_thread_to_core(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_peak_rss() {
+        let before = match peak_rss_bytes() {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                assert!(cfg!(not(target_os = "linux")));
+                return;
+            }
+        };
+        const SIZE: usize = 64 * 1024 * 1024;
+        let mut data = vec![0u8; SIZE];
+        // Touch every page, so that it's actually resident.
+        for i in (0..SIZE).step_by(4096) {
+            unsafe { std::ptr::write_volatile(&mut data[i], 1) };
+        }
+        let after = peak_rss_bytes().unwrap();
+        drop(data);
+        assert!(after >= before);
+        assert!(after >= SIZE as u64);
+        assert!(peak_rss_bytes().unwrap() >= after);
+    }
+
+    #[test]
+    fn test_write_measurement_csv() {
+        let block0 = HashMap::from([(ExtCosts::read_memory_byte, 100), (ExtCosts::base, 10)]);
+        let block1 = HashMap::from([(ExtCosts::base, 11)]);
+        let measurements = vec![
+            (GasCost::from_gas(5u64.into(), GasMetric::Time), block0),
+            (GasCost::from_gas(7u64.into(), GasMetric::Time), block1),
+        ];
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("measurements.csv");
+        write_measurement_csv(&path, &measurements).unwrap();
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows, vec!["block,gas,base,read_memory_byte", "0,5,10,100", "1,7,11,0"]);
+    }
+
+    #[test]
+    fn test_write_collapsed_stacks() {
+        let stacks = [
+            (
+                "function_call;receipt",
+                HashMap::from([
+                    (ExtCosts::read_memory_byte, 100),
+                    (ExtCosts::base, 10),
+                    (ExtCosts::write_register_base, 0),
+                ]),
+            ),
+            ("create_account", HashMap::from([(ExtCosts::base, 1)])),
+            ("function_call;receipt", HashMap::from([(ExtCosts::base, 5)])),
+        ];
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stacks.folded");
+        write_collapsed_stacks(&path, &stacks).unwrap();
+
+        let folded = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = folded.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "create_account;base 1",
+                "function_call;receipt;base 15",
+                "function_call;receipt;read_memory_byte 100",
+            ]
+        );
+        for line in lines {
+            let (stack, count) = line.rsplit_once(' ').unwrap();
+            assert!(count.parse::<u64>().unwrap() > 0);
+            assert!(stack.split(';').all(|frame| !frame.is_empty()));
+        }
+    }
 }